@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use unlink::testing;
+
+fuzz_target!(|ops: Vec<unlink::MultiOperation<i32>>| {
+    let report = testing::execute_multi(ops, 3, 20);
+
+    assert!(report.is_conserved());
+});
@@ -0,0 +1,37 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+use unlink::testing::{self, Weights};
+
+/// Mostly pushes, for deep stacks.
+const DEEP: Weights = Weights {
+    push: 7,
+    pop: 2,
+    append: 1,
+    ..Weights::NONE
+};
+
+/// Mostly pops, for stacks that keep running empty.
+const SHALLOW: Weights = Weights {
+    push: 3,
+    pop: 5,
+    pop_push: 1,
+    peek: 1,
+    ..Weights::NONE
+};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+
+    let weights = match u.arbitrary::<bool>() {
+        Ok(true) => DEEP,
+        _ => SHALLOW,
+    };
+
+    let Ok(ops) = weights.operations::<i32>(&mut u) else {
+        return;
+    };
+
+    assert!(testing::execute(ops, 20).is_conserved());
+});
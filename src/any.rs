@@ -0,0 +1,147 @@
+use core::any::Any;
+use core::cell::UnsafeCell;
+
+use crate::Stack;
+
+/// A boxed value of any type. The cell is only ever accessed by the thread that popped its node,
+/// since [AnyStack](AnyStack) hands out no references to values still on the stack.
+struct Erased(UnsafeCell<Option<Box<dyn Any + Send>>>);
+
+// # Safety: See above, no two threads access the same cell.
+unsafe impl Sync for Erased {}
+
+/// [AnyStack](AnyStack) is a lock free stack of values of any type, which are recovered by
+/// downcasting them when they are popped.
+/// ```
+/// use unlink::AnyStack;
+///
+/// let stack = AnyStack::new();
+///
+/// stack.push(1_u32);
+/// stack.push("event");
+///
+/// assert_eq!(stack.pop_downcast::<&str>().unwrap().ok(), Some("event"));
+/// assert!(matches!(stack.pop_downcast::<i64>(), Some(Err(_))));
+/// assert!(stack.pop().is_none());
+/// ```
+pub struct AnyStack {
+    inner: Stack<Erased>,
+}
+
+impl Default for AnyStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for AnyStack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnyStack").finish()
+    }
+}
+
+impl AnyStack {
+    pub fn new() -> Self {
+        AnyStack {
+            inner: Stack::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.peek().is_none()
+    }
+
+    pub fn push<T: Any + Send>(&self, val: T) {
+        self.inner
+            .push(Erased(UnsafeCell::new(Some(Box::new(val)))));
+    }
+
+    /// Pops the top value, whatever its type.
+    pub fn pop(&self) -> Option<Box<dyn Any + Send>> {
+        let entry = self.inner.pop()?;
+
+        // # Safety: We popped the node, so no other thread can access its cell.
+        unsafe { (*entry.0.get()).take() }
+    }
+
+    /// Pops the top value and downcasts it to `T`. A value of another type is popped all the
+    /// same and handed back boxed.
+    pub fn pop_downcast<T: Any>(&self) -> Option<Result<T, Box<dyn Any + Send>>> {
+        self.pop().map(|val| match val.downcast::<T>() {
+            Ok(val) => Ok(*val),
+            Err(val) => Err(val),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_downcast() {
+        let stack = AnyStack::new();
+
+        stack.push(1_u8);
+        stack.push(String::from("two"));
+        stack.push(3.0_f64);
+
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.pop_downcast::<f64>().unwrap().ok(), Some(3.0));
+
+        let Some(Err(val)) = stack.pop_downcast::<u8>() else {
+            panic!("expected a mismatched type");
+        };
+
+        assert_eq!(val.downcast_ref::<String>().unwrap(), "two");
+        assert_eq!(*stack.pop().unwrap().downcast::<u8>().unwrap(), 1);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = AnyStack::new();
+
+        (0..3).for_each(|_| stack.push(DropCounter(counter.clone())));
+
+        drop(stack.pop());
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        drop(stack);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_sync() {
+        let stack = AnyStack::new();
+
+        std::thread::scope(|s| {
+            for i in 0..4_usize {
+                let stack = &stack;
+                s.spawn(move || {
+                    for j in 0..200 {
+                        match j % 3 {
+                            0 => drop(stack.pop()),
+                            1 => stack.push(i),
+                            _ => stack.push(i.to_string()),
+                        }
+                    }
+                });
+            }
+        });
+
+        while let Some(val) = stack.pop() {
+            assert!(val.is::<usize>() || val.is::<String>());
+        }
+    }
+}
@@ -1,37 +1,303 @@
 use alloc::alloc::{alloc, dealloc};
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::ops::ControlFlow;
 use core::ptr::{null_mut, NonNull};
 use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
-use haphazard::{Domain, HazardPointer, Singleton};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 
-struct Node<V> {
+use crate::domain::{self, Hazard};
+use crate::error::{AllocError, Contended};
+use crate::fail;
+use crate::hazard::Domain;
+use crate::stats::{self, Counters, Event};
+use crate::txn;
+
+pub(crate) struct Node<V> {
     pub val: V,
-    next: AtomicPtr<Self>,
+    pub(crate) next: AtomicPtr<Self>,
+    /// The number of [Entries](Entry) reading `val`, or [WRITER](WRITER) while an
+    /// [EntryMut](EntryMut) has it.
+    #[cfg(feature = "peek-mut")]
+    lock: AtomicUsize,
+    #[cfg(feature = "tags")]
+    tag: usize,
+    #[cfg(feature = "age")]
+    pushed: std::time::Instant,
+    /// Whether the value was taken, possibly by a [transfer](crate::txn::transfer), while the
+    /// node stays linked until it reaches the head.
+    pub(crate) claim: AtomicUsize,
+    /// Three counts, in a third of the bits each, so that one of them is raised and the others
+    /// checked in a single step:
+    ///
+    /// - [UNLINKING](UNLINKING), the attempts to unlink the node, which is never decremented
+    ///   once one succeeds. The successor it links to is linked for as long as it is zero.
+    /// - [INSERTING](INSERTING), the threads [inserting](Stack::sorted_insert) a node right
+    ///   below this one, or [unlinking](Stack::compact) one, which keep it from being unlinked.
+    /// - [ANCHORED](ANCHORED), the walks that stand on the node, or will start over from it,
+    ///   which keep [compact](Stack::compact) from unlinking it below the head.
+    state: AtomicUsize,
+    /// The number of [Tokens](Token) that hold the node, plus [RECLAIMED](RECLAIMED) once it was
+    /// retired and no hazard pointer protects it any more. The last of them frees it.
+    #[cfg(feature = "tokens")]
+    tokens: AtomicUsize,
+    /// In debug builds, distinct for every node allocated and zero once it is freed, so that a
+    /// node can be told apart from another one that reuses its memory.
+    #[cfg(debug_assertions)]
+    generation: AtomicUsize,
+}
+
+/// The generation stamped on the next node allocated, in debug builds.
+#[cfg(debug_assertions)]
+static GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+#[cfg(feature = "peek-mut")]
+const WRITER: usize = !(usize::MAX >> 1);
+
+#[cfg(feature = "tokens")]
+const RECLAIMED: usize = !(usize::MAX >> 1);
+
+/// The bits of a node's `state` that each of its counts takes, which bounds the threads that
+/// operate on one node at once.
+const STATE_BITS: u32 = usize::BITS / 3;
+
+/// One attempt to unlink a node, in its `state`.
+const UNLINKING: usize = 1;
+
+/// One thread inserting below a node, in its `state`.
+const INSERTING: usize = 1 << STATE_BITS;
+
+/// One walk anchored on a node, in its `state`.
+const ANCHORED: usize = 1 << (2 * STATE_BITS);
+
+/// The count of `unit`s in a node's `state`.
+const fn count(state: usize, unit: usize) -> usize {
+    (state / unit) & ((1 << STATE_BITS) - 1)
+}
+
+#[cfg(test)]
+std::thread_local! {
+    /// How many more nodes this thread allocates before allocations fail.
+    static ALLOCS_LEFT: core::cell::Cell<usize> = const { core::cell::Cell::new(usize::MAX) };
 }
 
+/// [Unlinked](Unlinked) is the error of [successor](Node::successor), when the node a walk stands
+/// on was unlinked before its successor was protected.
+struct Unlinked;
+
 impl<V> Node<V> {
-    fn new(val: V) -> *mut Self {
+    pub(crate) fn new(val: V) -> *mut Self {
+        Self::tagged(val, 0)
+    }
+
+    pub(crate) fn tagged(val: V, tag: usize) -> *mut Self {
+        Self::try_tagged(val, tag).unwrap_or_else(|_| {
+            alloc::alloc::handle_alloc_error(core::alloc::Layout::new::<Self>())
+        })
+    }
+
+    /// Allocates a node for `val`, handing it back if the allocation failed.
+    #[cfg_attr(not(feature = "tags"), allow(unused_variables))]
+    pub(crate) fn try_tagged(val: V, tag: usize) -> Result<*mut Self, V> {
         unsafe {
             let node = Self::alloc();
+
+            if node.is_null() {
+                return Err(val);
+            }
+
             core::ptr::write(&mut (*node).val, val);
             core::ptr::write_bytes(&mut (*node).next, 0, 0);
-            node
+            #[cfg(feature = "peek-mut")]
+            core::ptr::write(&mut (*node).lock, AtomicUsize::new(0));
+            #[cfg(feature = "tags")]
+            core::ptr::write(&mut (*node).tag, tag);
+            #[cfg(feature = "age")]
+            core::ptr::write(&mut (*node).pushed, std::time::Instant::now());
+            core::ptr::write(&mut (*node).claim, AtomicUsize::new(txn::FREE));
+            core::ptr::write(&mut (*node).state, AtomicUsize::new(0));
+            #[cfg(feature = "tokens")]
+            core::ptr::write(&mut (*node).tokens, AtomicUsize::new(0));
+            #[cfg(debug_assertions)]
+            core::ptr::write(
+                &mut (*node).generation,
+                AtomicUsize::new(GENERATION.fetch_add(1, Ordering::Relaxed)),
+            );
+            Ok(node)
+        }
+    }
+
+    #[cfg(feature = "tags")]
+    pub(crate) fn tag(&self) -> usize {
+        self.tag
+    }
+
+    /// Without the `tags` feature, every value has the tag `0`.
+    #[cfg(not(feature = "tags"))]
+    pub(crate) fn tag(&self) -> usize {
+        0
+    }
+
+    /// Claims shared access to the value, waiting for an [EntryMut](EntryMut) to be dropped.
+    /// Without the `peek-mut` feature nothing writes the value, so this does nothing.
+    #[inline]
+    pub(crate) fn read(&self) {
+        #[cfg(feature = "peek-mut")]
+        while self.lock.fetch_add(1, Ordering::Acquire) & WRITER != 0 {
+            self.lock.fetch_sub(1, Ordering::Relaxed);
+
+            while self.lock.load(Ordering::Relaxed) & WRITER != 0 {
+                crate::wait::yield_now();
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn unread(&self) {
+        #[cfg(feature = "peek-mut")]
+        self.lock.fetch_sub(1, Ordering::Release);
+    }
+
+    /// Claims exclusive access to the value, waiting for all [Entries](Entry) to be dropped.
+    #[cfg(feature = "peek-mut")]
+    fn write(&self) {
+        while self
+            .lock
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            crate::wait::yield_now();
+        }
+    }
+
+    #[cfg(feature = "peek-mut")]
+    fn unwrite(&self) {
+        self.lock.fetch_sub(WRITER, Ordering::Release);
+    }
+
+    /// Counts an attempt to unlink the node, which must be made before its successor is read,
+    /// failing while a node is being inserted below it. Either this sees the insertion, or the
+    /// insertion sees the attempt, so that the successor read afterwards stays linked below it.
+    pub(crate) fn freeze(&self) -> bool {
+        self.enter(UNLINKING, INSERTING)
+    }
+
+    /// Withdraws an attempt to unlink the node, which failed.
+    pub(crate) fn thaw(&self) {
+        self.state.fetch_sub(UNLINKING, Ordering::SeqCst);
+    }
+
+    /// Keeps the node from being unlinked while a node is inserted below it, failing if an
+    /// attempt to unlink it was made.
+    fn pin(&self) -> bool {
+        self.enter(INSERTING, UNLINKING)
+    }
+
+    fn unpin(&self) {
+        self.state.fetch_sub(INSERTING, Ordering::SeqCst);
+    }
+
+    /// Counts one more `unit` in the state, unless the count of `blocker`s was not zero. The
+    /// same step reads the state it changes, so of two threads that count their own unit and
+    /// check for the other's, at least one sees the other.
+    fn enter(&self, unit: usize, blocker: usize) -> bool {
+        let state = self.state.fetch_add(unit, Ordering::SeqCst);
+
+        debug_assert!(
+            count(state + unit, unit) != 0,
+            "too many threads on one node"
+        );
+
+        if count(state, blocker) != 0 {
+            self.state.fetch_sub(unit, Ordering::SeqCst);
+            return false;
+        }
+
+        true
+    }
+
+    /// The count of `unit`s in the state.
+    fn count(&self, unit: usize) -> usize {
+        count(self.state.load(Ordering::SeqCst), unit)
+    }
+
+    /// Protects the successor of the node, for a walk that stands on the node to move on to, or
+    /// returns `None` if the node is the bottom one. Fails if the node was unlinked, or its link
+    /// replaced, before the successor was protected, which may have been reclaimed by then, so
+    /// that the walk has to start over from the head.
+    ///
+    /// A node is unlinked either at the head, once the node above it was frozen, or by
+    /// [compact](Stack::compact), which replaces the link to it. The successor of a node that is
+    /// neither frozen nor links elsewhere once it is protected was therefore protected in time.
+    fn successor<'a>(&self) -> Result<Option<NodeRef<'a, V>>, Unlinked> {
+        let next = self.next.load(Ordering::Acquire);
+
+        if next.is_null() {
+            return Ok(None);
+        }
+
+        let next = NodeRef::from_ptr(next);
+
+        if self.count(UNLINKING) != 0 || self.next.load(Ordering::SeqCst) != next.as_ptr() {
+            return Err(Unlinked);
         }
+
+        Ok(Some(next))
+    }
+
+    /// Whether the allocation fails in a test, which counts down the thread's
+    /// [ALLOCS_LEFT](ALLOCS_LEFT).
+    #[cfg(test)]
+    fn alloc_fails() -> bool {
+        ALLOCS_LEFT.with(|left| match left.get() {
+            0 => true,
+            n => {
+                left.set(n - 1);
+                false
+            }
+        })
     }
 
     unsafe fn alloc() -> *mut Self {
+        #[cfg(test)]
+        if Self::alloc_fails() {
+            return null_mut();
+        }
+
         let layout = layout::<Self>();
         alloc(layout).cast::<Self>()
     }
 
-    unsafe fn dealloc(raw: *mut Self) {
+    pub(crate) unsafe fn dealloc(raw: *mut Self) {
+        #[cfg(debug_assertions)]
+        (*raw).generation.store(0, Ordering::SeqCst);
+
+        // The value was dropped or moved out by now, but its bytes are still in the node.
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(core::slice::from_raw_parts_mut(
+            raw.cast::<core::mem::MaybeUninit<u8>>(),
+            core::mem::size_of::<Self>(),
+        ));
+
         let layout = layout::<Self>();
         dealloc(raw.cast(), layout);
     }
 
-    unsafe fn drop(raw: *mut Self) {
+    pub(crate) unsafe fn drop(raw: *mut Self) {
         core::ptr::drop_in_place(&mut (*raw).val);
         Self::dealloc(raw);
     }
+
+    /// Releases the hold of a [Token](Token) on the node, freeing it if it was reclaimed in the
+    /// meantime.
+    ///
+    /// # Safety: The hold must not be released again.
+    #[cfg(feature = "tokens")]
+    unsafe fn release(raw: *mut Self) {
+        if (*raw).tokens.fetch_sub(1, Ordering::AcqRel) == RECLAIMED + 1 {
+            DropNode::free(raw);
+        }
+    }
 }
 
 const unsafe fn layout<T>() -> core::alloc::Layout {
@@ -40,34 +306,91 @@ const unsafe fn layout<T>() -> core::alloc::Layout {
     core::alloc::Layout::from_size_align_unchecked(size, align)
 }
 
-/// [UniqueFamily](UniqueFamily) enables type checking for [HazardPointers](HazardPointer)
-struct UniqueFamily;
-
-unsafe impl Singleton for UniqueFamily {}
+/// [UniqueFamily](UniqueFamily) enables type checking for [HazardPointers](crate::hazard::HazardPointer)
+pub(crate) struct UniqueFamily;
 
-static UNIQUE_FAMILY: Domain<UniqueFamily> = Domain::new(&UniqueFamily);
+pub(crate) static UNIQUE_FAMILY: Domain<UniqueFamily> = Domain::new(&UniqueFamily);
 
+/// [Stack](Stack) is a lock free LIFO stack.
+///
+/// # Variance
+///
+/// Unlike `Vec`, a stack is invariant in `V`. Values are pushed through a shared reference, so a
+/// `&Stack<&'static str>` that could be used as a `&Stack<&'a str>` would accept a short lived
+/// reference that a later `pop` hands out as `'static`:
+/// ```compile_fail
+/// use unlink::Stack;
+///
+/// fn shorten<'a>(stack: &'a Stack<&'static str>) -> &'a Stack<&'a str> {
+///     stack
+/// }
+/// ```
+///
+/// The stack owns its values for the purposes of the drop check and auto traits, so it is only
+/// `Send` if `V` is:
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+///
+/// assert_send::<unlink::Stack<std::rc::Rc<i32>>>();
+/// ```
 pub struct Stack<V> {
-    head: AtomicPtr<Node<V>>,
-    domain: &'static Domain<UniqueFamily>,
-    len: AtomicUsize,
+    pub(crate) head: AtomicPtr<Node<V>>,
+    pub(crate) domain: &'static Domain<UniqueFamily>,
+    pub(crate) len: AtomicUsize,
+    pub(crate) nodes: AtomicUsize,
+    name: Option<&'static str>,
+    stats: Counters,
+    /// The stack owns values of `V`, which matters to the drop check when `V` may dangle.
+    _marker: PhantomData<V>,
 }
 
 impl<V> core::fmt::Debug for Stack<V> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Stack").finish()
+        let mut debug = f.debug_struct("Stack");
+
+        if let Some(name) = self.name {
+            debug.field("name", &name);
+        }
+
+        debug.finish()
     }
 }
 
 impl<V> Stack<V> {
-    pub fn new() -> Self {
+    /// Creates an empty stack. Being `const`, it can initialize a static as well, which
+    /// [global_stack](crate::global_stack) declares.
+    pub const fn new() -> Self {
         Stack {
             head: AtomicPtr::new(null_mut()),
             domain: &UNIQUE_FAMILY,
             len: AtomicUsize::new(0),
+            nodes: AtomicUsize::new(0),
+            name: None,
+            stats: Counters::new(),
+            _marker: PhantomData,
         }
     }
 
+    /// Creates a stack with a `name` that its `Debug` output and [stats](Stack::stats) include,
+    /// to tell stacks apart in diagnostics.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::<u32>::named("render_jobs");
+    ///
+    /// assert_eq!(stack.name(), Some("render_jobs"));
+    /// assert_eq!(format!("{:?}", stack), r#"Stack { name: "render_jobs" }"#);
+    /// ```
+    pub const fn named(name: &'static str) -> Self {
+        let mut stack = Self::new();
+        stack.name = Some(name);
+        stack
+    }
+
+    pub const fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
     pub fn len(&self) -> usize {
         let len = self.len.load(std::sync::atomic::Ordering::Relaxed);
         if len > isize::MAX as usize {
@@ -76,408 +399,5658 @@ impl<V> Stack<V> {
             len
         }
     }
-}
-
-impl<V> Stack<V>
-where
-    V: Send + Sync,
-{
-    pub fn push(&self, val: V) {
-        let node_ptr = Node::new(val);
-        let node = NodeRef::from_ptr(node_ptr);
 
-        let mut head_ptr = self.head.load(Ordering::SeqCst);
+    /// Whether the stack holds no nodes, read from the head with a single load, unlike
+    /// [len](Stack::len), which is only approximate. A stack whose values were all taken from
+    /// below its head, as by [pop_matching](Stack::pop_matching), still holds their nodes until
+    /// the next pop or peek unlinks them.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    ///
+    /// assert!(stack.is_empty());
+    ///
+    /// stack.push(1);
+    ///
+    /// assert!(!stack.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
 
-        node.next.store(head_ptr, Ordering::SeqCst);
+    /// An estimate of the memory held by this stack: the stack itself and its linked nodes. The
+    /// nodes it retired belong to the hazard pointer domain shared by all stacks until they are
+    /// reclaimed, so they are reported once for all of them by
+    /// [retired_bytes](crate::retired_bytes), which sums with the estimates of several stacks
+    /// without counting anything twice.
+    pub fn approx_bytes(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.nodes.load(Ordering::Relaxed) * core::mem::size_of::<Node<V>>()
+    }
 
-        while let Err(now) =
-            self.head
-                .compare_exchange(head_ptr, node_ptr, Ordering::AcqRel, Ordering::Relaxed)
-        {
-            node.next.store(now, Ordering::Release);
-            head_ptr = now;
+    /// A snapshot of the contention and reclamation counters of this stack.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::Stats {
+        crate::Stats {
+            name: self.name,
+            ..self.stats.snapshot()
         }
+    }
 
-        self.len.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    /// Reports a push or pop through the `metrics` facade, labelled with the name of the stack.
+    #[inline(always)]
+    fn report(&self, event: Event, retries: u64) {
+        stats::report(
+            self.name,
+            event,
+            retries,
+            self.nodes.load(Ordering::Relaxed),
+        );
     }
 
-    pub fn pop(&self) -> Option<Entry<'_, V>> {
-        let mut old_head = NodeRef::from_atomic_ptr(&self.head)?;
+    /// Seals the stack against further modification, in exchange for reads that need no hazard
+    /// pointers.
+    pub fn freeze(self) -> crate::FrozenStack<V> {
+        crate::FrozenStack::new(self.into_iter().collect())
+    }
 
-        let mut next_ptr = old_head.next.load(Ordering::Acquire);
+    /// Reverses the stack in place, by relinking its nodes, so that the values are popped in
+    /// the order they were pushed. This turns a stack that was filled in a collection phase into
+    /// a FIFO for the phase that dispatches its values.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let mut stack = Stack::new();
+    /// (0..3).for_each(|i| stack.push(i));
+    ///
+    /// stack.reverse();
+    ///
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+    /// ```
+    pub fn reverse(&mut self) {
+        let mut curr = core::mem::replace(self.head.get_mut(), null_mut());
+        let mut reversed = null_mut();
 
-        while let Err(_) = self.head.compare_exchange(
-            old_head.as_ptr(),
-            next_ptr,
-            Ordering::AcqRel,
-            Ordering::Relaxed,
-        ) {
-            old_head = NodeRef::from_atomic_ptr(&self.head)?;
+        // # Safety: We have exclusive ownership of self, and only relink the nodes it owns.
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next.load(Ordering::Acquire);
 
-            next_ptr = old_head.next.load(Ordering::Acquire);
-        }
+                // The values of nodes left behind by `pop_matching` were already handed out, and
+                // their entries borrowed the stack, so they are gone.
+                if (*curr).claim.load(Ordering::Acquire) == txn::TAKEN {
+                    Node::drop(curr);
+                    *self.nodes.get_mut() -= 1;
+                } else {
+                    (*curr).next.store(reversed, Ordering::Relaxed);
+                    reversed = curr;
+                }
 
-        unsafe {
-            self.domain.retire_ptr::<_, DropNode<_>>(old_head.as_ptr());
-            self.domain.eager_reclaim();
+                curr = next;
+            }
         }
 
-        Some(old_head.into())
+        *self.head.get_mut() = reversed;
     }
 
-    pub fn peek(&self) -> Option<Entry<'_, V>> {
-        NodeRef::from_atomic_ptr(&self.head).map(|n| n.into())
-    }
+    /// Rotates the stack in place, relinking its top `n` values below the others, as
+    /// [slice::rotate_left](slice::rotate_left) does with the top as the first element. `n` wraps
+    /// around the number of values, so that a stack of recurring tasks can be cycled through
+    /// round robin. Finding the bottom takes a walk over the whole stack, but nothing is
+    /// allocated.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let mut stack = (0..5).collect::<Stack<_>>();
+    ///
+    /// stack.rotate_left(2);
+    ///
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![2, 1, 0, 4, 3]);
+    /// ```
+    pub fn rotate_left(&mut self, n: usize) {
+        let mut link: *mut AtomicPtr<Node<V>> = &mut self.head;
+        let mut tail = null_mut::<Node<V>>();
+        let mut free = 0;
 
-    pub fn append(&self, other: Self) {
-        let Some(new_head) = NodeRef::from_atomic_ptr(&other.head) else {
-            return;
-        };
+        // # Safety: We have exclusive ownership of self, and only relink the nodes it owns.
+        unsafe {
+            // Drops the nodes left behind by `pop_matching`, as `reverse` does, while looking
+            // for the bottom.
+            loop {
+                let curr = (*link).load(Ordering::Acquire);
 
-        other.head.store(null_mut(), Ordering::Release);
+                if curr.is_null() {
+                    break;
+                }
 
-        let mut tail = new_head.as_ptr();
+                if (*curr).claim.load(Ordering::Acquire) == txn::TAKEN {
+                    (*link).store((*curr).next.load(Ordering::Acquire), Ordering::Relaxed);
+                    Node::drop(curr);
+                    *self.nodes.get_mut() -= 1;
+                } else {
+                    tail = curr;
+                    free += 1;
+                    link = &mut (*curr).next;
+                }
+            }
 
-        unsafe {
-            while !(*tail).next.load(Ordering::Acquire).is_null() {
-                tail = (*tail).next.load(Ordering::Acquire);
+            if free == 0 || n.is_multiple_of(free) {
+                return;
             }
-            tail
-        };
 
-        let mut old_head = self.head.load(Ordering::Acquire);
-        unsafe {
-            (*tail).next.store(old_head, Ordering::Release);
-        }
+            let first = *self.head.get_mut();
+            let mut last = first;
 
-        while let Err(head_now) = self.head.compare_exchange(
-            old_head,
-            new_head.as_ptr(),
-            Ordering::AcqRel,
-            Ordering::Relaxed,
-        ) {
-            old_head = head_now;
-            unsafe {
-                (*tail).next.store(old_head, Ordering::Release);
+            for _ in 1..n % free {
+                last = (*last).next.load(Ordering::Relaxed);
             }
+
+            *self.head.get_mut() = (*last).next.swap(null_mut(), Ordering::Relaxed);
+            (*tail).next.store(first, Ordering::Relaxed);
         }
     }
-}
 
-impl<V> Drop for Stack<V> {
-    fn drop(&mut self) {
-        // Deallocate all pointers that are no longer referred to.
-        self.domain.eager_reclaim();
+    /// Pops every value into `dst` in a single pass, top first as [IntoIter](IntoIter) yields
+    /// them, moving each value straight out of its node.
+    /// ```
+    /// use std::collections::BTreeSet;
+    /// use unlink::Stack;
+    ///
+    /// let mut stack = (0..3).collect::<Stack<_>>();
+    /// let mut popped = vec![9];
+    ///
+    /// stack.pop_all_into(&mut popped);
+    ///
+    /// assert_eq!(popped, vec![9, 2, 1, 0]);
+    /// assert!(stack.pop().is_none());
+    ///
+    /// let mut stack = Stack::from_iter([1, 1, 2]);
+    /// let mut distinct = BTreeSet::new();
+    ///
+    /// stack.pop_all_into(&mut distinct);
+    ///
+    /// assert_eq!(distinct.into_iter().collect::<Vec<_>>(), vec![1, 2]);
+    /// ```
+    pub fn pop_all_into<E: Extend<V>>(&mut self, dst: &mut E) {
+        dst.extend(core::iter::from_fn(|| self.pop_owned()));
+    }
 
-        let mut curr = self.head.load(Ordering::Acquire);
+    /// Pops every value, grouping them by the key `f` returns for them. Each group holds its
+    /// values in the order they were popped, the topmost first.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let mut stack = Stack::from_iter([("eu", 1), ("us", 2), ("eu", 3)]);
+    ///
+    /// let groups = stack.drain_grouped_by(|(region, _)| *region);
+    ///
+    /// assert_eq!(groups["eu"], vec![("eu", 3), ("eu", 1)]);
+    /// assert_eq!(groups["us"], vec![("us", 2)]);
+    /// assert!(stack.pop().is_none());
+    /// ```
+    pub fn drain_grouped_by<K, F>(&mut self, mut f: F) -> HashMap<K, Vec<V>>
+    where
+        K: Hash + Eq,
+        F: FnMut(&V) -> K,
+    {
+        let mut groups = HashMap::<K, Vec<V>>::new();
 
-        // # Safety: We have exclusive ownership of self.
-        unsafe {
-            while !curr.is_null() {
-                let next = (*curr).next.load(Ordering::Acquire);
-                Node::drop(curr);
-                curr = next;
-            }
+        while let Some(val) = self.pop_owned() {
+            groups.entry(f(&val)).or_default().push(val);
         }
-    }
-}
 
-/// [NodeRef](NodeRef) is a protected `*mut` to a Node. It will be valid until it is dropped.
-struct NodeRef<'a, V> {
-    node: NonNull<Node<V>>,
-    _hazard: HazardPointer<'a, UniqueFamily>,
-}
-
-impl<'a, V> NodeRef<'a, V> {
-    fn as_ptr(&self) -> *mut Node<V> {
-        self.node.as_ptr()
+        groups
     }
-}
 
-impl<'a, V> core::ops::Deref for NodeRef<'a, V> {
-    type Target = Node<V>;
-    fn deref(&self) -> &Self::Target {
-        unsafe { self.node.as_ref() }
-    }
-}
+    /// Unlinks the top value and moves it out of its node, which exclusive access makes safe.
+    fn pop_owned(&mut self) -> Option<V> {
+        loop {
+            let next = *self.head.get_mut();
+            if next.is_null() {
+                return None;
+            }
 
-impl<'a, V> NodeRef<'a, V> {
-    pub(crate) fn from_ptr(ptr: *mut Node<V>) -> Self {
-        assert!(!ptr.is_null());
+            unsafe {
+                *self.head.get_mut() = (*next).next.load(Ordering::Acquire);
+                *self.nodes.get_mut() -= 1;
 
-        let mut _hazard = HazardPointer::new_in_domain(&UNIQUE_FAMILY);
+                // The values of nodes left behind by `pop_matching` were already handed out.
+                if (*next).claim.load(Ordering::Acquire) == txn::TAKEN {
+                    Node::drop(next);
+                    continue;
+                }
 
-        _hazard.protect_raw(ptr);
+                let val = core::ptr::read(&(*next).val);
 
-        let node = unsafe { NonNull::new_unchecked(ptr) };
+                Node::<V>::dealloc(next);
 
-        NodeRef { node, _hazard }
+                return Some(val);
+            }
+        }
     }
 
-    fn from_atomic_ptr(ptr: &AtomicPtr<Node<V>>) -> Option<Self> {
-        let mut _hazard = HazardPointer::new_in_domain(&UNIQUE_FAMILY);
-
-        let node = _hazard.protect_ptr(&ptr)?.0;
-
-        Some(NodeRef { node, _hazard })
-    }
-}
+    /// Loads the head, first finishing any [transfer](crate::txn::transfer) installed there.
+    fn load_head(&self) -> *mut Node<V> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
 
-#[repr(transparent)]
-struct DropNode<V>(NonNull<Node<V>>);
+            if !txn::is_marked(head) {
+                return head;
+            }
 
-impl<V> Drop for DropNode<V> {
-    fn drop(&mut self) {
-        unsafe {
-            Node::drop(self.0.as_ptr());
+            txn::help(&self.head);
         }
     }
 }
 
-impl<V> core::ops::Deref for DropNode<V> {
-    type Target = Node<V>;
-    fn deref(&self) -> &Self::Target {
-        unsafe { self.0.as_ref() }
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    pub fn push(&self, val: V) {
+        self.push_protected(val, 0);
     }
-}
 
-unsafe impl<V> haphazard::raw::Pointer<Node<V>> for DropNode<V> {
-    fn into_raw(self) -> *mut Node<V> {
-        self.0.as_ptr()
+    /// Pushes `val` with a `tag` that [pop_matching](Stack::pop_matching) selects values by.
+    /// Values pushed without one have the tag `0`.
+    #[cfg(feature = "tags")]
+    pub fn push_tagged(&self, val: V, tag: usize) {
+        self.push_protected(val, tag);
     }
 
-    unsafe fn from_raw(ptr: *mut Node<V>) -> Self {
-        Self(NonNull::new_unchecked(ptr))
-    }
-}
+    /// Inserts `val` right above the topmost value that `cmp` orders after it, or at the bottom,
+    /// so that a stack filled this way stays sorted with its least value on top, and pops behave
+    /// as a priority queue's. Equal values are popped in the order they were inserted. Walks the
+    /// stack to find the spot, so it suits short stacks, or values that mostly go near the top.
+    ///
+    /// Values pushed by any other means are only sorted relative to the values above them.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// [3, 1, 4, 1, 5].into_iter().for_each(|i| stack.sorted_insert(i, |a, b| a.cmp(b)));
+    ///
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![1, 1, 3, 4, 5]);
+    /// ```
+    pub fn sorted_insert<F>(&self, val: V, mut cmp: F)
+    where
+        F: FnMut(&V, &V) -> core::cmp::Ordering,
+    {
+        let node_ptr = Node::new(val);
 
-pub struct Entry<'a, V> {
-    node: NonNull<Node<V>>,
-    _hazard: haphazard::HazardPointer<'a, UniqueFamily>,
-}
+        // # Safety: The node is only published by the compare exchange that inserts it.
+        let node = unsafe { &*node_ptr };
 
-impl<'a, V> core::ops::Deref for Entry<'a, V> {
-    type Target = V;
-    fn deref(&self) -> &Self::Target {
-        unsafe { &self.node.as_ref().val }
-    }
-}
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        self.stats.hazard_acquired();
 
-impl<'a, V> From<NodeRef<'a, V>> for Entry<'a, V> {
-    fn from(node_ref: NodeRef<'a, V>) -> Self {
-        unsafe { core::mem::transmute(node_ref) }
-    }
-}
+        let mut retries = 0;
 
-pub struct IntoIter<V> {
-    stack: Stack<V>,
-}
+        'restart: loop {
+            let mut above = None;
+            let mut below = self.protect_head();
 
-impl<V> Iterator for IntoIter<V> {
-    type Item = V;
+            while let Some(curr) = below.take() {
+                if !txn::is_taken(&curr.claim) {
+                    curr.read();
+                    let after = cmp(&curr.val, &node.val).is_gt();
+                    curr.unread();
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let next = self.stack.head.load(Ordering::Acquire);
-        if next.is_null() {
-            return None;
-        }
+                    if after {
+                        below = Some(curr);
+                        break;
+                    }
+                }
 
-        unsafe {
-            self.stack
-                .head
-                .store((*next).next.load(Ordering::Acquire), Ordering::Release);
+                let Ok(next) = curr.successor() else {
+                    retries += 1;
+                    self.stats.hazard_acquired();
+                    continue 'restart;
+                };
 
-            let val = core::ptr::read(&(*next).val);
+                above = Some(curr);
+                below = next;
+            }
 
-            Node::<V>::dealloc(next);
+            let below = below.map_or(null_mut(), |below| below.as_ptr());
 
-            Some(val)
-        }
-    }
-}
+            node.next.store(below, Ordering::Release);
 
-impl<V> IntoIterator for Stack<V> {
-    type Item = V;
-    type IntoIter = IntoIter<V>;
+            // A node that is pinned cannot be unlinked, so the node inserted below it is linked
+            // for as long as it is.
+            let inserted = match above {
+                None => fail::compare_exchange(
+                    &self.head,
+                    below,
+                    node_ptr,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok(),
+                Some(above) if above.pin() => {
+                    let inserted = fail::compare_exchange(
+                        &above.next,
+                        below,
+                        node_ptr,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok();
 
-    fn into_iter(self) -> Self::IntoIter {
-        IntoIter { stack: self }
-    }
-}
+                    above.unpin();
+                    inserted
+                }
+                Some(_) => false,
+            };
 
-impl<V> FromIterator<V> for Stack<V>
-where
-    V: Send + Sync,
-{
-    fn from_iter<T: IntoIterator<Item = V>>(iter: T) -> Self {
-        let stack = Stack::new();
-        for val in iter {
-            stack.push(val);
+            if inserted {
+                break;
+            }
+
+            retries += 1;
+            self.stats.hazard_acquired();
         }
 
-        stack
+        self.stats.operation(retries);
+        self.report(Event::Push, retries);
+
+        self.len.fetch_add(1, Ordering::Relaxed);
     }
-}
 
-mod test {
-    use super::*;
-    use std::sync::Arc;
-    use std::thread;
+    /// Pushes `val` unless an equal value is on the stack, returning whether it was pushed. The
+    /// push only succeeds if the head is still the one the walk started from, so of several
+    /// threads pushing equal values this way, only one does.
+    ///
+    /// Values [inserted](Stack::sorted_insert) below the head while the stack is walked are not
+    /// seen.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let retries = Stack::new();
+    ///
+    /// assert!(retries.push_unique("job 1"));
+    /// assert!(retries.push_unique("job 2"));
+    /// assert!(!retries.push_unique("job 1"));
+    ///
+    /// assert_eq!(retries.into_iter().collect::<Vec<_>>(), vec!["job 2", "job 1"]);
+    /// ```
+    pub fn push_unique(&self, val: V) -> bool
+    where
+        V: PartialEq,
+    {
+        let node_ptr = Node::new(val);
 
-    #[derive(Debug)]
-    struct CountOnDrop<V> {
+        // # Safety: The node is only published by the compare exchange that pushes it.
+        let node = unsafe { &*node_ptr };
+
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        self.stats.hazard_acquired();
+
+        let mut retries = 0;
+
+        'restart: loop {
+            // The head stays protected during the walk, so that it cannot be reclaimed and its
+            // memory reused by a node the walk did not see.
+            let head = self.protect_head();
+            let head_ptr = head.as_ref().map_or(null_mut(), |head| head.as_ptr());
+            let mut below = head.as_ref().map(|head| NodeRef::from_ptr(head.as_ptr()));
+
+            while let Some(curr) = below.take() {
+                if !txn::is_taken(&curr.claim) {
+                    curr.read();
+                    let equal = curr.val == node.val;
+                    curr.unread();
+
+                    if equal {
+                        self.nodes.fetch_sub(1, Ordering::Relaxed);
+                        self.stats.operation(retries);
+
+                        // # Safety: The node was never published.
+                        unsafe { Node::drop(node_ptr) };
+                        return false;
+                    }
+                }
+
+                let Ok(next) = curr.successor() else {
+                    retries += 1;
+                    self.stats.hazard_acquired();
+                    continue 'restart;
+                };
+
+                below = next;
+            }
+
+            node.next.store(head_ptr, Ordering::Release);
+
+            if fail::compare_exchange(
+                &self.head,
+                head_ptr,
+                node_ptr,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+            {
+                break;
+            }
+
+            retries += 1;
+            self.stats.hazard_acquired();
+        }
+
+        self.stats.operation(retries);
+        self.report(Event::Push, retries);
+
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        true
+    }
+
+    /// Pushes `val` and returns a [Token](Token) that can [remove](Stack::remove) it later,
+    /// wherever it is in the stack by then, without a walk. The value is only marked as taken,
+    /// and its node is unlinked once the values above it are popped.
+    ///
+    /// A token keeps the node from being freed, even after its value was popped, but holds no
+    /// hazard pointer, so pending tokens do not slow down reclaiming other nodes.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    ///
+    /// stack.push(0);
+    /// let token = stack.push_token(1);
+    /// stack.push(2);
+    ///
+    /// assert_eq!(stack.remove(token).map(|e| *e), Some(1));
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![2, 0]);
+    /// ```
+    #[cfg(feature = "tokens")]
+    pub fn push_token(&self, val: V) -> Token<'_, V> {
+        let node = self.push_protected(val, 0);
+
+        // The node is protected until the token holds it, so it cannot be reclaimed before.
+        node.tokens.fetch_add(1, Ordering::AcqRel);
+
+        Token {
+            node: node.node,
+            stack: self,
+        }
+    }
+
+    /// Takes the value pushed with `token` out of the stack, or returns `None` if it was popped
+    /// in the meantime.
+    ///
+    /// # Panics
+    ///
+    /// If `token` was handed out by another stack.
+    #[cfg(feature = "tokens")]
+    pub fn remove<'a>(&'a self, token: Token<'a, V>) -> Option<Entry<'a, V>> {
+        assert!(
+            core::ptr::eq(token.stack, self),
+            "the token was handed out by another stack"
+        );
+
+        // The entry takes over the token's hold on the node, which may already be retired, so
+        // that a hazard pointer protecting it now would be too late.
+        let token = core::mem::ManuallyDrop::new(token);
+
+        // # Safety: The token holds the node.
+        let node = unsafe { token.node.as_ref() };
+
+        if !txn::claim(&node.claim, txn::TAKEN) {
+            unsafe { Node::release(token.node.as_ptr()) };
+            return None;
+        }
+
+        // Unlink the node right away, along with any taken nodes above it, if nothing else is
+        // left on top of it.
+        drop(self.protect_head());
+
+        node.read();
+
+        Some(Entry {
+            node: token.node,
+            _hazard: Hazard::new(self.domain),
+            held: true,
+        })
+    }
+
+    /// Unlinks and retires the nodes of values that were taken from below the top, such as by
+    /// [extract_if](Stack::extract_if), and returns how many it unlinked. Such nodes otherwise
+    /// stay linked until the values above them are popped, and every walk over the stack passes
+    /// them.
+    ///
+    /// Nodes that a concurrent [visit](Stack::visit) or [Iter](Iter) stands on are left for a
+    /// later call, as are nodes that a value is being inserted below.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    ///
+    /// (0..5).for_each(|i| stack.push(i));
+    ///
+    /// drop(stack.extract_if(|v| (1..4).contains(v)));
+    ///
+    /// assert_eq!(stack.compact(), 3);
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4, 0]);
+    /// ```
+    pub fn compact(&self) -> usize {
+        self.stats.hazard_acquired();
+        let mut purged = 0;
+
+        // Taken nodes at the head are unlinked by `protect_head`.
+        'restart: while let Some(mut above) = self.protect_head() {
+            loop {
+                let curr = match above.successor() {
+                    Ok(Some(curr)) => curr,
+                    Ok(None) => break 'restart,
+                    Err(Unlinked) => {
+                        self.stats.hazard_acquired();
+                        continue 'restart;
+                    }
+                };
+
+                if txn::is_taken(&curr.claim) && self.purge(&above, &curr) {
+                    purged += 1;
+                    continue;
+                }
+
+                above = curr;
+            }
+        }
+
+        if purged > 0 {
+            self.stats.reclaimed(self.domain.eager_reclaim());
+        }
+
+        purged
+    }
+
+    /// Unlinks `node` from below `above` and retires it, unless a value is being inserted below
+    /// it, or a walk is anchored on it.
+    fn purge(&self, above: &NodeRef<'_, V>, node: &NodeRef<'_, V>) -> bool {
+        // A node that is pinned stays linked, so the node below it is unlinked once the link to
+        // it is replaced, and a frozen node keeps its own successor.
+        if !above.pin() {
+            return false;
+        }
+
+        let purged = node.freeze() && {
+            let unlinked = node.count(ANCHORED) == 0
+                && loop {
+                    match fail::compare_exchange(
+                        &above.next,
+                        node.as_ptr(),
+                        node.next.load(Ordering::Acquire),
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => break true,
+                        // The link was not replaced, so the exchange failed spuriously.
+                        Err(now) if now == node.as_ptr() => continue,
+                        Err(_) => break false,
+                    }
+                };
+
+            if !unlinked {
+                node.thaw();
+            }
+
+            unlinked
+        };
+
+        above.unpin();
+
+        if !purged {
+            return false;
+        }
+
+        self.nodes.fetch_sub(1, Ordering::Relaxed);
+
+        fail::yield_point();
+
+        unsafe {
+            DropNode::retire(self.domain, node.as_ptr());
+            self.stats.retired(1);
+        }
+
+        true
+    }
+
+    /// Pushes `val`, returning its node still protected.
+    fn push_protected(&self, val: V, tag: usize) -> NodeRef<'_, V> {
+        let node_ptr = Node::tagged(val, tag);
+        let node = NodeRef::from_ptr(node_ptr);
+
+        let mut head_ptr = self.load_head();
+
+        node.next.store(head_ptr, Ordering::SeqCst);
+
+        // Count the node before publishing it, so that a racing pop cannot take the count below
+        // zero.
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+
+        let mut retries = 0;
+
+        while let Err(now) = fail::compare_exchange(
+            &self.head,
+            head_ptr,
+            node_ptr,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        ) {
+            retries += 1;
+            head_ptr = if txn::is_marked(now) {
+                self.load_head()
+            } else {
+                now
+            };
+            node.next.store(head_ptr, Ordering::Release);
+        }
+
+        self.stats.operation(retries);
+        self.report(Event::Push, retries);
+
+        self.len.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        node
+    }
+
+    pub fn pop(&self) -> Option<Entry<'_, V>> {
+        self.stats.hazard_acquired();
+        let mut retries = 0;
+
+        loop {
+            let Some(old_head) = self.protect_head() else {
+                self.stats.operation(retries);
+                return None;
+            };
+
+            // The value may have been taken by `pop_matching` or a transfer after we unlinked
+            // the node, in which case we only reclaim it.
+            if self.unlink(&old_head) && txn::claim(&old_head.claim, txn::TAKEN) {
+                self.stats.operation(retries);
+                self.report(Event::Pop, retries);
+                return Some(old_head.into());
+            }
+
+            retries += 1;
+            self.stats.hazard_acquired();
+        }
+    }
+
+    /// Pops the top value with a single compare exchange, failing with [Contended](Contended)
+    /// rather than retrying if another thread got to the head first. Leaves it to the caller to
+    /// back off, or to look for work elsewhere.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(1);
+    ///
+    /// let popped = loop {
+    ///     match stack.try_pop_weak() {
+    ///         Ok(popped) => break popped,
+    ///         // Another thread got there first, back off before trying again.
+    ///         Err(unlink::Contended) => std::thread::yield_now(),
+    ///     }
+    /// };
+    ///
+    /// assert_eq!(popped.map(|e| *e), Some(1));
+    /// // An empty stack is not contended.
+    /// assert!(stack.try_pop_weak().unwrap().is_none());
+    /// ```
+    pub fn try_pop_weak(&self) -> Result<Option<Entry<'_, V>>, Contended> {
+        self.stats.hazard_acquired();
+
+        let Some(old_head) = self.protect_head() else {
+            self.stats.operation(0);
+            return Ok(None);
+        };
+
+        // A value taken after we unlinked its node went to another consumer, which is as much
+        // contention as a failed unlink.
+        if self.unlink(&old_head) && txn::claim(&old_head.claim, txn::TAKEN) {
+            self.stats.operation(0);
+            self.report(Event::Pop, 0);
+            return Ok(Some(old_head.into()));
+        }
+
+        self.stats.operation(1);
+        Err(Contended)
+    }
+
+    /// Pops the top value if it equals `expected`, and returns whether it did. The value is
+    /// compared and popped as one step, so of several threads claiming the same value this way,
+    /// only one succeeds, and none pops a value pushed on top of it in the meantime.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let jobs = unlink::stack!["build", "test"];
+    ///
+    /// assert!(!jobs.compare_and_pop(&"build"));
+    /// assert!(jobs.compare_and_pop(&"test"));
+    /// assert!(jobs.compare_and_pop(&"build"));
+    /// assert!(jobs.is_empty());
+    /// ```
+    pub fn compare_and_pop(&self, expected: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.stats.hazard_acquired();
+        let mut retries = 0;
+
+        loop {
+            let Some(old_head) = self.protect_head() else {
+                self.stats.operation(retries);
+                return false;
+            };
+
+            // The value stays read until it is popped, so that an `EntryMut` cannot change it
+            // after it was compared.
+            old_head.read();
+
+            if old_head.val != *expected {
+                old_head.unread();
+                self.stats.operation(retries);
+                return false;
+            }
+
+            let popped = self.unlink(&old_head) && txn::claim(&old_head.claim, txn::TAKEN);
+            old_head.unread();
+
+            if popped {
+                self.stats.operation(retries);
+                self.report(Event::Pop, retries);
+                return true;
+            }
+
+            retries += 1;
+            self.stats.hazard_acquired();
+        }
+    }
+
+    /// Pops the topmost value whose tag shares a bit with `mask`, leaving the values above it in
+    /// place.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// const IO: usize = 1;
+    /// const CPU: usize = 2;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push_tagged("read", IO);
+    /// stack.push_tagged("hash", CPU);
+    ///
+    /// assert_eq!(stack.pop_matching(IO).map(|e| *e), Some("read"));
+    /// assert!(stack.pop_matching(IO).is_none());
+    /// assert_eq!(stack.pop().map(|e| *e), Some("hash"));
+    /// ```
+    #[cfg(feature = "tags")]
+    pub fn pop_matching(&self, mask: usize) -> Option<Entry<'_, V>> {
+        self.pop_matching_by(|tag| tag & mask != 0)
+    }
+
+    /// Pops the topmost value whose tag satisfies `f`, leaving the values above it in place.
+    ///
+    /// The node of a value taken below the head stays linked, unseen by other operations, until
+    /// the values above it are popped.
+    #[cfg(feature = "tags")]
+    pub fn pop_matching_by<F>(&self, mut f: F) -> Option<Entry<'_, V>>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        self.pop_first(|node| f(node.tag))
+    }
+
+    /// Pops the topmost value that was pushed more than `age` ago.
+    #[cfg(feature = "age")]
+    pub fn pop_older_than(&self, age: core::time::Duration) -> Option<Entry<'_, V>> {
+        self.pop_first(|node| node.pushed.elapsed() > age)
+    }
+
+    /// Drops every value that was pushed more than `age` ago, returning how many there were.
+    /// Like values taken by [pop_matching](Stack::pop_matching), their nodes are reclaimed once
+    /// the values above them are popped.
+    /// ```
+    /// use std::time::Duration;
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push("stale");
+    ///
+    /// std::thread::sleep(Duration::from_millis(200));
+    /// stack.push("fresh");
+    ///
+    /// assert_eq!(stack.discard_older_than(Duration::from_millis(100)), 1);
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec!["fresh"]);
+    /// ```
+    #[cfg(feature = "age")]
+    pub fn discard_older_than(&self, age: core::time::Duration) -> usize {
+        let mut discarded = 0;
+
+        self.walk(|node| {
+            if node.pushed.elapsed() > age && txn::claim(&node.claim, txn::TAKEN) {
+                discarded += 1;
+            }
+
+            false
+        });
+
+        drop(self.protect_head());
+
+        discarded
+    }
+
+    /// Pops values for as long as `window` lasts, or until the stack is empty, returning clones
+    /// of them in the order they were popped. Values pushed in the meantime are popped as well.
+    /// ```
+    /// use std::time::Duration;
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..3).for_each(|i| stack.push(i));
+    ///
+    /// assert_eq!(stack.pop_for(Duration::from_millis(50)), vec![2, 1, 0]);
+    /// assert!(stack.pop_for(Duration::ZERO).is_empty());
+    /// ```
+    pub fn pop_for(&self, window: core::time::Duration) -> Vec<V>
+    where
+        V: Clone,
+    {
+        let deadline = std::time::Instant::now() + window;
+        let mut popped = Vec::new();
+
+        while std::time::Instant::now() < deadline {
+            let Some(entry) = self.pop() else {
+                break;
+            };
+
+            popped.push(V::clone(&entry));
+        }
+
+        popped
+    }
+
+    /// Pops values and sends clones of them through `sender` until the stack is empty, or the
+    /// receiver was dropped, returning how many were sent. A value popped when the receiver turns
+    /// out to be gone is pushed back.
+    /// ```
+    /// use std::sync::mpsc;
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..3).for_each(|i| stack.push(i));
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    ///
+    /// assert_eq!(stack.forward_to(&tx), 3);
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 1, 0]);
+    /// ```
+    pub fn forward_to(&self, sender: &std::sync::mpsc::Sender<V>) -> usize
+    where
+        V: Clone,
+    {
+        let mut sent = 0;
+
+        while let Some(entry) = self.pop() {
+            if let Err(std::sync::mpsc::SendError(val)) = sender.send(V::clone(&entry)) {
+                self.push(val);
+                break;
+            }
+
+            sent += 1;
+        }
+
+        sent
+    }
+
+    /// Takes every value matching `pred` out of the stack, and returns them in a new stack in
+    /// the same order, leaving the other values in place. Values are taken one at a time, so a
+    /// value pushed concurrently may or may not be extracted.
+    ///
+    /// The extracted values are cloned into the new stack, since entries of this stack may still
+    /// refer to them, and the nodes they leave behind are reclaimed once the values above them
+    /// are popped.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..6).for_each(|i| stack.push(i));
+    ///
+    /// let odd = stack.extract_if(|v| v % 2 == 1);
+    ///
+    /// assert_eq!(odd.into_iter().collect::<Vec<_>>(), vec![5, 3, 1]);
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4, 2, 0]);
+    /// ```
+    pub fn extract_if<F>(&self, mut pred: F) -> Stack<V>
+    where
+        V: Clone,
+        F: FnMut(&V) -> bool,
+    {
+        let mut extracted = Vec::new();
+
+        self.walk(|node| {
+            node.read();
+
+            if pred(&node.val) && txn::claim(&node.claim, txn::TAKEN) {
+                extracted.push(node.val.clone());
+            }
+
+            node.unread();
+
+            false
+        });
+
+        drop(self.protect_head());
+
+        extracted.into_iter().rev().collect()
+    }
+
+    /// Pops the value with the smallest key, or the topmost one among equal keys. Scans the whole
+    /// stack, so it suits small stacks best.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// [3, 1, 4, 1, 5].into_iter().for_each(|i| stack.push(i));
+    ///
+    /// assert_eq!(stack.pop_min_by_key(|v| *v).map(|e| *e), Some(1));
+    /// assert_eq!(stack.pop_max_by_key(|v| *v).map(|e| *e), Some(5));
+    /// assert_eq!(stack.pop().map(|e| *e), Some(4));
+    /// ```
+    pub fn pop_min_by_key<K, F>(&self, f: F) -> Option<Entry<'_, V>>
+    where
+        K: Ord,
+        F: FnMut(&V) -> K,
+    {
+        self.pop_extreme_by_key(f, |key, best| key < best)
+    }
+
+    /// Pops the value with the largest key, or the topmost one among equal keys. Scans the whole
+    /// stack, so it suits small stacks best.
+    pub fn pop_max_by_key<K, F>(&self, f: F) -> Option<Entry<'_, V>>
+    where
+        K: Ord,
+        F: FnMut(&V) -> K,
+    {
+        self.pop_extreme_by_key(f, |key, best| key > best)
+    }
+
+    fn pop_extreme_by_key<K, F>(&self, mut f: F, better: fn(&K, &K) -> bool) -> Option<Entry<'_, V>>
+    where
+        F: FnMut(&V) -> K,
+    {
+        loop {
+            let mut best: Option<(K, NodeRef<'_, V>)> = None;
+
+            self.walk(|node| {
+                node.read();
+                let key = f(&node.val);
+                node.unread();
+
+                if best.as_ref().is_none_or(|(best, _)| better(&key, best)) {
+                    // # Safety: The walk protects the node, so it can be protected again.
+                    best = Some((key, NodeRef::from_ptr(node as *const _ as *mut _)));
+                }
+
+                false
+            });
+
+            let (_, node) = best?;
+
+            // Another thread may have taken the value since, in which case we scan again.
+            if txn::claim(&node.claim, txn::TAKEN) {
+                if self.head.load(Ordering::Acquire) == node.as_ptr() {
+                    self.unlink(&node);
+                }
+
+                return Some(node.into());
+            }
+        }
+    }
+
+    /// Pops the greatest value according to `cmp` among the top `k` values, or the topmost one
+    /// among equals, leaving the values above it in place. Inspects at most `k` nodes per scan,
+    /// so a scheduler gets mostly prioritized pops at a bounded cost.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// [9, 1, 5, 2].into_iter().for_each(|i| stack.push(i));
+    ///
+    /// assert_eq!(stack.pop_best_of_k(3, |a, b| a.cmp(b)).map(|e| *e), Some(5));
+    /// assert_eq!(stack.pop_best_of_k(3, |a, b| a.cmp(b)).map(|e| *e), Some(9));
+    /// assert_eq!(stack.pop().map(|e| *e), Some(2));
+    /// ```
+    pub fn pop_best_of_k<F>(&self, k: usize, mut cmp: F) -> Option<Entry<'_, V>>
+    where
+        F: FnMut(&V, &V) -> core::cmp::Ordering,
+    {
+        if k == 0 {
+            return None;
+        }
+
+        loop {
+            let mut best: Option<NodeRef<'_, V>> = None;
+            let mut seen = 0;
+
+            self.walk(|node| {
+                seen += 1;
+
+                let better = best.as_ref().is_none_or(|best| {
+                    node.read();
+                    best.read();
+                    let ordering = cmp(&node.val, &best.val);
+                    best.unread();
+                    node.unread();
+
+                    ordering.is_gt()
+                });
+
+                if better {
+                    // # Safety: The walk protects the node, so it can be protected again.
+                    best = Some(NodeRef::from_ptr(node as *const _ as *mut _));
+                }
+
+                seen == k
+            });
+
+            let node = best?;
+
+            // Another thread may have taken the value since, in which case we scan again.
+            if txn::claim(&node.claim, txn::TAKEN) {
+                if self.head.load(Ordering::Acquire) == node.as_ptr() {
+                    self.unlink(&node);
+                }
+
+                return Some(node.into());
+            }
+        }
+    }
+
+    /// Passes the values to `f` from the top down, until it breaks, and returns whether it did.
+    /// Lighter than handing out an [Entry](Entry) for every value: only the node the walk stands
+    /// on is protected, and its value is read while `f` runs. Values pushed during the walk may or
+    /// may not be visited, but none is visited twice.
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..10).for_each(|i| stack.push(i));
+    ///
+    /// let mut seen = 0;
+    /// let found = stack.visit(|v| {
+    ///     seen += 1;
+    ///     if *v == 7 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    /// });
+    ///
+    /// assert!(found.is_break());
+    /// assert_eq!(seen, 3);
+    /// ```
+    pub fn visit<F>(&self, mut f: F) -> ControlFlow<()>
+    where
+        F: FnMut(&V) -> ControlFlow<()>,
+    {
+        self.visit_nodes(|node| {
+            node.read();
+            let flow = f(&node.val);
+            node.unread();
+
+            flow
+        })
+    }
+
+    /// Iterates over the values from the top down, yielding an [Entry](Entry) for each, which
+    /// keeps it readable after it is popped. The walk is the one [visit](Stack::visit) makes, so
+    /// it runs alongside pushes and pops, and yields no value twice.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = unlink::stack![1, 2, 3];
+    /// let mut iter = stack.iter();
+    ///
+    /// let top = iter.next().unwrap();
+    /// stack.pop();
+    ///
+    /// assert_eq!(*top, 3);
+    /// assert_eq!(iter.map(|e| *e).collect::<Vec<_>>(), vec![2, 1]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            stack: self,
+            started: false,
+            curr: None,
+            fresh: false,
+            visited: None,
+            skipping: false,
+        }
+    }
+
+    /// Returns [Entries](Entry) to the values, top first, as [visit](Stack::visit) walks them.
+    fn entries(&self) -> Vec<Entry<'_, V>> {
+        let mut entries = Vec::new();
+
+        let _ = self.visit_nodes(|node| {
+            entries.push(NodeRef::from_ptr(node.as_ptr()).into());
+            ControlFlow::Continue(())
+        });
+
+        entries
+    }
+
+    /// Walks the nodes whose values were not taken for [visit](Stack::visit), passing each of
+    /// them to `f` once.
+    fn visit_nodes<F>(&self, mut f: F) -> ControlFlow<()>
+    where
+        F: FnMut(&NodeRef<'_, V>) -> ControlFlow<()>,
+    {
+        self.stats.hazard_acquired();
+
+        // The node the walk stood on when it last started over, which was visited along with the
+        // nodes above it.
+        let mut visited: Option<Anchor<'_, V>> = None;
+
+        'restart: loop {
+            let Some(mut curr) = self.anchor_head() else {
+                return ControlFlow::Continue(());
+            };
+
+            let mut skipping = visited.is_some();
+
+            loop {
+                if skipping {
+                    skipping = visited
+                        .as_ref()
+                        .is_some_and(|v| v.as_ptr() != curr.as_ptr());
+                } else if !txn::is_taken(&curr.claim) && f(&curr).is_break() {
+                    return ControlFlow::Break(());
+                }
+
+                let next = match curr.successor() {
+                    Ok(None) if !skipping => return ControlFlow::Continue(()),
+                    Ok(None) => {
+                        // The node was unlinked, which an anchored node only is at the head, so
+                        // every node above it went along with it, and none of the nodes left was
+                        // visited.
+                        visited = None;
+                        self.stats.hazard_acquired();
+                        continue 'restart;
+                    }
+                    // The successor is anchored before the walk moves on to it, so that the walk
+                    // can start over from it.
+                    Ok(Some(next)) => Anchor::new(next),
+                    Err(Unlinked) => None,
+                };
+
+                let Some(next) = next else {
+                    if !skipping {
+                        visited = Some(curr);
+                    }
+
+                    self.stats.hazard_acquired();
+                    continue 'restart;
+                };
+
+                curr = next;
+            }
+        }
+    }
+
+    /// Whether both stacks hold the same values, as many times each, in any order. Each stack is
+    /// walked once, so values pushed or popped meanwhile may or may not be counted, which suits
+    /// checking the outcome of a concurrent test once its threads are done.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let a = unlink::stack![1, 2, 2, 3];
+    /// let b = unlink::stack![2, 3, 2, 1];
+    ///
+    /// assert!(a.eq_ignoring_order(&b));
+    ///
+    /// b.pop();
+    /// b.push(3);
+    ///
+    /// assert!(!a.eq_ignoring_order(&b));
+    /// ```
+    pub fn eq_ignoring_order(&self, other: &Stack<V>) -> bool
+    where
+        V: Hash + Eq,
+    {
+        let (ours, theirs) = (self.entries(), other.entries());
+
+        if ours.len() != theirs.len() {
+            return false;
+        }
+
+        let mut counts = HashMap::<&V, usize>::new();
+
+        for entry in &ours {
+            *counts.entry(&**entry).or_default() += 1;
+        }
+
+        theirs.iter().all(|entry| match counts.get_mut(&**entry) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        })
+    }
+
+    /// Pops the topmost value whose node satisfies `f`.
+    #[cfg(any(feature = "tags", feature = "age", test))]
+    fn pop_first<F>(&self, mut f: F) -> Option<Entry<'_, V>>
+    where
+        F: FnMut(&Node<V>) -> bool,
+    {
+        let node = self.walk(|node| f(node) && txn::claim(&node.claim, txn::TAKEN))?;
+
+        if self.head.load(Ordering::Acquire) == node.as_ptr() {
+            self.unlink(&node);
+        }
+
+        Some(node.into())
+    }
+
+    /// Pops the topmost value that `f` selects, so that tests can take values from below the top
+    /// without the `tags` feature, as [pop_matching_by](Stack::pop_matching_by) would.
+    #[cfg(test)]
+    pub(crate) fn pop_where<F>(&self, mut f: F) -> Option<Entry<'_, V>>
+    where
+        F: FnMut(&V) -> bool,
+    {
+        self.pop_first(|node| {
+            node.read();
+            let selected = f(&node.val);
+            node.unread();
+
+            selected
+        })
+    }
+
+    /// Walks the stack from the top down, calling `visit` on every node whose value was not
+    /// taken, and returns the first node it returns `true` for.
+    fn walk<F>(&self, mut visit: F) -> Option<NodeRef<'_, V>>
+    where
+        F: FnMut(&Node<V>) -> bool,
+    {
+        self.stats.hazard_acquired();
+
+        'restart: loop {
+            let mut curr = self.protect_head()?;
+
+            loop {
+                if !txn::is_taken(&curr.claim) && visit(&curr) {
+                    return Some(curr);
+                }
+
+                curr = match curr.successor() {
+                    Ok(Some(next)) => next,
+                    Ok(None) => return None,
+                    Err(Unlinked) => {
+                        self.stats.hazard_acquired();
+                        continue 'restart;
+                    }
+                };
+            }
+        }
+    }
+
+    /// Protects the head, first finishing any [transfer](crate::txn::transfer) installed there,
+    /// and unlinking nodes whose values were taken.
+    pub(crate) fn protect_head(&self) -> Option<NodeRef<'_, V>> {
+        loop {
+            let mut head = NodeRef::from_atomic_ptr(&self.head)?;
+
+            if txn::is_marked(head.as_ptr()) {
+                drop(head);
+                txn::help(&self.head);
+                continue;
+            }
+
+            // The head is linked, as it was protected before it changed.
+            head.stamp();
+
+            if txn::is_taken(&head.claim) {
+                self.unlink(&head);
+                continue;
+            }
+
+            return Some(head);
+        }
+    }
+
+    /// Protects the head as [protect_head](Stack::protect_head) does, and anchors it.
+    fn anchor_head(&self) -> Option<Anchor<'_, V>> {
+        loop {
+            if let Some(head) = Anchor::new(self.protect_head()?) {
+                return Some(head);
+            }
+        }
+    }
+
+    /// Unlinks `node` if it is still the head, and retires it.
+    fn unlink(&self, node: &NodeRef<'_, V>) -> bool {
+        if !node.freeze() {
+            return false;
+        }
+
+        let next = node.next.load(Ordering::Acquire);
+
+        if fail::compare_exchange(
+            &self.head,
+            node.as_ptr(),
+            next,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        )
+        .is_err()
+        {
+            node.thaw();
+            return false;
+        }
+
+        node.check_generation();
+        self.nodes.fetch_sub(1, Ordering::Relaxed);
+
+        fail::yield_point();
+
+        unsafe {
+            DropNode::retire(self.domain, node.as_ptr());
+            self.stats.retired(1);
+            self.stats.reclaimed(self.domain.eager_reclaim());
+        }
+
+        true
+    }
+
+    pub fn peek(&self) -> Option<Entry<'_, V>> {
+        self.stats.hazard_acquired();
+        self.protect_head().map(|n| n.into())
+    }
+
+    /// Peeks at the bottom value, the oldest one still on the stack, by walking down to it. The
+    /// value is protected like a [peeked](Stack::peek) one, so it can be read while other threads
+    /// pop it.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..3).for_each(|i| stack.push(i));
+    ///
+    /// assert_eq!(stack.peek_bottom().map(|e| *e), Some(0));
+    /// assert_eq!(stack.peek().map(|e| *e), Some(2));
+    /// ```
+    pub fn peek_bottom(&self) -> Option<Entry<'_, V>> {
+        let mut bottom = None;
+
+        let _ = self.visit_nodes(|node| {
+            bottom = Some(NodeRef::from_ptr(node.as_ptr()));
+            ControlFlow::Continue(())
+        });
+
+        bottom.map(|n| n.into())
+    }
+
+    /// Mutable access to the top value, which other threads cannot read until the returned
+    /// [EntryMut](EntryMut) is dropped. Waits for the [Entries](Entry) already referring to the
+    /// value to be dropped first, so holding one on the same thread deadlocks.
+    ///
+    /// The value may be popped while it is borrowed, in which case the pop waits for the
+    /// [EntryMut](EntryMut) as well.
+    ///
+    /// Needs the `peek-mut` feature, which puts a lock on every value that pops, peeks, and walks
+    /// take as well. Without it, values that change in place can hold a `Mutex` or atomics.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(0);
+    ///
+    /// *stack.peek_mut().unwrap() += 1;
+    ///
+    /// assert_eq!(stack.pop().map(|e| *e), Some(1));
+    /// ```
+    #[cfg(feature = "peek-mut")]
+    pub fn peek_mut(&self) -> Option<EntryMut<'_, V>> {
+        self.stats.hazard_acquired();
+        let NodeRef { node, _hazard, .. } = self.protect_head()?;
+
+        // # Safety: The node is protected.
+        unsafe { node.as_ref().write() };
+
+        Some(EntryMut { node, _hazard })
+    }
+
+    /// Replaces the top value with `new` if it is still the value of `expected`, peeked from this
+    /// stack, and returns the replaced value as if it was popped. Hands `new` back if the top
+    /// changed in the meantime.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(1);
+    ///
+    /// let top = stack.peek().unwrap();
+    /// let old = stack.compare_exchange_top(&top, *top + 1).unwrap();
+    ///
+    /// assert_eq!(*old, 1);
+    /// assert_eq!(stack.compare_exchange_top(&top, 5).err(), Some(5));
+    /// assert_eq!(stack.pop().map(|e| *e), Some(2));
+    /// ```
+    pub fn compare_exchange_top(&self, expected: &Entry<'_, V>, new: V) -> Result<Entry<'_, V>, V> {
+        self.stats.hazard_acquired();
+        let node = NodeRef::from_ptr(expected.node.as_ptr());
+
+        txn::replace_head(self, &node, new)?;
+
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        fail::yield_point();
+
+        unsafe {
+            DropNode::retire(self.domain, node.as_ptr());
+            self.stats.retired(1);
+            self.stats.reclaimed(self.domain.eager_reclaim());
+        }
+
+        Ok(node.into())
+    }
+
+    /// Replaces the top value with `new` as one atomic step, and returns the replaced value as
+    /// if it was popped, or hands `new` back if the stack is empty. Unlike a pop followed by a
+    /// push, no other thread sees the stack without either value, or pushes in between. The
+    /// new value is untagged.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let versions = unlink::stack![1, 2];
+    ///
+    /// assert_eq!(versions.replace_top(3).map(|e| *e), Ok(2));
+    /// assert_eq!(versions.into_iter().collect::<Vec<_>>(), vec![3, 1]);
+    /// assert_eq!(Stack::new().replace_top(4).err(), Some(4));
+    /// ```
+    pub fn replace_top(&self, mut new: V) -> Result<Entry<'_, V>, V> {
+        loop {
+            let Some(top) = self.peek() else {
+                return Err(new);
+            };
+
+            // Fails only if another thread changed the head since we peeked at it.
+            match self.compare_exchange_top(&top, new) {
+                Ok(old) => return Ok(old),
+                Err(val) => new = val,
+            }
+        }
+    }
+
+    /// Moves the values of `other` on top of this stack, keeping their order, by splicing its
+    /// nodes with a single compare exchange. Values held in another collection, such as a `Vec`,
+    /// are spliced the same way by [push_iter](Stack::push_iter).
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = unlink::stack![0];
+    ///
+    /// stack.append(unlink::stack![1, 2]);
+    /// stack.push_iter(vec![3, 4]);
+    ///
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4, 3, 2, 1, 0]);
+    /// ```
+    pub fn append(&self, other: Self) {
+        self.stats.hazard_acquired();
+        let Some(new_head) = NodeRef::from_atomic_ptr(&other.head) else {
+            return;
+        };
+
+        other.head.store(null_mut(), Ordering::Release);
+
+        let mut tail = new_head.as_ptr();
+        let mut nodes = 1;
+
+        unsafe {
+            while !(*tail).next.load(Ordering::Acquire).is_null() {
+                tail = (*tail).next.load(Ordering::Acquire);
+                nodes += 1;
+            }
+            tail
+        };
+
+        other.nodes.store(0, Ordering::Relaxed);
+
+        self.splice(new_head.as_ptr(), tail, nodes);
+    }
+
+    /// Pushes the values of `iter` as if one by one, the last one on top, and returns how many
+    /// were pushed. The values are linked into a chain of their own first, which is spliced onto
+    /// the stack with a single compare exchange, so other threads see all of them at once.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(0);
+    ///
+    /// assert_eq!(stack.push_iter([1, 2, 3]), 3);
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+    /// ```
+    pub fn push_iter<I: IntoIterator<Item = V>>(&self, iter: I) -> usize {
+        // As in `from_iter_lifo`, the chain is kept in a stack of its own, so that its values
+        // are dropped if `iter` panics.
+        let mut chain = Stack::new();
+        let mut bottom = null_mut::<Node<V>>();
+
+        for val in iter {
+            let node = Node::new(val);
+
+            unsafe { (*node).next.store(*chain.head.get_mut(), Ordering::Relaxed) };
+
+            if bottom.is_null() {
+                bottom = node;
+            }
+
+            *chain.head.get_mut() = node;
+            *chain.nodes.get_mut() += 1;
+        }
+
+        self.splice_chunk(&mut chain, bottom)
+    }
+
+    /// Moves up to `n` values from the top of the stack onto `other`, keeping their order, and
+    /// returns how many were moved. The values are detached from this stack with a single
+    /// compare exchange and spliced onto `other` with another, so other threads may observe them
+    /// in neither stack in between.
+    ///
+    /// As with [txn::transfer](crate::txn::transfer), the values are cloned into new nodes.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let busy = Stack::new();
+    /// let idle = Stack::new();
+    /// (0..5).for_each(|i| busy.push(i));
+    ///
+    /// assert_eq!(busy.transfer_n(&idle, 3), 3);
+    /// assert_eq!(busy.transfer_n(&idle, 3), 2);
+    ///
+    /// assert!(busy.pop().is_none());
+    /// assert_eq!(idle.into_iter().collect::<Vec<_>>(), vec![1, 0, 4, 3, 2]);
+    /// ```
+    pub fn transfer_n(&self, other: &Stack<V>, n: usize) -> usize
+    where
+        V: Clone,
+    {
+        if n == 0 || core::ptr::eq(self, other) {
+            return 0;
+        }
+
+        let detached = self.detach(n);
+
+        let mut top = null_mut::<Node<V>>();
+        let mut bottom = null_mut::<Node<V>>();
+        let mut moved = 0;
+
+        // Link the new nodes bottom up, retiring the detached ones only after all of them were
+        // marked as unlinked, so that a thread walking past them notices in time.
+        for &node in detached.iter().rev() {
+            // # Safety: Only we unlinked the detached nodes, so they stay valid until we retire
+            // them.
+            let node = unsafe { &*node };
+
+            // The value may have been taken by `pop_matching` or a transfer in the meantime.
+            if !txn::claim(&node.claim, txn::TAKEN) {
+                continue;
+            }
+
+            node.read();
+            let new = Node::tagged(node.val.clone(), node.tag());
+            node.unread();
+
+            unsafe { (*new).next.store(top, Ordering::Relaxed) };
+
+            if bottom.is_null() {
+                bottom = new;
+            }
+
+            top = new;
+            moved += 1;
+        }
+
+        unsafe { self.retire_detached(&detached) };
+
+        if moved > 0 {
+            other.splice(top, bottom, moved);
+            other.len.fetch_add(moved, Ordering::Relaxed);
+        }
+
+        moved
+    }
+
+    /// Takes every value off the stack with a single compare exchange, and returns clones of them
+    /// in a new stack in the same order, for handing accumulated work off to the thread that
+    /// claims it.
+    ///
+    /// As with [transfer_n](Stack::transfer_n), the values are cloned into new nodes, since other
+    /// threads may still be reading the old ones.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let inbox = unlink::stack![1, 2, 3];
+    /// let claimed = inbox.take_all();
+    ///
+    /// assert!(inbox.is_empty());
+    /// assert_eq!(claimed.into_iter().collect::<Vec<_>>(), vec![3, 2, 1]);
+    /// ```
+    pub fn take_all(&self) -> Stack<V>
+    where
+        V: Clone,
+    {
+        let taken = Stack::new();
+        self.transfer_n(&taken, usize::MAX);
+
+        taken
+    }
+
+    /// Pushes clones of the values of `other` onto this stack, in the same order and with the same
+    /// tags, leaving `other` as it was. Returns how many values were cloned. The values are read
+    /// as [visit](Stack::visit) reads them, and spliced on top of this stack with a single compare
+    /// exchange, so that other threads see all of them at once.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let template = unlink::stack!["fetch", "parse"];
+    /// let workers = [Stack::new(), Stack::new()];
+    ///
+    /// for worker in &workers {
+    ///     assert_eq!(worker.extend_cloned(&template), 2);
+    /// }
+    ///
+    /// assert_eq!(workers[1].pop().map(|e| *e), Some("parse"));
+    /// assert_eq!(template.into_iter().collect::<Vec<_>>(), vec!["parse", "fetch"]);
+    /// ```
+    pub fn extend_cloned(&self, other: &Stack<V>) -> usize
+    where
+        V: Clone,
+    {
+        let mut top = null_mut::<Node<V>>();
+        let mut bottom = null_mut::<Node<V>>();
+        let mut cloned = 0;
+
+        let _ = other.visit_nodes(|node| {
+            node.read();
+            let new = Node::tagged(node.val.clone(), node.tag());
+            node.unread();
+
+            // # Safety: The new nodes are not published until they are spliced.
+            unsafe {
+                (*new).next.store(null_mut(), Ordering::Relaxed);
+
+                if bottom.is_null() {
+                    top = new;
+                } else {
+                    (*bottom).next.store(new, Ordering::Relaxed);
+                }
+            }
+
+            bottom = new;
+            cloned += 1;
+
+            ControlFlow::Continue(())
+        });
+
+        if cloned > 0 {
+            self.splice(top, bottom, cloned);
+            self.len.fetch_add(cloned, Ordering::Relaxed);
+        }
+
+        cloned
+    }
+
+    /// Clones the stack, values and tags alike, failing with [AllocError](AllocError) rather than
+    /// aborting if memory for a node runs out. The nodes allocated by then are freed again. The
+    /// values are read as [visit](Stack::visit) reads them.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = unlink::stack![1, 2];
+    /// let clone = stack.try_clone().unwrap();
+    ///
+    /// assert_eq!(clone.into_iter().collect::<Vec<_>>(), vec![2, 1]);
+    /// assert_eq!(stack.pop().map(|e| *e), Some(2));
+    /// ```
+    pub fn try_clone(&self) -> Result<Stack<V>, AllocError>
+    where
+        V: Clone,
+    {
+        let mut clone = Stack::new();
+        clone.name = self.name;
+
+        let mut nodes = 0;
+        let mut tail: *mut AtomicPtr<Node<V>> = &mut clone.head;
+
+        // As in `from_iter_fifo`, every node is linked below the last one as soon as it is
+        // allocated, so that dropping the clone frees all of them.
+        let flow = self.visit_nodes(|node| {
+            node.read();
+            let val = node.val.clone();
+            node.unread();
+
+            let Ok(new) = Node::try_tagged(val, node.tag()) else {
+                return ControlFlow::Break(());
+            };
+
+            // # Safety: The clone is not shared yet, and `tail` points into it.
+            unsafe {
+                (*new).next.store(null_mut(), Ordering::Relaxed);
+                (*tail).store(new, Ordering::Relaxed);
+                tail = &mut (*new).next;
+            }
+
+            nodes += 1;
+            ControlFlow::Continue(())
+        });
+
+        *clone.nodes.get_mut() = nodes;
+        *clone.len.get_mut() = nodes;
+
+        if flow.is_break() {
+            return Err(AllocError);
+        }
+
+        Ok(clone)
+    }
+
+    /// Detaches every value with a single compare exchange, and returns an iterator that pops
+    /// them one by one, top first, handing out an [Entry](Entry) for each. Values pushed after the
+    /// stack was detached stay on it. Dropping the iterator drops the values it did not yield.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = unlink::stack![1, 2, 3];
+    /// let drain = stack.drain();
+    ///
+    /// stack.push(4);
+    ///
+    /// assert_eq!(drain.map(|e| *e).collect::<Vec<_>>(), vec![3, 2, 1]);
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4]);
+    /// ```
+    pub fn drain(&self) -> Drain<'_, V> {
+        Drain {
+            stack: self,
+            detached: self.detach(usize::MAX),
+            next: 0,
+        }
+    }
+
+    /// Drops every value with a single compare exchange, rather than one per value as popping
+    /// them in a loop would, and resets [len](Stack::len). The values are dropped once no other
+    /// thread reads them, so entries handed out before stay valid. Values pushed concurrently
+    /// either stay on the stack or are dropped with the others, though [len](Stack::len) may not
+    /// count those that stay.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = unlink::stack![1, 2, 3];
+    /// let top = stack.peek().unwrap();
+    ///
+    /// stack.clear();
+    ///
+    /// assert!(stack.is_empty());
+    /// assert_eq!(stack.len(), 0);
+    /// assert_eq!(*top, 3);
+    /// ```
+    pub fn clear(&self) {
+        let detached = self.detach(usize::MAX);
+        self.len.store(0, Ordering::Relaxed);
+
+        // # Safety: The nodes were just detached by us, and are retired only here.
+        unsafe { self.retire_detached(&detached) };
+    }
+
+    /// Detaches every value with a single compare exchange, and returns clones of them, top
+    /// first, as [pop_n](Stack::pop_n) does. Unlike [pop_all_into](Stack::pop_all_into), which
+    /// moves the values out of a stack it owns, this works on a shared stack, whose nodes other
+    /// threads may still be reading, so the values are cloned and the nodes retired rather than
+    /// freed.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = unlink::stack![1, 2, 3];
+    ///
+    /// assert_eq!(stack.pop_all_cloned(), vec![3, 2, 1]);
+    /// assert!(stack.pop_all_cloned().is_empty());
+    /// ```
+    pub fn pop_all_cloned(&self) -> Vec<V>
+    where
+        V: Clone,
+    {
+        self.pop_n(usize::MAX)
+    }
+
+    /// Pops up to `n` values and returns clones of them, top first. As for
+    /// [pop_into_slice](Stack::pop_into_slice), the values are detached with a single compare
+    /// exchange and their nodes retired in one pass, rather than paying for both once per value.
+    /// Nodes whose values were already taken, as by [pop_matching](Stack::pop_matching), are
+    /// detached along with the others and skipped, so fewer may be popped even if the stack held
+    /// enough.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..5).for_each(|i| stack.push(i.to_string()));
+    ///
+    /// assert_eq!(stack.pop_n(2), vec!["4", "3"]);
+    /// assert_eq!(stack.pop_n(5).len(), 3);
+    /// ```
+    pub fn pop_n(&self, n: usize) -> Vec<V>
+    where
+        V: Clone,
+    {
+        let mut popped = Vec::with_capacity(n.min(64));
+        self.pop_batch(n, |val| popped.push(val.clone()));
+
+        popped
+    }
+
+    /// Pops up to `dst.len()` values into `dst`, top first, and returns how many were popped.
+    /// The values are detached with a single compare exchange, as for
+    /// [transfer_n](Stack::transfer_n), and copied out of their nodes one after the other, before
+    /// any of the nodes is retired, which costs far less per value than as many
+    /// [pops](Stack::pop). Values taken while they were detached are skipped, so fewer may be
+    /// popped even if the stack held enough.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..5).for_each(|i| stack.push(i as f32));
+    ///
+    /// let mut batch = [0.0; 3];
+    ///
+    /// assert_eq!(stack.pop_into_slice(&mut batch), 3);
+    /// assert_eq!(batch, [4.0, 3.0, 2.0]);
+    /// assert_eq!(stack.pop_into_slice(&mut batch), 2);
+    /// assert_eq!(batch[..2], [1.0, 0.0]);
+    /// ```
+    pub fn pop_into_slice(&self, dst: &mut [V]) -> usize
+    where
+        V: Copy,
+    {
+        let mut popped = 0;
+
+        self.pop_batch(dst.len(), |val| {
+            dst[popped] = *val;
+            popped += 1;
+        })
+    }
+
+    /// Detaches up to `n` values as [pop_into_slice](Stack::pop_into_slice) does, and passes them
+    /// to `f` top first, before any of their nodes is retired. Returns how many there were.
+    pub(crate) fn pop_batch<F>(&self, n: usize, mut f: F) -> usize
+    where
+        F: FnMut(&V),
+    {
+        if n == 0 {
+            return 0;
+        }
+
+        let detached = self.detach(n);
+        let mut popped = 0;
+
+        for &node in &detached {
+            // # Safety: As in `transfer_n`.
+            let node = unsafe { &*node };
+
+            if !txn::claim(&node.claim, txn::TAKEN) {
+                continue;
+            }
+
+            node.read();
+            f(&node.val);
+            node.unread();
+
+            popped += 1;
+        }
+
+        unsafe { self.retire_detached(&detached) };
+
+        popped
+    }
+
+    /// Detaches up to `n` nodes from the top of the stack with a single compare exchange, and
+    /// returns them top first, frozen as unlinked.
+    fn detach(&self, n: usize) -> Vec<*mut Node<V>> {
+        self.stats.hazard_acquired();
+        let mut retries = 0;
+
+        let chain = 'detach: loop {
+            let Some(first) = self.protect_head() else {
+                self.stats.operation(retries);
+                return Vec::new();
+            };
+
+            let mut chain = vec![first];
+
+            while chain.len() < n {
+                match chain[chain.len() - 1].successor() {
+                    Ok(Some(next)) => chain.push(next),
+                    Ok(None) => break,
+                    Err(Unlinked) => {
+                        retries += 1;
+                        self.stats.hazard_acquired();
+                        continue 'detach;
+                    }
+                }
+            }
+
+            // A value may have been inserted below one of the nodes since we read its successor,
+            // which freezing them rules out from here on.
+            let frozen = chain.iter().take_while(|node| node.freeze()).count();
+
+            let intact = frozen == chain.len()
+                && chain
+                    .windows(2)
+                    .all(|pair| pair[0].next.load(Ordering::Acquire) == pair[1].as_ptr());
+
+            // Nodes are never linked again once unlinked, so if the first node is still the head,
+            // the nodes down to the last one are still linked below it.
+            if intact
+                && fail::compare_exchange(
+                    &self.head,
+                    chain[0].as_ptr(),
+                    chain[frozen - 1].next.load(Ordering::Acquire),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                chain[0].check_generation();
+                break chain;
+            }
+
+            chain[..frozen].iter().for_each(|node| node.thaw());
+
+            retries += 1;
+            self.stats.hazard_acquired();
+        };
+
+        self.stats.operation(retries);
+
+        // Only we unlinked the detached nodes, so they stay valid until we retire them.
+        let detached: Vec<_> = chain.iter().map(NodeRef::as_ptr).collect();
+
+        drop(chain);
+        self.nodes.fetch_sub(detached.len(), Ordering::Relaxed);
+
+        fail::yield_point();
+
+        detached
+    }
+
+    /// Retires the nodes returned by [detach](Stack::detach).
+    ///
+    /// # Safety: The nodes must not be retired again.
+    unsafe fn retire_detached(&self, detached: &[*mut Node<V>]) {
+        for &node in detached {
+            DropNode::retire(self.domain, node);
+        }
+
+        self.stats.retired(detached.len());
+        self.stats.reclaimed(self.domain.eager_reclaim());
+    }
+
+    /// Pops exactly `n` values from the top of the stack as one atomic step, and returns them
+    /// in a new stack in the same order, or returns `None` without popping anything if there are
+    /// fewer. No other thread observes some of the values popped and others not, so a group of
+    /// values pushed together is never split between consumers.
+    ///
+    /// The values are first claimed, and then taken together, which fails and starts over if
+    /// another thread takes one of them in between. Values pushed while this runs may end up
+    /// above the ones popped. As with [transfer_n](Stack::transfer_n), the values are cloned
+    /// into new nodes.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..5).for_each(|i| stack.push(i));
+    ///
+    /// let batch = stack.try_pop_n_exact(3).unwrap();
+    ///
+    /// assert_eq!(batch.into_iter().collect::<Vec<_>>(), vec![4, 3, 2]);
+    /// assert!(stack.try_pop_n_exact(3).is_none());
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![1, 0]);
+    /// ```
+    pub fn try_pop_n_exact(&self, n: usize) -> Option<Stack<V>>
+    where
+        V: Clone,
+    {
+        if n == 0 {
+            return Some(Stack::new());
+        }
+
+        self.stats.hazard_acquired();
+        let mut retries = 0;
+
+        let nodes = 'restart: loop {
+            let Some(mut curr) = self.protect_head() else {
+                self.stats.operation(retries);
+                return None;
+            };
+
+            let mut nodes = Vec::with_capacity(n);
+
+            loop {
+                let Ok(next) = curr.successor() else {
+                    retries += 1;
+                    self.stats.hazard_acquired();
+                    continue 'restart;
+                };
+
+                if !txn::is_taken(&curr.claim) {
+                    nodes.push(curr);
+                }
+
+                if nodes.len() == n {
+                    break;
+                }
+
+                let Some(next) = next else {
+                    self.stats.operation(retries);
+                    return None;
+                };
+
+                curr = next;
+            }
+
+            let claims = nodes.iter().map(|node| &node.claim).collect::<Vec<_>>();
+
+            if txn::take_all(self.domain, &claims) {
+                break nodes;
+            }
+
+            retries += 1;
+            self.stats.hazard_acquired();
+        };
+
+        self.stats.operation(retries);
+
+        let popped = Stack::from_iter_fifo(nodes.iter().map(|node| {
+            node.read();
+            let val = node.val.clone();
+            node.unread();
+            val
+        }));
+
+        // Unlink the taken nodes right away if nothing was pushed on top of them.
+        drop(nodes);
+        drop(self.protect_head());
+
+        Some(popped)
+    }
+
+    /// Links the chain of `nodes` new nodes from `top` down to `bottom` on top of the stack.
+    fn splice(&self, top: *mut Node<V>, bottom: *mut Node<V>, nodes: usize) {
+        self.nodes.fetch_add(nodes, Ordering::Relaxed);
+
+        let mut old_head = self.load_head();
+        unsafe {
+            (*bottom).next.store(old_head, Ordering::Release);
+        }
+
+        let mut retries = 0;
+
+        while let Err(head_now) = fail::compare_exchange(
+            &self.head,
+            old_head,
+            top,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        ) {
+            retries += 1;
+            old_head = if txn::is_marked(head_now) {
+                self.load_head()
+            } else {
+                head_now
+            };
+            unsafe {
+                (*bottom).next.store(old_head, Ordering::Release);
+            }
+        }
+
+        self.stats.operation(retries);
+    }
+
+    /// Splices the values of `chunk`, down to `bottom`, onto the stack, leaving `chunk` empty.
+    fn splice_chunk(&self, chunk: &mut Stack<V>, bottom: *mut Node<V>) -> usize {
+        let nodes = core::mem::take(chunk.nodes.get_mut());
+        let top = core::mem::replace(chunk.head.get_mut(), null_mut());
+
+        if nodes > 0 {
+            self.splice(top, bottom, nodes);
+            self.len.fetch_add(nodes, Ordering::Relaxed);
+        }
+
+        nodes
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<V> Stack<V>
+where
+    V: Send + Sync + core::fmt::Debug,
+{
+    /// Writes the chain of nodes as a Graphviz digraph, including nodes whose values were taken
+    /// but that are still linked. Nodes protected by a hazard pointer of another thread, or
+    /// referred to by an [Entry](Entry), are drawn in red with the number of hazard pointers.
+    ///
+    /// Only available in debug builds. The chain is read node by node, so it is only a consistent
+    /// picture while no other thread modifies the stack.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// (0..3).for_each(|i| stack.push(i));
+    ///
+    /// let mut dot = Vec::new();
+    /// stack.dump_dot(&mut dot).unwrap();
+    ///
+    /// assert!(String::from_utf8(dot).unwrap().starts_with("digraph stack {"));
+    /// ```
+    pub fn dump_dot<W: std::io::Write>(&self, mut w: W) -> std::io::Result<()> {
+        // Taken before our own hazard pointers protect anything.
+        let protected = self.domain.protected();
+
+        let nodes = 'restart: loop {
+            let mut nodes = Vec::new();
+
+            let Some(mut curr) = NodeRef::from_atomic_ptr(&self.head) else {
+                break nodes;
+            };
+
+            if txn::is_marked(curr.as_ptr()) {
+                drop(curr);
+                self.load_head();
+                continue;
+            }
+
+            loop {
+                let state = match curr.claim.load(Ordering::Acquire) {
+                    txn::FREE => "",
+                    txn::TAKEN => "\\ntaken",
+                    _ => "\\ntransferring",
+                };
+
+                curr.read();
+                let val = format!("{:?}", curr.val);
+                curr.unread();
+
+                let ptr = curr.as_ptr();
+                let hazards = protected.iter().filter(|&&p| p == ptr.cast()).count();
+
+                nodes.push((ptr, val, curr.tag(), state, hazards));
+
+                curr = match curr.successor() {
+                    Ok(Some(next)) => next,
+                    Ok(None) => break 'restart nodes,
+                    Err(Unlinked) => continue 'restart,
+                };
+            }
+        };
+
+        writeln!(w, "digraph stack {{")?;
+        writeln!(w, "    head [shape=plaintext];")?;
+
+        for (i, (ptr, val, tag, state, hazards)) in nodes.iter().enumerate() {
+            let mut label = format!("{:p}\\n{}", ptr, val.escape_debug());
+
+            if cfg!(feature = "tags") {
+                label += &format!("\\ntag {}", tag);
+            }
+
+            label += state;
+
+            write!(w, "    node{} [shape=box, label=\"{}", i, label)?;
+
+            if *hazards > 0 {
+                write!(w, "\\nhazard x{}\", color=red", hazards)?;
+            } else {
+                write!(w, "\"")?;
+            }
+
+            writeln!(w, "];")?;
+        }
+
+        if !nodes.is_empty() {
+            writeln!(w, "    head -> node0;")?;
+        }
+
+        for i in 1..nodes.len() {
+            writeln!(w, "    node{} -> node{};", i - 1, i)?;
+        }
+
+        writeln!(w, "}}")
+    }
+}
+
+impl<T> Stack<alloc::sync::Arc<T>>
+where
+    T: Send + Sync,
+{
+    /// Clones the top `Arc`, releasing the hazard pointer right away rather than holding on to
+    /// an [Entry](Entry).
+    pub fn peek_cloned_arc(&self) -> Option<alloc::sync::Arc<T>> {
+        self.peek().map(|entry| alloc::sync::Arc::clone(&entry))
+    }
+
+    /// Pops the top `Arc` and returns a clone of it, releasing the hazard pointer right away.
+    /// The popped `Arc` itself is dropped once its node is reclaimed, so the strong count stays
+    /// one higher until then.
+    /// ```
+    /// use std::sync::Arc;
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(Arc::new("job"));
+    ///
+    /// assert_eq!(stack.peek_cloned_arc().as_deref(), Some(&"job"));
+    /// assert_eq!(stack.pop_arc().as_deref(), Some(&"job"));
+    /// assert!(stack.pop_arc().is_none());
+    /// ```
+    pub fn pop_arc(&self) -> Option<alloc::sync::Arc<T>> {
+        self.pop().map(|entry| alloc::sync::Arc::clone(&entry))
+    }
+}
+
+/// Clones the stack as [try_clone](Stack::try_clone) does, aborting if memory runs out.
+impl<V> Clone for Stack<V>
+where
+    V: Clone + Send + Sync,
+{
+    fn clone(&self) -> Self {
+        self.try_clone().unwrap_or_else(|_| {
+            alloc::alloc::handle_alloc_error(core::alloc::Layout::new::<Node<V>>())
+        })
+    }
+}
+
+impl<V> crate::ConcurrentStack<V> for Stack<V>
+where
+    V: Send + Sync,
+{
+    type Entry<'a>
+        = Entry<'a, V>
+    where
+        Self: 'a;
+
+    fn push(&self, val: V) -> Result<(), V> {
+        Stack::push(self, val);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Self::Entry<'_>> {
+        Stack::pop(self)
+    }
+
+    fn peek(&self) -> Option<Self::Entry<'_>> {
+        Stack::peek(self)
+    }
+
+    fn append(&self, other: Self) {
+        Stack::append(self, other)
+    }
+
+    /// Counts the linked nodes, which [len](Stack::len) does not, as it counts pushes.
+    fn len(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.protect_head().is_none()
+    }
+}
+
+#[cfg(not(feature = "nightly"))]
+impl<V> Drop for Stack<V> {
+    fn drop(&mut self) {
+        self.drop_nodes();
+    }
+}
+
+// # Safety: Dropping the stack only drops the values it owns, and never otherwise accesses them.
+#[cfg(feature = "nightly")]
+unsafe impl<#[may_dangle] V> Drop for Stack<V> {
+    fn drop(&mut self) {
+        self.drop_nodes();
+    }
+}
+
+impl<V> Stack<V> {
+    fn drop_nodes(&mut self) {
+        // Deallocate all pointers that are no longer referred to.
+        self.domain.eager_reclaim();
+
+        let mut curr = self.head.load(Ordering::Acquire);
+
+        // # Safety: We have exclusive ownership of self.
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next.load(Ordering::Acquire);
+                Node::drop(curr);
+                curr = next;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+impl<V> Stack<V> {
+    /// Panics unless the stack is quiescent: no hazard pointer, such as that of an
+    /// [Entry](Entry), protects one of its nodes, and no retired node awaits reclamation once
+    /// the unprotected ones were reclaimed. Retired nodes are shared by all collections in the
+    /// process, so entries held on to for any of them fail the check as well.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(1);
+    ///
+    /// let entry = stack.pop();
+    /// drop(entry);
+    ///
+    /// stack.assert_quiescent();
+    /// ```
+    pub fn assert_quiescent(&self) {
+        self.domain.eager_reclaim();
+
+        // Taken before our own hazard pointers protect anything.
+        let protected = self.domain.protected();
+        let backlog = self.domain.backlog();
+
+        let linked = 'restart: loop {
+            let mut linked = Vec::new();
+
+            let Some(mut curr) = NodeRef::from_atomic_ptr(&self.head) else {
+                break linked;
+            };
+
+            if txn::is_marked(curr.as_ptr()) {
+                drop(curr);
+                self.load_head();
+                continue;
+            }
+
+            loop {
+                let ptr = curr.as_ptr();
+                let hazards = protected.iter().filter(|&&p| p == ptr.cast()).count();
+
+                if hazards > 0 {
+                    linked.push((ptr, hazards));
+                }
+
+                curr = match curr.successor() {
+                    Ok(Some(next)) => next,
+                    Ok(None) => break 'restart linked,
+                    Err(Unlinked) => continue 'restart,
+                };
+            }
+        };
+
+        assert!(
+            linked.is_empty() && backlog == 0,
+            "{:?} is not quiescent: {} of its nodes are protected {:?}, and {} retired nodes \
+             await reclamation",
+            self,
+            linked.len(),
+            linked,
+            backlog,
+        );
+    }
+}
+
+/// The fewest nodes [dispose_parallel](Stack::dispose_parallel) hands to a thread, below which
+/// spawning it costs more than it saves.
+const MIN_SEGMENT: usize = 1 << 14;
+
+impl<V> Stack<V>
+where
+    V: Send,
+{
+    /// Drops the stack and its values like `drop` does, but splits the chain into segments that
+    /// are freed on up to `threads` threads, for stacks large enough that freeing them on one
+    /// thread stalls. Smaller stacks are freed on fewer threads, down to only the calling one.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = (0..100_000).map(|i| i.to_string()).collect::<Stack<_>>();
+    ///
+    /// stack.dispose_parallel(4);
+    /// ```
+    pub fn dispose_parallel(mut self, threads: usize) {
+        self.domain.eager_reclaim();
+
+        let nodes = *self.nodes.get_mut();
+        let threads = threads.min(nodes / MIN_SEGMENT).max(1);
+        let per_thread = nodes.div_ceil(threads);
+
+        let mut segments = Vec::with_capacity(threads);
+        let mut curr = core::mem::replace(self.head.get_mut(), null_mut());
+
+        // # Safety: We have exclusive ownership of self, and the chain was detached from it.
+        unsafe {
+            while !curr.is_null() {
+                segments.push(Segment(curr));
+
+                for _ in 1..per_thread {
+                    let next = (*curr).next.load(Ordering::Acquire);
+
+                    if next.is_null() {
+                        break;
+                    }
+
+                    curr = next;
+                }
+
+                curr = (*curr).next.swap(null_mut(), Ordering::AcqRel);
+            }
+        }
+
+        std::thread::scope(|s| {
+            let mut segments = segments.into_iter();
+            let first = segments.next();
+
+            for segment in segments {
+                s.spawn(move || segment.free());
+            }
+
+            if let Some(segment) = first {
+                segment.free();
+            }
+        });
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    /// Distributes the values over `n` new stacks, round robin from the top down, so that each
+    /// shard keeps their relative order. The nodes are relinked rather than popped and pushed
+    /// again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = (0..5).collect::<Stack<_>>();
+    /// let shards = stack.split_to_shards(2);
+    ///
+    /// let shards = shards
+    ///     .into_iter()
+    ///     .map(|s| s.into_iter().collect::<Vec<_>>())
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert_eq!(shards, vec![vec![4, 2, 0], vec![3, 1]]);
+    /// ```
+    pub fn split_to_shards(mut self, n: usize) -> Vec<Stack<V>> {
+        assert!(n > 0, "cannot split a stack into zero shards");
+
+        let mut shards = (0..n).map(|_| Stack::new()).collect::<Vec<_>>();
+        let mut tails = shards
+            .iter_mut()
+            .map(|s| &mut s.head as *mut AtomicPtr<Node<V>>)
+            .collect::<Vec<_>>();
+        let mut counts = vec![0; n];
+
+        let mut curr = core::mem::replace(self.head.get_mut(), null_mut());
+        let mut shard = 0;
+
+        // # Safety: We own the chain, which no other thread can reach, and the tails point into
+        // the shards, which are not touched otherwise until the chain is relinked.
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next.load(Ordering::Acquire);
+
+                // The values of nodes left behind by `pop_matching` were already handed out.
+                if (*curr).claim.load(Ordering::Acquire) == txn::TAKEN {
+                    Node::drop(curr);
+                } else {
+                    (*curr).next.store(null_mut(), Ordering::Relaxed);
+                    (*tails[shard]).store(curr, Ordering::Relaxed);
+
+                    tails[shard] = &mut (*curr).next;
+                    counts[shard] += 1;
+                    shard = (shard + 1) % n;
+                }
+
+                curr = next;
+            }
+        }
+
+        for (stack, count) in shards.iter_mut().zip(counts) {
+            *stack.nodes.get_mut() = count;
+            *stack.len.get_mut() = count;
+        }
+
+        shards
+    }
+}
+
+/// [Segment](Segment) is a null terminated chain of nodes that
+/// [dispose_parallel](Stack::dispose_parallel) frees on another thread.
+struct Segment<V>(*mut Node<V>);
+
+// # Safety: A segment owns its nodes, and only drops their values on the thread it is sent to.
+unsafe impl<V: Send> Send for Segment<V> {}
+
+impl<V> Segment<V> {
+    fn free(self) {
+        let mut curr = self.0;
+
+        // # Safety: The nodes of the segment are reachable from nowhere else.
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next.load(Ordering::Acquire);
+                Node::drop(curr);
+                curr = next;
+            }
+        }
+    }
+}
+
+/// The most values [Stack](Stack)'s `defmt::Format` implementation logs, from the top.
+#[cfg(feature = "defmt")]
+const DEFMT_VALUES: usize = 8;
+
+#[cfg(feature = "defmt")]
+impl<V> defmt::Format for Stack<V>
+where
+    V: Send + Sync + defmt::Format,
+{
+    fn format(&self, f: defmt::Formatter<'_>) {
+        // Walks like `walk`, but gathers the nodes first, so that a restart does not log a value
+        // twice.
+        let (top, truncated) = 'restart: loop {
+            let mut top: Vec<NodeRef<'_, V>> = Vec::with_capacity(DEFMT_VALUES);
+
+            let Some(mut curr) = self.protect_head() else {
+                break (top, false);
+            };
+
+            loop {
+                let Ok(next) = curr.successor() else {
+                    continue 'restart;
+                };
+
+                if !txn::is_taken(&curr.claim) {
+                    if top.len() == DEFMT_VALUES {
+                        break 'restart (top, true);
+                    }
+
+                    top.push(curr);
+                }
+
+                match next {
+                    Some(next) => curr = next,
+                    None => break 'restart (top, false),
+                }
+            }
+        };
+
+        defmt::write!(f, "Stack {{ ");
+
+        if let Some(name) = self.name {
+            defmt::write!(f, "name: {=str}, ", name);
+        }
+
+        defmt::write!(f, "len: {=usize}, top: [", self.len());
+
+        for (i, node) in top.iter().enumerate() {
+            if i > 0 {
+                defmt::write!(f, ", ");
+            }
+
+            node.read();
+            defmt::write!(f, "{}", node.val);
+            node.unread();
+        }
+
+        if truncated {
+            defmt::write!(f, ", ..");
+        }
+
+        defmt::write!(f, "] }}");
+    }
+}
+
+/// The most values [extend_from_deserializer](Stack::extend_from_deserializer) links before it
+/// splices them onto the stack.
+#[cfg(feature = "serde")]
+const SERDE_CHUNK: usize = 1024;
+
+#[cfg(feature = "serde")]
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    /// Pushes the values of a sequence as they are deserialized, the last one on top, and
+    /// returns how many were pushed. Values are linked into chunks that are spliced onto the
+    /// stack one at a time, so the sequence is never held in memory as a whole, and other threads
+    /// can pop the values of a chunk as soon as it was spliced. If deserialization fails, the
+    /// values of the chunk in progress are dropped, while the ones spliced before stay.
+    /// ```
+    /// use serde::de::value::{Error, SeqDeserializer};
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::new();
+    /// stack.push(0);
+    ///
+    /// let de = SeqDeserializer::<_, Error>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(stack.extend_from_deserializer(de).unwrap(), 3);
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+    /// ```
+    pub fn extend_from_deserializer<'de, D>(&self, deserializer: D) -> Result<usize, D::Error>
+    where
+        V: serde::Deserialize<'de>,
+        D: serde::Deserializer<'de>,
+    {
+        serde::de::DeserializeSeed::deserialize(self, deserializer)
+    }
+}
+
+/// Deserializes a sequence onto an existing stack, as
+/// [extend_from_deserializer](Stack::extend_from_deserializer) does, for stacks nested in other
+/// deserialized data. Yields the number of values pushed.
+#[cfg(feature = "serde")]
+impl<'de, V> serde::de::DeserializeSeed<'de> for &Stack<V>
+where
+    V: Send + Sync + serde::Deserialize<'de>,
+{
+    type Value = usize;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<usize, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, V> serde::de::Visitor<'de> for &Stack<V>
+where
+    V: Send + Sync + serde::Deserialize<'de>,
+{
+    type Value = usize;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("a sequence of values")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<usize, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        // The chunk owns its values until they are spliced, so that they are dropped if
+        // deserialization fails.
+        let mut chunk = Stack::new();
+        let mut bottom = null_mut::<Node<V>>();
+        let mut pushed = 0;
+
+        while let Some(val) = seq.next_element()? {
+            let node = Node::new(val);
+
+            unsafe { (*node).next.store(*chunk.head.get_mut(), Ordering::Relaxed) };
+
+            if bottom.is_null() {
+                bottom = node;
+            }
+
+            *chunk.head.get_mut() = node;
+            *chunk.nodes.get_mut() += 1;
+
+            if *chunk.nodes.get_mut() == SERDE_CHUNK {
+                pushed += self.splice_chunk(&mut chunk, bottom);
+                bottom = null_mut();
+            }
+        }
+
+        Ok(pushed + self.splice_chunk(&mut chunk, bottom))
+    }
+}
+
+/// Deserializes a sequence into a stack that holds its last value on top, as if the values
+/// were pushed in order.
+#[cfg(feature = "serde")]
+impl<'de, V> serde::Deserialize<'de> for Stack<V>
+where
+    V: Send + Sync + serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let stack = Stack::new();
+        stack.extend_from_deserializer(deserializer)?;
+        Ok(stack)
+    }
+}
+
+/// [NodeRef](NodeRef) is a protected `*mut` to a Node. It will be valid until it is dropped.
+pub(crate) struct NodeRef<'a, V> {
+    node: NonNull<Node<V>>,
+    _hazard: Hazard<'a>,
+    /// The generation of the node once it is known to be linked, or zero, in debug builds.
+    #[cfg(debug_assertions)]
+    generation: usize,
+}
+
+impl<'a, V> NodeRef<'a, V> {
+    pub(crate) fn as_ptr(&self) -> *mut Node<V> {
+        self.node.as_ptr()
+    }
+}
+
+impl<'a, V> core::ops::Deref for NodeRef<'a, V> {
+    type Target = Node<V>;
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.node.as_ref() }
+    }
+}
+
+impl<'a, V> NodeRef<'a, V> {
+    pub(crate) fn from_ptr(ptr: *mut Node<V>) -> Self {
+        assert!(!ptr.is_null());
+
+        let mut _hazard = Hazard::new(&UNIQUE_FAMILY);
+
+        _hazard.protect_raw(ptr);
+
+        let node = unsafe { NonNull::new_unchecked(ptr) };
+
+        NodeRef {
+            node,
+            _hazard,
+            #[cfg(debug_assertions)]
+            generation: 0,
+        }
+    }
+
+    pub(crate) fn from_atomic_ptr(ptr: &AtomicPtr<Node<V>>) -> Option<Self> {
+        let mut _hazard = Hazard::new(&UNIQUE_FAMILY);
+
+        let node = _hazard.protect_ptr(&ptr)?.0;
+
+        Some(NodeRef {
+            node,
+            _hazard,
+            #[cfg(debug_assertions)]
+            generation: 0,
+        })
+    }
+
+    /// Records the generation of the node, which must be known to be linked, for
+    /// [check_generation](NodeRef::check_generation).
+    fn stamp(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            self.generation = (**self).generation.load(Ordering::SeqCst);
+        }
+    }
+
+    /// Panics in debug builds if the node was freed, or its memory reused by another node, since
+    /// it was [stamped](NodeRef::stamp). A compare exchange that succeeded against it in the
+    /// meantime hit an ABA, which its hazard pointer should have ruled out.
+    fn check_generation(&self) {
+        #[cfg(debug_assertions)]
+        {
+            let now = (**self).generation.load(Ordering::SeqCst);
+
+            assert!(
+                self.generation == 0 || now == self.generation,
+                "ABA on node {:p}: its generation changed from {} to {} while it was protected",
+                self.as_ptr(),
+                self.generation,
+                now,
+            );
+        }
+    }
+}
+
+/// A protected node that [compact](Stack::compact) does not unlink below the head, which a walk
+/// stands on so that it can start over from it without passing any value twice.
+struct Anchor<'a, V>(NodeRef<'a, V>);
+
+impl<'a, V> Anchor<'a, V> {
+    /// Anchors `node`, failing if an attempt to unlink it was made. Either this sees the attempt,
+    /// or [compact](Stack::compact) sees the anchor.
+    fn new(node: NodeRef<'a, V>) -> Option<Self> {
+        node.enter(ANCHORED, UNLINKING).then(|| Anchor(node))
+    }
+}
+
+impl<'a, V> core::ops::Deref for Anchor<'a, V> {
+    type Target = NodeRef<'a, V>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, V> Drop for Anchor<'a, V> {
+    fn drop(&mut self) {
+        self.state.fetch_sub(ANCHORED, Ordering::SeqCst);
+    }
+}
+
+/// The number of bytes held by nodes retired through [DropNode::retire](DropNode::retire) that
+/// have not been reclaimed yet.
+static RETIRED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The memory held by nodes that any [Stack](Stack) or [Slot](crate::Slot) retired, and that
+/// were not reclaimed yet, as they may still be read by other threads.
+/// ```
+/// use unlink::Stack;
+///
+/// let stack = Stack::new();
+/// stack.push(1);
+///
+/// let popped = stack.pop();
+///
+/// // The popped value is held, so its node cannot be reclaimed.
+/// assert!(unlink::retired_bytes() > 0);
+/// # drop(popped);
+/// ```
+pub fn retired_bytes() -> usize {
+    RETIRED_BYTES.load(Ordering::Relaxed)
+}
+
+#[repr(transparent)]
+pub(crate) struct DropNode<V>(NonNull<Node<V>>);
+
+impl<V: Send> DropNode<V> {
+    /// Retires `raw` into `domain`, accounting for its memory until it is reclaimed.
+    ///
+    /// # Safety: `raw` must be unlinked and must not be retired again.
+    pub(crate) unsafe fn retire(domain: &Domain<UniqueFamily>, raw: *mut Node<V>) {
+        RETIRED_BYTES.fetch_add(core::mem::size_of::<Node<V>>(), Ordering::Relaxed);
+        domain::retire::<_, DropNode<_>>(domain, raw);
+    }
+}
+
+impl<V> DropNode<V> {
+    /// Frees `raw`, which was retired, once neither a hazard pointer nor a token holds it.
+    ///
+    /// # Safety: `raw` must not be freed again.
+    unsafe fn free(raw: *mut Node<V>) {
+        Node::drop(raw);
+
+        domain::reclaimed();
+        RETIRED_BYTES.fetch_sub(core::mem::size_of::<Node<V>>(), Ordering::Relaxed);
+    }
+}
+
+impl<V> Drop for DropNode<V> {
+    fn drop(&mut self) {
+        // A node that a token still holds is freed once the token is dropped.
+        #[cfg(feature = "tokens")]
+        if self.tokens.fetch_add(RECLAIMED, Ordering::AcqRel) != 0 {
+            return;
+        }
+
+        unsafe { DropNode::free(self.0.as_ptr()) };
+    }
+}
+
+impl<V> core::ops::Deref for DropNode<V> {
+    type Target = Node<V>;
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+unsafe impl<V> crate::hazard::Pointer<Node<V>> for DropNode<V> {
+    unsafe fn from_raw(ptr: *mut Node<V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+pub struct Entry<'a, V> {
+    node: NonNull<Node<V>>,
+    _hazard: Hazard<'a>,
+    /// Whether the entry took over the hold of a [Token](Token) on the node, which its hazard
+    /// pointer does not protect then.
+    #[cfg(feature = "tokens")]
+    held: bool,
+}
+
+impl<'a, V> core::ops::Deref for Entry<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.node.as_ref().val }
+    }
+}
+
+impl<'a, V> Entry<'a, V> {
+    /// The time since the value was pushed.
+    #[cfg(feature = "age")]
+    pub fn age(&self) -> core::time::Duration {
+        unsafe { self.node.as_ref().pushed.elapsed() }
+    }
+}
+
+impl<'a, V> Drop for Entry<'a, V> {
+    fn drop(&mut self) {
+        unsafe { self.node.as_ref().unread() }
+
+        #[cfg(feature = "tokens")]
+        if self.held {
+            unsafe { Node::release(self.node.as_ptr()) };
+        }
+    }
+}
+
+impl<'a, V> From<NodeRef<'a, V>> for Entry<'a, V> {
+    fn from(node_ref: NodeRef<'a, V>) -> Self {
+        node_ref.read();
+
+        let NodeRef { node, _hazard, .. } = node_ref;
+
+        Entry {
+            node,
+            _hazard,
+            #[cfg(feature = "tokens")]
+            held: false,
+        }
+    }
+}
+
+/// [Iter](Iter) walks a [Stack](Stack) from the top down, returned by [iter](Stack::iter).
+pub struct Iter<'a, V> {
+    stack: &'a Stack<V>,
+    started: bool,
+    /// The node the walk stands on, which stays protected so that the walk can go on from it.
+    curr: Option<Anchor<'a, V>>,
+    /// Whether `curr` was neither yielded nor skipped yet.
+    fresh: bool,
+    /// As in `visit_nodes`, the node the walk stood on when it last started over.
+    visited: Option<Anchor<'a, V>>,
+    /// Whether the walk skips the nodes up to `visited`, which were yielded before.
+    skipping: bool,
+}
+
+impl<'a, V> core::fmt::Debug for Iter<'a, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Iter").finish()
+    }
+}
+
+impl<'a, V> Iter<'a, V>
+where
+    V: Send + Sync,
+{
+    /// Starts the walk over from the head.
+    fn restart(&mut self) {
+        self.stack.stats.hazard_acquired();
+        self.curr = self.stack.anchor_head();
+        self.fresh = true;
+        self.skipping = self.visited.is_some();
+    }
+}
+
+impl<'a, V> Iterator for Iter<'a, V>
+where
+    V: Send + Sync,
+{
+    type Item = Entry<'a, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            self.restart();
+        }
+
+        loop {
+            let curr = self.curr.take()?;
+
+            if core::mem::take(&mut self.fresh) {
+                if self.skipping {
+                    self.skipping = self
+                        .visited
+                        .as_ref()
+                        .is_some_and(|v| v.as_ptr() != curr.as_ptr());
+                } else if !txn::is_taken(&curr.claim) {
+                    let entry = NodeRef::from_ptr(curr.as_ptr()).into();
+                    self.curr = Some(curr);
+
+                    return Some(entry);
+                }
+            }
+
+            let next = match curr.successor() {
+                Ok(None) if !self.skipping => return None,
+                Ok(None) => {
+                    // As in `visit_nodes`, the node was unlinked along with every node above it.
+                    self.visited = None;
+                    self.restart();
+                    continue;
+                }
+                // As in `visit_nodes`, the successor is anchored before the walk moves on to it.
+                Ok(Some(next)) => Anchor::new(next),
+                Err(Unlinked) => None,
+            };
+
+            let Some(next) = next else {
+                if !self.skipping {
+                    self.visited = Some(curr);
+                }
+
+                self.restart();
+                continue;
+            };
+
+            self.curr = Some(next);
+            self.fresh = true;
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a Stack<V>
+where
+    V: Send + Sync,
+{
+    type Item = Entry<'a, V>;
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// [Drain](Drain) pops the values detached by [drain](Stack::drain).
+pub struct Drain<'a, V: Send + Sync> {
+    stack: &'a Stack<V>,
+    /// Only we unlinked the detached nodes, so they stay valid until we retire them.
+    detached: Vec<*mut Node<V>>,
+    next: usize,
+}
+
+impl<'a, V: Send + Sync> core::fmt::Debug for Drain<'a, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Drain").finish()
+    }
+}
+
+impl<'a, V: Send + Sync> Iterator for Drain<'a, V> {
+    type Item = Entry<'a, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&node) = self.detached.get(self.next) {
+            self.next += 1;
+
+            // # Safety: See above.
+            let claimed = txn::claim(unsafe { &(*node).claim }, txn::TAKEN);
+
+            // The value may have been taken by `pop_matching` or a transfer in the meantime.
+            if claimed {
+                return Some(NodeRef::from_ptr(node).into());
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.detached.len() - self.next))
+    }
+}
+
+impl<'a, V: Send + Sync> Drop for Drain<'a, V> {
+    fn drop(&mut self) {
+        // Entries handed out protect their nodes, which are reclaimed once they are dropped.
+        unsafe { self.stack.retire_detached(&self.detached) };
+    }
+}
+
+/// [Token](Token) refers to a value pushed with [push_token](Stack::push_token), so that
+/// [remove](Stack::remove) can take it out of the stack later.
+#[cfg(feature = "tokens")]
+pub struct Token<'a, V> {
+    /// The node, which the token holds instead of protecting it.
+    node: NonNull<Node<V>>,
+    stack: &'a Stack<V>,
+}
+
+#[cfg(feature = "tokens")]
+impl<'a, V> Drop for Token<'a, V> {
+    fn drop(&mut self) {
+        unsafe { Node::release(self.node.as_ptr()) };
+    }
+}
+
+#[cfg(feature = "tokens")]
+impl<'a, V> core::fmt::Debug for Token<'a, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token").finish()
+    }
+}
+
+/// [EntryMut](EntryMut) is exclusive access to a value of a [Stack](Stack), returned by
+/// [peek_mut](Stack::peek_mut).
+#[cfg(feature = "peek-mut")]
+pub struct EntryMut<'a, V> {
+    node: NonNull<Node<V>>,
+    _hazard: Hazard<'a>,
+}
+
+#[cfg(feature = "peek-mut")]
+impl<'a, V> core::ops::Deref for EntryMut<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.node.as_ref().val }
+    }
+}
+
+#[cfg(feature = "peek-mut")]
+impl<'a, V> core::ops::DerefMut for EntryMut<'a, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // # Safety: We hold the node's write claim, so no other reference to the value exists.
+        unsafe { &mut (*self.node.as_ptr()).val }
+    }
+}
+
+#[cfg(feature = "peek-mut")]
+impl<'a, V> Drop for EntryMut<'a, V> {
+    fn drop(&mut self) {
+        unsafe { self.node.as_ref().unwrite() }
+    }
+}
+
+pub struct IntoIter<V> {
+    stack: Stack<V>,
+    /// Whether the stack is reversed, with its bottom on top, since values were last taken from
+    /// the back.
+    reversed: bool,
+}
+
+impl<V> IntoIter<V> {
+    /// Stops iterating, and returns a stack holding the values that were not yielded yet.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = (0..5).collect::<Stack<_>>();
+    /// let mut iter = stack.into_iter();
+    ///
+    /// assert_eq!(iter.next(), Some(4));
+    ///
+    /// let rest = iter.into_inner();
+    ///
+    /// assert_eq!(rest.pop().map(|e| *e), Some(3));
+    /// ```
+    pub fn into_inner(mut self) -> Stack<V> {
+        self.orient(false);
+        self.stack
+    }
+
+    /// Reverses the stack in place, unless it is already `reversed` or not.
+    fn orient(&mut self, reversed: bool) {
+        if self.reversed != reversed {
+            self.stack.reverse();
+            self.reversed = reversed;
+        }
+    }
+}
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.orient(false);
+        self.stack.pop_owned()
+    }
+}
+
+/// Yields the values from the bottom up, in the order they were pushed. The stack is reversed in
+/// place the first time, and again whenever values are taken from the other end since, so
+/// alternating between the ends costs a walk over the remaining values each time.
+/// ```
+/// use unlink::Stack;
+///
+/// let stack = Stack::new();
+/// (0..5).for_each(|i| stack.push(i));
+///
+/// assert_eq!(stack.into_iter().rev().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+/// ```
+impl<V> DoubleEndedIterator for IntoIter<V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.orient(true);
+        self.stack.pop_owned()
+    }
+}
+
+impl<V> IntoIterator for Stack<V> {
+    type Item = V;
+    type IntoIter = IntoIter<V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            stack: self,
+            reversed: false,
+        }
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    /// Builds a stack that pops the values of `iter` last to first, as pushing them one by one
+    /// would. This is what [collect](Iterator::collect) builds as well.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::from_iter_lifo([1, 2, 3]);
+    ///
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 2, 1]);
+    /// ```
+    pub fn from_iter_lifo<T: IntoIterator<Item = V>>(iter: T) -> Self {
+        let mut stack = Stack::new();
+        let mut nodes = 0;
+
+        // No other thread sees the stack yet, so the chain is linked without compare exchanges.
+        // The head is kept current, so that the stack drops the values so far if `iter` panics.
+        for val in iter {
+            let node = Node::new(val);
+
+            unsafe { (*node).next.store(*stack.head.get_mut(), Ordering::Relaxed) };
+
+            *stack.head.get_mut() = node;
+            nodes += 1;
+        }
+
+        *stack.nodes.get_mut() = nodes;
+        *stack.len.get_mut() = nodes;
+
+        stack
+    }
+
+    /// Builds a stack that pops the values of `iter` in the order it yields them, the first one
+    /// ending up on top.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let stack = Stack::from_iter_fifo([1, 2, 3]);
+    ///
+    /// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    /// ```
+    pub fn from_iter_fifo<T: IntoIterator<Item = V>>(iter: T) -> Self {
+        let mut stack = Stack::new();
+        let mut nodes = 0;
+        let mut tail = &mut stack.head;
+
+        // As in `from_iter_lifo`, but linking every node below the last one, so that the chain
+        // is always reachable from the head.
+        for val in iter {
+            let node = Node::new(val);
+
+            unsafe { (*node).next.store(null_mut(), Ordering::Relaxed) };
+
+            *tail.get_mut() = node;
+            tail = unsafe { &mut (*node).next };
+            nodes += 1;
+        }
+
+        *stack.nodes.get_mut() = nodes;
+        *stack.len.get_mut() = nodes;
+
+        stack
+    }
+
+    /// Builds a stack of `n` values, calling `f` with the index of each, so that `f(0)` ends up
+    /// on top and the values pop in index order. The nodes are linked into a single chain, as in
+    /// [from_iter_fifo](Stack::from_iter_fifo), rather than pushed one by one.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let particles = Stack::from_fn(3, |i| i * 10);
+    ///
+    /// assert_eq!(particles.into_iter().collect::<Vec<_>>(), vec![0, 10, 20]);
+    /// ```
+    pub fn from_fn<F: FnMut(usize) -> V>(n: usize, f: F) -> Self {
+        Stack::from_iter_fifo((0..n).map(f))
+    }
+
+    /// Builds a stack of `n` clones of `val`, linked into a single chain. The last one is `val`
+    /// itself, so `val` is cloned `n - 1` times, and dropped if `n` is zero.
+    /// ```
+    /// use unlink::Stack;
+    ///
+    /// let permits = Stack::repeat((), 2);
+    ///
+    /// assert!(permits.pop().is_some());
+    /// assert!(permits.pop().is_some());
+    /// assert!(permits.pop().is_none());
+    /// ```
+    pub fn repeat(val: V, n: usize) -> Self
+    where
+        V: Clone,
+    {
+        Stack::from_iter_lifo(core::iter::repeat_n(val, n))
+    }
+}
+
+impl<V> FromIterator<V> for Stack<V>
+where
+    V: Send + Sync,
+{
+    /// Builds the stack with [from_iter_lifo](Stack::from_iter_lifo), so that the last value
+    /// `iter` yields is popped first.
+    fn from_iter<T: IntoIterator<Item = V>>(iter: T) -> Self {
+        Stack::from_iter_lifo(iter)
+    }
+}
+
+/// Pushes the values with [push_iter](Stack::push_iter), so that other threads see all of them
+/// at once, the last one on top.
+/// ```
+/// use unlink::Stack;
+///
+/// let mut stack = Stack::new();
+///
+/// (&stack).extend([1, 2]);
+/// stack.extend((0..2).map(|i| i * 10));
+///
+/// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![10, 0, 2, 1]);
+/// ```
+impl<V> Extend<V> for &Stack<V>
+where
+    V: Send + Sync,
+{
+    fn extend<T: IntoIterator<Item = V>>(&mut self, iter: T) {
+        self.push_iter(iter);
+    }
+}
+
+impl<V> Extend<V> for Stack<V>
+where
+    V: Send + Sync,
+{
+    fn extend<T: IntoIterator<Item = V>>(&mut self, iter: T) {
+        self.push_iter(iter);
+    }
+}
+
+/// Builds the stack as if the values were pushed front to back, so that the back of the deque
+/// ends up on top.
+/// ```
+/// use std::collections::VecDeque;
+/// use unlink::Stack;
+///
+/// let stack = Stack::from(VecDeque::from([1, 2, 3]));
+///
+/// assert_eq!(stack.pop().map(|e| *e), Some(3));
+/// assert_eq!(VecDeque::from(stack), VecDeque::from([1, 2]));
+/// ```
+impl<V> From<VecDeque<V>> for Stack<V>
+where
+    V: Send + Sync,
+{
+    fn from(deque: VecDeque<V>) -> Self {
+        Stack::from_iter_lifo(deque)
+    }
+}
+
+/// Puts the bottom of the stack at the front of the deque and its top at the back, so that
+/// converting back and forth keeps the order.
+impl<V> From<Stack<V>> for VecDeque<V> {
+    fn from(stack: Stack<V>) -> Self {
+        stack.into_iter().rev().collect()
+    }
+}
+
+/// Builds the stack with the greatest value on top, so that it pops the values in the order the
+/// heap would.
+/// ```
+/// use std::collections::BinaryHeap;
+/// use unlink::Stack;
+///
+/// let stack = Stack::from(BinaryHeap::from([2, 3, 1]));
+///
+/// assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 2, 1]);
+/// ```
+impl<V> From<BinaryHeap<V>> for Stack<V>
+where
+    V: Ord + Send + Sync,
+{
+    fn from(heap: BinaryHeap<V>) -> Self {
+        Stack::from_iter_lifo(heap.into_sorted_vec())
+    }
+}
+
+/// Collects the values into a heap, whose order disregards the stack's.
+impl<V: Ord> From<Stack<V>> for BinaryHeap<V> {
+    fn from(stack: Stack<V>) -> Self {
+        stack.into_iter().collect()
+    }
+}
+
+/// Creates a [Stack](Stack) holding the given values, the last one on top, as if they were
+/// pushed in order. `stack![val; n]` holds `n` clones of `val`.
+/// ```
+/// let stack = unlink::stack![1, 2, 3];
+///
+/// assert_eq!(stack.pop().map(|e| *e), Some(3));
+/// assert_eq!(unlink::stack!["a"; 2].into_iter().collect::<Vec<_>>(), vec!["a", "a"]);
+/// ```
+#[macro_export]
+macro_rules! stack {
+    () => {
+        $crate::Stack::new()
+    };
+    ($elem:expr; $n:expr) => {
+        <$crate::Stack<_> as ::core::iter::FromIterator<_>>::from_iter(
+            ::core::iter::repeat($elem).take($n),
+        )
+    };
+    ($($x:expr),+ $(,)?) => {
+        <$crate::Stack<_> as ::core::iter::FromIterator<_>>::from_iter([$($x),+])
+    };
+}
+
+/// Declares a process wide [Stack](Stack) in a static, which needs neither a `OnceLock` nor an
+/// `Arc` to be shared between threads. The stack is [named](Stack::named) after the static,
+/// unless a `name` is given in the configuration.
+/// ```
+/// use unlink::global_stack;
+///
+/// global_stack!(static JOBS: Stack<u32>);
+/// global_stack!(pub static RETRIES: Stack<&'static str> = { name: "retry_queue" });
+///
+/// std::thread::spawn(|| JOBS.push(1)).join().unwrap();
+///
+/// assert_eq!(JOBS.pop().map(|e| *e), Some(1));
+/// assert_eq!(JOBS.name(), Some("JOBS"));
+/// assert_eq!(RETRIES.name(), Some("retry_queue"));
+/// ```
+#[macro_export]
+macro_rules! global_stack {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: Stack<$ty:ty> $(;)?) => {
+        $(#[$attr])*
+        $vis static $name: $crate::Stack<$ty> = $crate::Stack::named(::core::stringify!($name));
+    };
+    (
+        $(#[$attr:meta])* $vis:vis static $name:ident: Stack<$ty:ty> = {
+            name: $label:expr $(,)?
+        } $(;)?
+    ) => {
+        $(#[$attr])*
+        $vis static $name: $crate::Stack<$ty> = $crate::Stack::named($label);
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(Debug)]
+    struct CountOnDrop<V> {
         val: V,
         counter: Arc<AtomicUsize>,
     }
 
-    impl<V> Drop for CountOnDrop<V> {
-        fn drop(&mut self) {
-            self.counter.fetch_add(1, Ordering::SeqCst);
-        }
+    impl<V> Drop for CountOnDrop<V> {
+        fn drop(&mut self) {
+            self.counter.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    unsafe impl<V> Send for CountOnDrop<V> {}
+    unsafe impl<V> Sync for CountOnDrop<V> {}
+
+    #[test]
+    fn test_new_node() {
+        let node = Node::new(1);
+
+        unsafe {
+            Node::dealloc(node);
+        }
+    }
+
+    #[test]
+    fn test_push_front() {
+        let list = Stack::new();
+
+        list.push(1);
+
+        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_push_pop_sync() {
+        let list = Arc::new(Stack::new());
+
+        let mut threads = vec![];
+
+        for i in 0..10 {
+            let list = list.clone();
+
+            threads.push(std::thread::spawn(move || {
+                for _ in 0..100 {
+                    if rand::random::<u8>() % 3 != 0 {
+                        list.push(i);
+                    } else {
+                        list.pop();
+                    }
+                }
+            }))
+        }
+
+        for thead in threads {
+            thead.join().unwrap();
+        }
+
+        Arc::try_unwrap(list)
+            .unwrap()
+            .into_iter()
+            .for_each(|e| println!("{}", e));
+    }
+
+    #[test]
+    fn test_append() {
+        let expected = vec![2, 3, 7, 2, 0, 0, 3, 4, 2, 5];
+
+        let stack = Stack::new();
+
+        expected[expected.len() / 2..]
+            .iter()
+            .rev()
+            .for_each(|&e| stack.push(e));
+
+        let other = Stack::new();
+
+        expected[..expected.len() / 2]
+            .iter()
+            .rev()
+            .for_each(|&e| other.push(e));
+
+        stack.append(other);
+
+        let actual: Vec<i32> = stack.into_iter().map(|e| e).collect();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_push_iter() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        assert_eq!(stack.push_iter(core::iter::empty()), 0);
+        assert!(stack.is_empty());
+
+        let values = (0..4).map(|i| {
+            assert!(i < 3, "the iterator panics");
+
+            CountOnDrop {
+                val: i,
+                counter: counter.clone(),
+            }
+        });
+
+        // The values linked before the panic are dropped, and none is pushed.
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            stack.push_iter(values);
+        }));
+
+        assert!(panicked.is_err());
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+        assert!(stack.is_empty());
+
+        let stack = Stack::new();
+        stack.push(0);
+
+        assert_eq!(stack.push_iter(1..4), 3);
+        assert_eq!(stack.len(), 4);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_extend() {
+        fn fill<E: Extend<usize>>(dst: &mut E) {
+            dst.extend(0..3);
+        }
+
+        let mut stack = Stack::new();
+
+        fill(&mut &stack);
+        fill(&mut stack);
+
+        assert_eq!(stack.len(), 6);
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            vec![2, 1, 0, 2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn test_push_iter_sync() {
+        let stack = Stack::new();
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..10 {
+                        stack.push_iter((0..10).map(|j| (t * 10 + i) * 10 + j));
+                    }
+                });
+            }
+        });
+
+        // Every chain shows up whole, as it was spliced with a single compare exchange.
+        let values = stack.into_iter().collect::<Vec<_>>();
+
+        assert_eq!(values.len(), 400);
+        assert!(values.chunks(10).all(|chain| chain
+            .iter()
+            .rev()
+            .copied()
+            .eq(chain[9]..chain[9] + 10)));
+    }
+
+    #[test]
+    fn test_hazard() {
+        let stack = Stack::new();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        stack.append(
+            vec![
+                CountOnDrop {
+                    counter: counter.clone(),
+                    val: 0,
+                },
+                CountOnDrop {
+                    counter: counter.clone(),
+                    val: 2,
+                },
+                CountOnDrop {
+                    counter: counter.clone(),
+                    val: 3,
+                },
+            ]
+            .into_iter()
+            .fold(Stack::new(), |stack, e| {
+                stack.push(e);
+                stack
+            }),
+        );
+
+        let top = stack.peek().unwrap();
+
+        let owned = stack.pop().unwrap();
+
+        assert_eq!(top.val, owned.val);
+
+        drop(owned);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        assert!(top.val != -1);
+
+        drop(top);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        stack.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        stack.pop();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        stack.pop();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        stack.push(CountOnDrop {
+            val: 0,
+            counter: counter.clone(),
+        });
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        stack.pop();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        let stack = Stack::new();
+
+        (0..3).for_each(|i| stack.push(i));
+
+        assert_eq!(*stack.pop().unwrap(), 2);
+        assert_eq!(*stack.peek().unwrap(), 1);
+
+        let stats = stack.stats();
+
+        // Injected compare exchange failures make for retries even on a single thread.
+        if cfg!(not(feature = "fail-points")) {
+            assert_eq!(stats.cas_retries, 0);
+            assert_eq!(stats.hazard_acquisitions, 2);
+        }
+
+        assert_eq!(stats.retries_per_op.count(), 4);
+        assert_eq!(stats.retired, 1);
+        assert_eq!(stats.name, None);
+        assert_eq!(Stack::<u8>::named("jobs").stats().name, Some("jobs"));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..100 {
+                        stack.push(i);
+                        stack.pop();
+                    }
+                });
+            }
+        });
+
+        let stats = stack.stats();
+
+        assert_eq!(stats.retired, 401);
+        assert!(stats.hazard_acquisitions >= 402);
+        assert_eq!(stats.retries_per_op.count(), 804);
+        assert_eq!(stats.reclaim_batches.count(), 401);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let stack = Stack::new();
+
+        assert!(stack.is_empty());
+
+        // Unlike the length, which appending leaves as it was.
+        stack.append(crate::stack![0, 1]);
+
+        assert!(!stack.is_empty());
+        assert_eq!(stack.len(), 0);
+
+        drop(stack.extract_if(|v| *v == 0));
+        stack.pop();
+
+        // The taken node is left at the head, until a peek unlinks it.
+        assert!(!stack.is_empty());
+        assert!(stack.peek().is_none());
+        assert!(stack.is_empty());
+    }
+
+    #[cfg(not(any(
+        feature = "peek-mut",
+        feature = "tags",
+        feature = "tokens",
+        feature = "age"
+    )))]
+    #[test]
+    fn test_node_size() {
+        // The value, its link, its claim, and its state, and its generation in debug builds.
+        let words = 4 + cfg!(debug_assertions) as usize;
+
+        assert_eq!(
+            core::mem::size_of::<Node<usize>>(),
+            words * core::mem::size_of::<usize>()
+        );
+    }
+
+    #[cfg(feature = "peek-mut")]
+    #[test]
+    fn test_peek_mut() {
+        let stack = Stack::new();
+
+        assert!(stack.peek_mut().is_none());
+
+        stack.push(vec![1]);
+        stack.peek_mut().unwrap().push(2);
+
+        assert_eq!(*stack.peek().unwrap(), vec![1, 2]);
+    }
+
+    #[cfg(feature = "peek-mut")]
+    #[test]
+    fn test_peek_mut_sync() {
+        let stack = Stack::new();
+        stack.push(0_usize);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        *stack.peek_mut().unwrap() += 1;
+                    }
+                });
+            }
+
+            s.spawn(|| {
+                for _ in 0..500 {
+                    let seen = *stack.peek().unwrap();
+                    assert!(seen <= 2000);
+                }
+            });
+        });
+
+        assert_eq!(stack.pop().map(|e| *e), Some(2000));
+    }
+
+    #[test]
+    fn test_peek_bottom() {
+        let stack = Stack::new();
+
+        assert!(stack.peek_bottom().is_none());
+
+        (0..4).for_each(|i| stack.push(i));
+
+        // A taken bottom node is skipped even while it is still linked.
+        drop(stack.extract_if(|v| *v == 0));
+
+        let bottom = stack.peek_bottom().unwrap();
+
+        while stack.pop().is_some() {}
+
+        assert_eq!(*bottom, 1);
+        assert!(stack.peek_bottom().is_none());
+    }
+
+    #[test]
+    fn test_pop_for() {
+        use std::sync::atomic::AtomicBool;
+        use std::time::Duration;
+
+        const AHEAD: usize = 1 << 16;
+
+        let stack = Stack::new();
+        let done = AtomicBool::new(false);
+
+        (0..AHEAD).for_each(|i| stack.push(i));
+
+        // Ends with the window even though the stack never runs empty. The pusher starts, and
+        // stays, only so far ahead, so that it cannot run out of memory while the popper is not
+        // scheduled, nor fall behind while it is not scheduled itself.
+        let (popped, pushed) = thread::scope(|s| {
+            let pusher = s.spawn(|| {
+                let mut pushed = AHEAD;
+
+                while !done.load(Ordering::Relaxed) {
+                    if stack.nodes.load(Ordering::Relaxed) >= AHEAD {
+                        thread::yield_now();
+                        continue;
+                    }
+
+                    stack.push(pushed);
+                    pushed += 1;
+                }
+
+                pushed
+            });
+
+            let popped = stack.pop_for(Duration::from_millis(20));
+            done.store(true, Ordering::Relaxed);
+
+            (popped, pusher.join().unwrap())
+        });
+
+        assert!(!popped.is_empty());
+
+        let mut values = popped.into_iter().chain(stack).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..pushed).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_forward_to() {
+        let stack = Stack::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        assert_eq!(stack.forward_to(&tx), 0);
+
+        (0..4).for_each(|i| stack.push(i));
+
+        assert_eq!(stack.forward_to(&tx), 4);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+
+        drop(rx);
+        (4..6).for_each(|i| stack.push(i));
+
+        // The receiver is gone, so the value popped for it goes back on top.
+        assert_eq!(stack.forward_to(&tx), 0);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![5, 4]);
+    }
+
+    #[test]
+    fn test_compare_and_pop() {
+        let stack = Stack::new();
+
+        assert!(!stack.compare_and_pop(&0));
+
+        (0..3).for_each(|i| stack.push(i));
+
+        assert!(!stack.compare_and_pop(&1));
+        assert!(stack.compare_and_pop(&2));
+
+        // A value taken from the head is skipped, like a popped one.
+        assert_eq!(stack.pop_where(|_| true).map(|e| *e), Some(1));
+        assert!(stack.compare_and_pop(&0));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_compare_and_pop_sync() {
+        let stack = Stack::new();
+        (0..1000).for_each(|i| stack.push(i));
+
+        let claimed = thread::scope(|s| {
+            let claimers = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..1000)
+                            .rev()
+                            .filter(|i| stack.compare_and_pop(i))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            claimers
+                .into_iter()
+                .flat_map(|c| c.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut values = claimed.into_iter().chain(stack).collect::<Vec<_>>();
+        values.sort();
+
+        // Every value was claimed at most once.
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "tags")]
+    #[test]
+    fn test_pop_matching() {
+        let stack = Stack::new();
+
+        stack.push_tagged(0, 1);
+        stack.push_tagged(1, 2);
+        stack.push(2);
+        stack.push_tagged(3, 2);
+
+        assert!(stack.pop_matching(4).is_none());
+        assert_eq!(stack.pop_matching(1).map(|e| *e), Some(0));
+        assert_eq!(stack.pop_matching(2).map(|e| *e), Some(3));
+        assert_eq!(stack.pop_matching_by(|tag| tag == 0).map(|e| *e), Some(2));
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[cfg(feature = "tags")]
+    #[test]
+    fn test_pop_matching_skips_taken() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        for i in 0..4 {
+            stack.push_tagged(
+                CountOnDrop {
+                    val: i,
+                    counter: counter.clone(),
+                },
+                i % 2,
+            );
+        }
+
+        let taken = stack.pop_matching(1).unwrap();
+
+        assert_eq!(taken.val, 3);
+        assert_eq!(stack.peek().unwrap().val, 2);
+        assert_eq!(stack.pop_matching(1).unwrap().val, 1);
+        assert_eq!(stack.pop().unwrap().val, 2);
+        assert_eq!(stack.pop().unwrap().val, 0);
+        assert!(stack.pop().is_none());
+
+        drop(taken);
+        drop(stack);
+        UNIQUE_FAMILY.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+    }
+
+    #[cfg(feature = "tags")]
+    #[test]
+    fn test_pop_matching_sync() {
+        let stack = Stack::new();
+        let popped = [AtomicUsize::new(0), AtomicUsize::new(0)];
+
+        thread::scope(|s| {
+            for i in 0..6 {
+                let (stack, popped) = (&stack, &popped);
+                s.spawn(move || {
+                    let class = i % 2;
+
+                    for j in 0..500 {
+                        stack.push_tagged(class, 1 << (j % 2));
+
+                        if let Some(e) = stack.pop_matching(1 << class) {
+                            popped[*e].fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        let remaining = stack.into_iter().collect::<Vec<_>>();
+
+        assert_eq!(
+            popped
+                .iter()
+                .map(|p| p.load(Ordering::Relaxed))
+                .sum::<usize>()
+                + remaining.len(),
+            3000
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "age")]
+    fn test_older_than() {
+        use core::time::Duration;
+
+        let stack = Stack::new();
+
+        (0..3).for_each(|i| stack.push(i));
+        thread::sleep(Duration::from_millis(200));
+        (3..5).for_each(|i| stack.push(i));
+
+        // The fresh values stay younger than the cutoff for a tenth of a second after the sleep.
+        let cutoff = Duration::from_millis(100);
+        let popped = stack.pop_older_than(cutoff).unwrap();
+
+        assert_eq!(*popped, 2);
+        assert!(stack.peek().unwrap().age() < popped.age());
+
+        drop(popped);
+
+        assert_eq!(stack.discard_older_than(cutoff), 2);
+        assert!(stack.pop_older_than(cutoff).is_none());
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4, 3]);
+    }
+
+    #[test]
+    fn test_pop_by_key() {
+        let stack = Stack::new();
+
+        ["pear", "fig", "apple", "kiwi", "plum"]
+            .into_iter()
+            .for_each(|s| stack.push(s));
+
+        assert_eq!(stack.pop_min_by_key(|s| s.len()).map(|e| *e), Some("fig"));
+        assert_eq!(stack.pop_max_by_key(|s| s.len()).map(|e| *e), Some("apple"));
+        assert_eq!(stack.pop_min_by_key(|s| s.len()).map(|e| *e), Some("plum"));
+        assert_eq!(stack.pop_max_by_key(|s| *s).map(|e| *e), Some("pear"));
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec!["kiwi"]);
+
+        assert!(Stack::<i32>::new().pop_min_by_key(|v| *v).is_none());
+    }
+
+    #[test]
+    fn test_pop_by_key_sync() {
+        let stack = Stack::new();
+
+        (0..200).for_each(|i| stack.push(i));
+
+        let popped = thread::scope(|s| {
+            let threads = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        core::iter::from_fn(|| stack.pop_min_by_key(|v| *v).map(|e| *e))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        for values in &popped {
+            assert!(values.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        let mut all = popped.concat();
+        all.sort();
+
+        assert_eq!(all, (0..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_transfer_n() {
+        let a = Stack::new();
+        let b = Stack::new();
+
+        (0..6).for_each(|i| a.push(i));
+        b.push(10);
+
+        let taken = a.pop_where(|_| true).unwrap();
+
+        assert_eq!(a.transfer_n(&b, 0), 0);
+        assert_eq!(a.transfer_n(&a, 2), 0);
+        assert_eq!(a.transfer_n(&b, 3), 3);
+        assert_eq!(*taken, 5);
+        drop(taken);
+        assert_eq!(a.nodes.load(Ordering::Relaxed), 2);
+        assert_eq!(b.nodes.load(Ordering::Relaxed), 4);
+        assert_eq!(b.into_iter().collect::<Vec<_>>(), vec![4, 3, 2, 10]);
+        assert_eq!(a.into_iter().collect::<Vec<_>>(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_transfer_n_sync() {
+        let a = Stack::new();
+        let b = Stack::new();
+
+        (0..1000).for_each(|i| a.push(i));
+
+        let popped = thread::scope(|s| {
+            s.spawn(|| while a.transfer_n(&b, 7) > 0 {});
+            s.spawn(|| while b.transfer_n(&a, 5) > 0 {});
+
+            let popper = s.spawn(|| {
+                (0..200)
+                    .filter_map(|_| a.pop().or_else(|| b.pop()).map(|e| *e))
+                    .collect::<Vec<_>>()
+            });
+
+            popper.join().unwrap()
+        });
+
+        let mut values = popped.into_iter().chain(a).chain(b).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pop_into_slice() {
+        let stack = Stack::new();
+
+        (0..6).for_each(|i| stack.push(i));
+
+        let taken = stack.pop_where(|v| *v == 4).unwrap();
+        let mut dst = [0; 4];
+
+        assert_eq!(stack.pop_into_slice(&mut []), 0);
+        assert_eq!(stack.pop_into_slice(&mut dst), 3);
+        assert_eq!(dst[..3], [5, 3, 2]);
+
+        drop(taken);
+
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 2);
+        assert_eq!(stack.pop_into_slice(&mut dst), 2);
+        assert_eq!(dst[..2], [1, 0]);
+        assert_eq!(stack.pop_into_slice(&mut dst), 0);
+    }
+
+    #[test]
+    fn test_pop_into_slice_sync() {
+        let stack = Stack::new();
+
+        let popped = thread::scope(|s| {
+            let pusher = s.spawn(|| (0..1000).for_each(|i| stack.push(i)));
+
+            let poppers = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut popped = Vec::new();
+                        let mut dst = [0; 16];
+
+                        for _ in 0..200 {
+                            let n = stack.pop_into_slice(&mut dst);
+                            popped.extend_from_slice(&dst[..n]);
+                        }
+
+                        popped
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            pusher.join().unwrap();
+
+            poppers
+                .into_iter()
+                .flat_map(|p| p.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut values = popped.into_iter().chain(stack).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drain() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        (0..4).for_each(|i| {
+            let val = CountOnDrop {
+                val: i,
+                counter: counter.clone(),
+            };
+
+            stack.push(val);
+        });
+
+        let taken = stack.pop_where(|c| c.val == 2).unwrap();
+        let mut drain = stack.drain();
+
+        assert!(stack.is_empty());
+
+        let top = drain.next().unwrap();
+
+        assert_eq!(drain.next().map(|e| e.val), Some(1));
+
+        // The values not yielded are dropped along with the iterator, and the yielded ones once
+        // their entries are.
+        drop(drain);
+        stack.domain.eager_reclaim();
+
+        assert_eq!(top.val, 3);
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        drop((top, taken));
+        stack.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+        assert!(stack.drain().next().is_none());
+    }
+
+    #[test]
+    fn test_drain_sync() {
+        let stack = Stack::new();
+
+        let drained = thread::scope(|s| {
+            let pusher = s.spawn(|| (0..1000).for_each(|i| stack.push(i)));
+
+            let drainers = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..100)
+                            .flat_map(|_| stack.drain().map(|e| *e).collect::<Vec<_>>())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            pusher.join().unwrap();
+
+            drainers
+                .into_iter()
+                .flat_map(|d| d.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut values = drained.into_iter().chain(stack).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_clear() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        (0..4).for_each(|i| {
+            stack.push(CountOnDrop {
+                val: i,
+                counter: counter.clone(),
+            })
+        });
+
+        let top = stack.peek().unwrap();
+
+        stack.clear();
+        stack.domain.eager_reclaim();
+
+        assert!(stack.is_empty());
+        assert_eq!(stack.len(), 0);
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 0);
+
+        // The peeked value outlives the clear, the others are dropped.
+        assert_eq!(top.val, 3);
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+
+        drop(top);
+        stack.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+
+        stack.clear();
+        stack.push(CountOnDrop {
+            val: 4,
+            counter: counter.clone(),
+        });
+
+        assert_eq!(stack.len(), 1);
+    }
+
+    #[test]
+    fn test_pop_all_cloned() {
+        let stack = Stack::new();
+        (0..4).for_each(|i| stack.push(vec![i]));
+
+        assert_eq!(stack.pop_where(|v| v[0] == 2).map(|e| e[0]), Some(2));
+
+        let peeked = stack.peek().unwrap();
+
+        assert_eq!(stack.pop_all_cloned(), vec![vec![3], vec![1], vec![0]]);
+        assert_eq!(*peeked, vec![3]);
+        assert!(stack.is_empty());
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_pop_n() {
+        let stack = Stack::new();
+        (0..6).for_each(|i| stack.push(i));
+
+        let taken = stack.pop_where(|v| *v == 4).unwrap();
+
+        assert!(stack.pop_n(0).is_empty());
+
+        // The taken value's node is detached along with the others, and counts toward `n`.
+        assert_eq!(stack.pop_n(3), vec![5, 3]);
+
+        drop(taken);
+
+        assert_eq!(stack.pop_n(4), vec![2, 1, 0]);
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_take_all() {
+        let stack = Stack::new();
+        (0..4).for_each(|i| stack.push(i));
+
+        assert_eq!(stack.pop_where(|v| *v == 1).map(|e| *e), Some(1));
+
+        let peeked = stack.peek().unwrap();
+        let taken = stack.take_all();
+
+        assert!(stack.is_empty());
+        assert_eq!(*peeked, 3);
+        assert_eq!(taken.pop_where(|v| *v == 2).map(|e| *e), Some(2));
+        assert_eq!(taken.into_iter().collect::<Vec<_>>(), vec![3, 0]);
+        assert!(stack.take_all().is_empty());
+    }
+
+    #[test]
+    fn test_sorted_insert() {
+        let stack = Stack::new();
+        let by_key = |a: &(usize, char), b: &(usize, char)| a.0.cmp(&b.0);
+
+        for val in [(2, 'a'), (0, 'b'), (2, 'c'), (1, 'd')] {
+            stack.sorted_insert(val, by_key);
+        }
+
+        assert_eq!(stack.pop().map(|e| *e), Some((0, 'b')));
+
+        // A value taken below the head is skipped.
+        assert_eq!(stack.pop_where(|_| true).map(|e| *e), Some((1, 'd')));
+
+        stack.sorted_insert((3, 'e'), by_key);
+        stack.sorted_insert((0, 'f'), by_key);
+
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            vec![(0, 'f'), (2, 'a'), (2, 'c'), (3, 'e')]
+        );
+    }
+
+    #[test]
+    fn test_sorted_insert_sync() {
+        let stack = Stack::new();
+
+        let popped = thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..250 {
+                        stack.sorted_insert((i * 97) % 250 * 4 + t, |a, b| a.cmp(b));
+                    }
+                });
+            }
+
+            // Unlinks nodes at the head while values are inserted below them.
+            let popper = s.spawn(|| {
+                let mut popped = Vec::new();
+                let mut dst = [0; 4];
+
+                for i in 0..400 {
+                    if i % 2 == 0 {
+                        popped.extend(stack.pop().map(|e| *e));
+                    } else {
+                        let n = stack.pop_into_slice(&mut dst);
+                        popped.extend_from_slice(&dst[..n]);
+                    }
+                }
+
+                popped
+            });
+
+            popper.join().unwrap()
+        });
+
+        let rest = stack.into_iter().collect::<Vec<_>>();
+
+        assert!(rest.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let mut values = popped.into_iter().chain(rest).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_push_unique() {
+        let stack = Stack::new();
+
+        assert!(stack.push_unique(vec![0]));
+        assert!(stack.push_unique(vec![1]));
+        assert!(!stack.push_unique(vec![0]));
+
+        // A taken value no longer counts.
+        assert_eq!(stack.pop_where(|_| true).map(|e| e[0]), Some(1));
+        drop(stack.extract_if(|v| v[0] == 0));
+
+        assert!(stack.push_unique(vec![0]));
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![vec![0]]);
+    }
+
+    #[test]
+    fn test_push_unique_sync() {
+        let stack = Stack::new();
+
+        let pushed = thread::scope(|s| {
+            let pushers = (0..4)
+                .map(|_| s.spawn(|| (0..100).filter(|&i| stack.push_unique(i)).count()))
+                .collect::<Vec<_>>();
+
+            pushers
+                .into_iter()
+                .map(|p| p.join().unwrap())
+                .sum::<usize>()
+        });
+
+        let mut values = stack.into_iter().collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(pushed, 100);
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_visit() {
+        let stack = Stack::new();
+        (0..6).for_each(|i| stack.push(i));
+
+        // A value taken below the head is skipped.
+        assert_eq!(stack.pop_where(|v| v % 2 == 1).map(|e| *e), Some(5));
+
+        let mut seen = Vec::new();
+        let flow = stack.visit(|v| {
+            seen.push(*v);
+            ControlFlow::Continue(())
+        });
+
+        assert!(flow.is_continue());
+        assert_eq!(seen, vec![4, 3, 2, 1, 0]);
+
+        seen.clear();
+        let flow = stack.visit(|v| {
+            seen.push(*v);
+
+            if *v == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        assert!(flow.is_break());
+        assert_eq!(seen, vec![4, 3, 2]);
+    }
+
+    #[test]
+    fn test_visit_sync() {
+        let stack = Stack::new();
+        (0..1000).for_each(|i| stack.push(i));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1000..2000 {
+                    stack.push(i);
+                    stack.pop();
+                    stack.pop();
+                }
+            });
+
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        let mut seen = Vec::new();
+                        let _ = stack.visit(|v| {
+                            seen.push(*v);
+                            ControlFlow::Continue(())
+                        });
+
+                        let visited = seen.len();
+                        seen.sort();
+                        seen.dedup();
+
+                        assert_eq!(seen.len(), visited);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_iter() {
+        let stack = Stack::new();
+
+        assert!(stack.iter().next().is_none());
+
+        (0..6).for_each(|i| stack.push(i));
+
+        // A value taken below the head is skipped.
+        assert_eq!(stack.pop_where(|v| v % 2 == 1).map(|e| *e), Some(5));
+        assert_eq!(stack.pop_where(|v| v % 2 == 1).map(|e| *e), Some(3));
+
+        let mut iter = stack.iter();
+        let top = iter.next().unwrap();
+
+        // The rest of the walk goes on from the popped value's node.
+        assert_eq!(stack.pop().map(|e| *e), Some(4));
+        assert_eq!(*top, 4);
+        assert_eq!(iter.map(|e| *e).collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert_eq!((&stack).into_iter().count(), 3);
+    }
+
+    #[test]
+    fn test_iter_sync() {
+        let stack = Stack::new();
+        (0..1000).for_each(|i| stack.push(i));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1000..2000 {
+                    stack.push(i);
+                    stack.pop();
+                    stack.pop();
+                }
+            });
+
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        let mut seen = stack.iter().map(|e| *e).collect::<Vec<_>>();
+
+                        let yielded = seen.len();
+                        seen.sort();
+                        seen.dedup();
+
+                        assert_eq!(seen.len(), yielded);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_eq_ignoring_order() {
+        let a = crate::stack![0, 1, 1, 2];
+        let b = crate::stack![1, 2, 0, 1];
+
+        assert!(a.eq_ignoring_order(&b));
+        assert!(a.eq_ignoring_order(&a));
+
+        // Taken values are not counted.
+        assert_eq!(b.extract_if(|v| *v == 0).pop().map(|e| *e), Some(0));
+
+        assert!(!a.eq_ignoring_order(&b));
+
+        b.push(1);
+
+        assert!(!a.eq_ignoring_order(&b));
+        assert!(Stack::<i32>::new().eq_ignoring_order(&Stack::new()));
+    }
+
+    #[test]
+    fn test_extend_cloned() {
+        let source = Stack::new();
+        (0..4).for_each(|i| source.push(i));
+
+        // A value taken below the head is not cloned.
+        assert_eq!(source.pop_where(|v| *v == 2).map(|e| *e), Some(2));
+
+        let stack = Stack::new();
+        stack.push(9);
+
+        assert_eq!(stack.extend_cloned(&source), 3);
+        assert_eq!(stack.extend_cloned(&Stack::new()), 0);
+
+        // The clones keep their order, so this one is taken from below the top.
+        assert_eq!(stack.pop_where(|v| *v == 1).map(|e| *e), Some(1));
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 0, 9]);
+        assert_eq!(source.into_iter().collect::<Vec<_>>(), vec![3, 1, 0]);
+    }
+
+    #[cfg(feature = "tags")]
+    #[test]
+    fn test_clones_keep_tags() {
+        let stack = Stack::new();
+        (0..4).for_each(|i| stack.push_tagged(i, i % 2));
+
+        let extended = Stack::new();
+        extended.extend_cloned(&stack);
+
+        for clone in [stack.clone(), extended] {
+            assert_eq!(clone.pop_matching(1).map(|e| *e), Some(3));
+            assert_eq!(clone.pop_matching(1).map(|e| *e), Some(1));
+            assert_eq!(clone.into_iter().collect::<Vec<_>>(), vec![2, 0]);
+        }
+    }
+
+    #[test]
+    fn test_try_pop_n_exact() {
+        let stack = Stack::new();
+
+        (0..6).for_each(|i| stack.push(i));
+
+        let taken = stack.pop_where(|v| *v == 4).unwrap();
+
+        assert!(stack.try_pop_n_exact(0).unwrap().pop().is_none());
+        assert!(stack.try_pop_n_exact(6).is_none());
+
+        let batch = stack.try_pop_n_exact(3).unwrap();
+
+        drop(taken);
+
+        assert_eq!(batch.into_iter().collect::<Vec<_>>(), vec![5, 3, 2]);
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 2);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_try_pop_n_exact_sync() {
+        let stack = Stack::new();
+
+        (0..999).for_each(|i| stack.push(i));
+
+        let (batches, popped) = thread::scope(|s| {
+            let batchers = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        core::iter::from_fn(|| stack.try_pop_n_exact(3))
+                            .map(|batch| batch.into_iter().collect::<Vec<_>>())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let popper = s.spawn(|| {
+                (0..100)
+                    .filter_map(|_| stack.pop().map(|e| *e))
+                    .collect::<Vec<_>>()
+            });
+
+            let batches = batchers
+                .into_iter()
+                .flat_map(|b| b.join().unwrap())
+                .collect::<Vec<_>>();
+
+            (batches, popper.join().unwrap())
+        });
+
+        // Every batch holds values that were next to each other.
+        for batch in &batches {
+            assert_eq!(batch.len(), 3);
+            assert!(batch.windows(2).all(|w| w[0] == w[1] + 1));
+        }
+
+        let mut values = batches
+            .into_iter()
+            .flatten()
+            .chain(popped)
+            .chain(stack)
+            .collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..999).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_extend_from_deserializer() {
+        use serde::de::value::{Error, SeqDeserializer};
+
+        let stack = Stack::<u32>::new();
+        let values = 0..(2 * SERDE_CHUNK + 1) as u32;
+
+        let de = SeqDeserializer::<_, Error>::new(values.clone());
+
+        assert_eq!(stack.extend_from_deserializer(de), Ok(2 * SERDE_CHUNK + 1));
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 2 * SERDE_CHUNK + 1);
+        assert!(stack.into_iter().eq(values.rev()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_extend_from_deserializer_error() {
+        use serde::de::{value::Error, DeserializeSeed, Error as _, IntoDeserializer};
+        use serde::de::{SeqAccess, Visitor};
+
+        /// Yields values counting up from the first field, and fails once it reaches the second.
+        struct Truncated(u32, u32);
+
+        impl<'de> SeqAccess<'de> for Truncated {
+            type Error = Error;
+
+            fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+            where
+                T: DeserializeSeed<'de>,
+            {
+                if self.0 == self.1 {
+                    return Err(Error::custom("truncated"));
+                }
+
+                self.0 += 1;
+                seed.deserialize((self.0 - 1).into_deserializer()).map(Some)
+            }
+        }
+
+        let stack = Stack::<u32>::new();
+
+        assert!((&stack)
+            .visit_seq(Truncated(0, SERDE_CHUNK as u32 + 10))
+            .is_err());
+
+        // Only the first chunk was spliced, and the values of the second one were dropped.
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), SERDE_CHUNK);
+        assert!(stack.into_iter().eq((0..SERDE_CHUNK as u32).rev()));
+    }
+
+    #[cfg(feature = "tokens")]
+    #[test]
+    fn test_remove() {
+        let stack = Stack::new();
+
+        let bottom = stack.push_token(0);
+        let middle = stack.push_token(1);
+        let top = stack.push_token(2);
+
+        assert_eq!(stack.remove(middle).map(|e| *e), Some(1));
+
+        // The node stays linked below the top one.
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 3);
+        assert_eq!(stack.pop().map(|e| *e), Some(2));
+        assert!(stack.remove(top).is_none());
+
+        // Removing the top value unlinks the taken node below it as well.
+        assert_eq!(stack.remove(bottom).map(|e| *e), Some(0));
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(feature = "tokens")]
+    #[test]
+    fn test_token_holds_node() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        let tokens = (0..3)
+            .map(|i| {
+                stack.push_token(CountOnDrop {
+                    val: i,
+                    counter: counter.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        while stack.pop().is_some() {}
+        stack.domain.eager_reclaim();
+
+        // The popped values stay in their nodes, which the tokens hold.
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        let mut tokens = tokens.into_iter();
+
+        assert!(stack.remove(tokens.next().unwrap()).is_none());
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        drop(tokens);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[cfg(feature = "tokens")]
+    #[test]
+    #[should_panic(expected = "another stack")]
+    fn test_remove_foreign_token() {
+        let stack = Stack::new();
+        let other = Stack::new();
+
+        stack.remove(other.push_token(0));
+    }
+
+    #[cfg(feature = "tokens")]
+    #[test]
+    fn test_remove_sync() {
+        let stack = Stack::new();
+
+        let (removed, popped) = thread::scope(|s| {
+            let remover = s.spawn(|| {
+                (0..500)
+                    .map(|i| stack.push_token(i))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .filter_map(|token| stack.remove(token).map(|e| *e))
+                    .collect::<Vec<_>>()
+            });
+
+            let popper = s.spawn(|| {
+                (0..300)
+                    .filter_map(|_| stack.pop().map(|e| *e))
+                    .collect::<Vec<_>>()
+            });
+
+            (remover.join().unwrap(), popper.join().unwrap())
+        });
+
+        let mut values = removed
+            .into_iter()
+            .chain(popped)
+            .chain(stack)
+            .collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..500).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_compare_exchange_top() {
+        let stack = Stack::new();
+
+        stack.push(0);
+
+        let first = stack.peek().unwrap();
+        stack.push(1);
+
+        assert_eq!(stack.compare_exchange_top(&first, 10).err(), Some(10));
+
+        let second = stack.peek().unwrap();
+        let taken = stack.pop_where(|_| true).unwrap();
+
+        assert_eq!(stack.compare_exchange_top(&second, 11).err(), Some(11));
+
+        drop((taken, second));
+
+        let top = stack.peek().unwrap();
+        let old = stack.compare_exchange_top(&top, 12).unwrap();
+
+        assert_eq!((*first, *old), (0, 0));
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 1);
+
+        drop((first, top, old));
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![12]);
+    }
+
+    #[test]
+    fn test_compare_exchange_top_sync() {
+        let stack = Stack::new();
+
+        stack.push(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..250 {
+                        loop {
+                            let top = stack.peek().unwrap();
+
+                            if stack.compare_exchange_top(&top, *top + 1).is_ok() {
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![1000]);
+    }
+
+    #[test]
+    fn test_replace_top() {
+        let stack = Stack::new();
+
+        assert_eq!(stack.replace_top(0).err(), Some(0));
+
+        stack.push(1);
+        stack.push(2);
+
+        let peeked = stack.peek().unwrap();
+        let taken = stack.pop_where(|_| true).unwrap();
+
+        // The taken value is skipped, as a pop would skip it.
+        assert_eq!(stack.replace_top(3).map(|e| *e), Ok(1));
+        assert_eq!((*peeked, *taken), (2, 2));
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 1);
+
+        drop((peeked, taken));
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_replace_top_sync() {
+        let stack = Stack::new();
+
+        stack.push(0);
+
+        // Every replaced value is seen by exactly one thread, and the stack is never empty.
+        let mut replaced = thread::scope(|s| {
+            let threads = (0..4)
+                .map(|t| {
+                    let stack = &stack;
+                    s.spawn(move || {
+                        (1..=250)
+                            .map(|i| *stack.replace_top(t * 250 + i).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        replaced.extend(stack);
+        replaced.sort();
+
+        assert_eq!(replaced, (0..=1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pop_best_of_k() {
+        let stack = Stack::new();
+
+        [4, 8, 1, 7, 3].into_iter().for_each(|i| stack.push(i));
+
+        let taken = stack.pop_where(|_| true).unwrap();
+
+        assert!(stack.pop_best_of_k(0, |a, b| a.cmp(b)).is_none());
+        assert_eq!(stack.pop_best_of_k(1, |a, b| a.cmp(b)).map(|e| *e), Some(7));
+        assert_eq!(stack.pop_best_of_k(2, |a, b| b.cmp(a)).map(|e| *e), Some(1));
+        assert_eq!(
+            stack.pop_best_of_k(usize::MAX, |a, b| a.cmp(b)).map(|e| *e),
+            Some(8)
+        );
+
+        drop(taken);
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn test_pop_best_of_k_sync() {
+        let stack = Stack::new();
+
+        (0..1000).for_each(|i| stack.push(i));
+
+        let mut values = thread::scope(|s| {
+            let threads = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        core::iter::from_fn(|| stack.pop_best_of_k(4, |a, b| a.cmp(b)))
+                            .map(|e| *e)
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_arc() {
+        let stack = Stack::new();
+        let val = Arc::new(1);
+
+        stack.push(Arc::clone(&val));
+
+        let peeked = stack.peek_cloned_arc().unwrap();
+        let popped = stack.pop_arc().unwrap();
+
+        assert!(Arc::ptr_eq(&peeked, &val));
+        assert!(Arc::ptr_eq(&popped, &val));
+        assert!(stack.peek_cloned_arc().is_none());
+    }
+
+    #[test]
+    fn test_try_pop_weak_sync() {
+        let stack = Stack::new();
+
+        (0..1000).for_each(|i| stack.push(i));
+
+        let mut values = thread::scope(|s| {
+            let threads = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut values = Vec::new();
+
+                        loop {
+                            match stack.try_pop_weak() {
+                                Ok(Some(entry)) => values.push(*entry),
+                                Ok(None) => return values,
+                                Err(Contended) => thread::yield_now(),
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_dump_dot() {
+        let stack = Stack::new();
+
+        (0..3).for_each(|i| stack.push(i));
+
+        let taken = stack.pop_where(|_| true).unwrap();
+        let peeked = stack.peek().unwrap();
+
+        let mut dot = Vec::new();
+        stack.dump_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+
+        assert_eq!(*peeked, 1);
+        assert_eq!(dot.matches("->").count(), 2);
+        assert_eq!(dot.matches("[shape=box").count(), 2);
+        assert_eq!(dot.matches("hazard x1").count(), 1);
+        assert!(dot.contains("node0 -> node1;"));
+
+        drop((taken, peeked));
+
+        let mut dot = Vec::new();
+        Stack::<String>::new().dump_dot(&mut dot).unwrap();
+
+        assert_eq!(
+            String::from_utf8(dot).unwrap(),
+            "digraph stack {\n    head [shape=plaintext];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_if() {
+        let stack = Stack::new();
+
+        (0..10).for_each(|i| stack.push(i));
+
+        let small = stack.extract_if(|v| *v < 3);
+        let none = stack.extract_if(|v| *v > 100);
+
+        assert_eq!(small.into_iter().collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert!(none.pop().is_none());
+        assert_eq!(stack.pop().map(|e| *e), Some(9));
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            vec![8, 7, 6, 5, 4, 3]
+        );
     }
 
-    unsafe impl<V> Send for CountOnDrop<V> {}
-    unsafe impl<V> Sync for CountOnDrop<V> {}
+    #[test]
+    fn test_extract_if_sync() {
+        let stack = Stack::new();
+
+        (0..1000).for_each(|i| stack.push(i));
+
+        let (extracted, popped) = thread::scope(|s| {
+            let sweeper = s.spawn(|| {
+                (0..4)
+                    .map(|r| stack.extract_if(|v| v % 4 == r).into_iter().count())
+                    .sum::<usize>()
+            });
+
+            let popper = s.spawn(|| core::iter::from_fn(|| stack.pop()).count());
+
+            (sweeper.join().unwrap(), popper.join().unwrap())
+        });
+
+        assert_eq!(extracted + popped, 1000);
+    }
 
     #[test]
-    fn test_new_node() {
-        let node = Node::new(1);
+    fn test_stack_macro() {
+        let empty: Stack<i32> = crate::stack![];
+        let stack = crate::stack![1, 2, 3,];
+        let clones = crate::stack![String::from("a"); 3];
 
-        unsafe {
-            Node::dealloc(node);
-        }
+        assert!(empty.pop().is_none());
+        assert_eq!(stack.nodes.load(Ordering::Relaxed), 3);
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert_eq!(clones.into_iter().collect::<Vec<_>>(), vec!["a"; 3]);
     }
 
     #[test]
-    fn test_push_front() {
-        let list = Stack::new();
+    fn test_from_iter_panic() {
+        let counter = Arc::new(AtomicUsize::new(0));
 
-        list.push(1);
+        let result = std::panic::catch_unwind(|| {
+            (0..4)
+                .map(|i| {
+                    assert!(i < 3);
+                    CountOnDrop {
+                        val: i,
+                        counter: Arc::clone(&counter),
+                    }
+                })
+                .collect::<Stack<_>>()
+        });
 
-        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+        assert!(result.is_err());
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
 
-        assert_eq!(list.len(), 1);
+    #[test]
+    fn test_from_iter_order() {
+        let lifo = Stack::from_iter_lifo(0..4);
+        let fifo = Stack::from_iter_fifo(0..4);
+
+        assert_eq!(lifo.len(), 4);
+        assert_eq!(fifo.len(), 4);
+
+        fifo.push(4);
+
+        assert_eq!(lifo.into_iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+        assert_eq!(fifo.into_iter().collect::<Vec<_>>(), vec![4, 0, 1, 2, 3]);
+        assert!(Stack::<i32>::from_iter_fifo([]).pop().is_none());
     }
 
     #[test]
-    fn test_push_pop_sync() {
-        let list = Arc::new(Stack::new());
+    fn test_from_fn() {
+        let mut calls = Vec::new();
+        let stack = Stack::from_fn(4, |i| {
+            calls.push(i);
+            i.to_string()
+        });
 
-        let mut threads = vec![];
+        assert_eq!(calls, vec![0, 1, 2, 3]);
+        assert_eq!(stack.len(), 4);
+        assert_eq!(stack.pop().map(|e| e.clone()), Some("0".to_string()));
+        assert!(Stack::from_fn(0, |i| i).pop().is_none());
+    }
 
-        for i in 0..10 {
-            let list = list.clone();
+    #[test]
+    fn test_try_clone() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::named("jobs");
 
-            threads.push(std::thread::spawn(move || {
-                for _ in 0..100 {
-                    if rand::random::<u8>() % 3 != 0 {
-                        list.push(i);
-                    } else {
-                        list.pop();
-                    }
-                }
-            }))
+        for i in 0..4 {
+            let val = Arc::new(CountOnDrop {
+                val: i,
+                counter: counter.clone(),
+            });
+
+            stack.push(val);
         }
 
-        for thead in threads {
-            thead.join().unwrap();
+        let clone = stack.clone();
+
+        assert_eq!(clone.name(), Some("jobs"));
+        assert_eq!(clone.pop_where(|v| v.val == 1).map(|e| e.val), Some(1));
+
+        ALLOCS_LEFT.with(|left| left.set(2));
+        let failed = stack.try_clone();
+        ALLOCS_LEFT.with(|left| left.set(usize::MAX));
+
+        // The two clones made before the allocation failed were dropped again.
+        assert_eq!(failed.err(), Some(AllocError));
+        assert!(stack
+            .entries()
+            .iter()
+            .all(|entry| Arc::strong_count(entry) == 2));
+
+        drop((stack, clone));
+
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let val = CountOnDrop {
+            val: 7,
+            counter: counter.clone(),
+        };
+
+        let stack = Stack::repeat(vec![1, 2], 3);
+
+        assert_eq!(stack.len(), 3);
+        assert!(stack.into_iter().all(|v| v == vec![1, 2]));
+
+        drop(Stack::repeat(Arc::new(val), 0));
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_std_conversions() {
+        let deque = VecDeque::from([0, 1, 2]);
+        let stack = Stack::from(deque.clone());
+
+        assert_eq!(stack.peek().map(|e| *e), Some(2));
+        assert_eq!(VecDeque::from(stack), deque);
+
+        let stack = Stack::from(BinaryHeap::from([1, 4, 2, 3]));
+        stack.push(0);
+
+        assert_eq!(stack.pop().map(|e| *e), Some(0));
+        assert_eq!(BinaryHeap::from(stack).into_sorted_vec(), vec![1, 2, 3, 4]);
+        assert!(VecDeque::from(Stack::<u8>::new()).is_empty());
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let stack = crate::stack![0, 1, 2, 3];
+        let taken = stack.pop_where(|_| true).map(|e| *e);
+
+        let mut iter = stack.into_iter();
+
+        assert_eq!(taken, Some(3));
+        assert_eq!(iter.next(), Some(2));
+
+        let rest = iter.into_inner();
+
+        assert_eq!(rest.nodes.load(Ordering::Relaxed), 2);
+
+        rest.push(4);
+
+        assert_eq!(rest.into_iter().collect::<Vec<_>>(), vec![4, 1, 0]);
+    }
+
+    #[test]
+    fn test_into_iter_rev() {
+        let stack = crate::stack![0, 1, 2, 3, 4, 5];
+
+        // A value taken below the head is skipped from either end.
+        assert_eq!(stack.extract_if(|v| *v == 2).pop().map(|e| *e), Some(2));
+
+        let mut iter = stack.into_iter();
+
+        assert_eq!(iter.next_back(), Some(0));
+        assert_eq!(iter.next_back(), Some(1));
+        assert_eq!(iter.next(), Some(5));
+
+        let rest = iter.into_inner();
+
+        assert_eq!(rest.into_iter().rev().collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_dispose_parallel() {
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        for len in [0, 10, MIN_SEGMENT * 3 + 1] {
+            let stack = (0..len)
+                .map(|val| CountOnDrop {
+                    val,
+                    counter: counter.clone(),
+                })
+                .collect::<Stack<_>>();
+
+            let counted = |val| CountOnDrop {
+                val,
+                counter: counter.clone(),
+            };
+
+            // Leaves a taken node behind the head.
+            stack.push(counted(len));
+            stack.push(counted(len + 1));
+            drop(stack.pop_where(|c| c.val == len));
+
+            stack.dispose_parallel(4);
         }
 
-        Arc::try_unwrap(list)
-            .unwrap()
+        assert_eq!(counter.load(Ordering::SeqCst), MIN_SEGMENT * 3 + 17);
+    }
+
+    #[test]
+    fn test_split_to_shards() {
+        let stack = (0..7).collect::<Stack<_>>();
+
+        stack.push(7);
+        stack.push(8);
+        drop(stack.pop_where(|v| *v == 7));
+
+        let shards = stack.split_to_shards(3);
+
+        assert_eq!(
+            shards.iter().map(|s| s.len()).collect::<Vec<_>>(),
+            [3, 3, 2]
+        );
+
+        let shards = shards
             .into_iter()
-            .for_each(|e| println!("{}", e));
+            .map(|s| s.into_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(shards, [vec![8, 4, 1], vec![6, 3, 0], vec![5, 2]]);
     }
 
     #[test]
-    fn test_append() {
-        let expected = vec![2, 3, 7, 2, 0, 0, 3, 4, 2, 5];
+    fn test_reverse() {
+        let mut stack = (0..4).collect::<Stack<_>>();
 
-        let stack = Stack::new();
+        stack.push(4);
+        stack.push(5);
+        drop(stack.pop_where(|v| *v == 4));
 
-        expected[expected.len() / 2..]
-            .iter()
-            .rev()
-            .for_each(|&e| stack.push(e));
+        stack.reverse();
 
-        let other = Stack::new();
+        assert_eq!(*stack.nodes.get_mut(), 5);
+        assert_eq!(stack.pop().map(|e| *e), Some(0));
 
-        expected[..expected.len() / 2]
-            .iter()
-            .rev()
-            .for_each(|&e| other.push(e));
+        stack.push(6);
 
-        stack.append(other);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![6, 1, 2, 3, 5]);
 
-        let actual: Vec<i32> = stack.into_iter().map(|e| e).collect();
+        let mut empty = Stack::<i32>::new();
+        empty.reverse();
 
-        assert_eq!(expected, actual);
+        assert!(empty.pop().is_none());
     }
 
     #[test]
-    fn test_hazard() {
+    fn test_global_stack() {
+        global_stack!(static SHARED: Stack<usize>);
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                s.spawn(move || (0..100).for_each(|i| SHARED.push(t * 100 + i)));
+            }
+        });
+
+        let mut values = core::iter::from_fn(|| SHARED.pop().map(|e| *e)).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..400).collect::<Vec<_>>());
+        assert_eq!(SHARED.name(), Some("SHARED"));
+    }
+
+    #[test]
+    fn test_compact() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut stack = Stack::new();
+
+        (0..7).for_each(|i| {
+            let val = CountOnDrop {
+                val: i,
+                counter: counter.clone(),
+            };
+
+            stack.push(val);
+        });
+
+        // Takes the odd values, all of them below the head.
+        while stack.pop_where(|c| c.val % 2 == 1).is_some() {}
+
+        assert_eq!(*stack.nodes.get_mut(), 7);
+        assert_eq!(stack.compact(), 3);
+        assert_eq!(stack.compact(), 0);
+        assert_eq!(*stack.nodes.get_mut(), 4);
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+
+        let values = stack.into_iter().map(|c| c.val).collect::<Vec<_>>();
+
+        assert_eq!(values, vec![6, 4, 2, 0]);
+        assert_eq!(counter.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn test_compact_sync() {
+        use std::sync::atomic::AtomicBool;
+
         let stack = Stack::new();
+        (0..1000).for_each(|i| stack.push(i));
+
+        let done = AtomicBool::new(false);
+        let evens = (0..1000).rev().filter(|i| i % 2 == 0).collect::<Vec<_>>();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                while stack.pop_where(|v| v % 2 == 1).is_some() {}
+                done.store(true, Ordering::SeqCst);
+            });
+
+            s.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    stack.compact();
+                }
+            });
+
+            // Walks that stand on a node being purged must neither skip nor repeat values.
+            s.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    let mut visited = Vec::new();
+                    let _ = stack.visit(|&i| {
+                        visited.push(i);
+                        ControlFlow::Continue(())
+                    });
+
+                    let yielded = stack.iter().map(|e| *e).collect::<Vec<_>>();
+
+                    for values in [visited, yielded] {
+                        let values = values
+                            .into_iter()
+                            .filter(|i| i % 2 == 0)
+                            .collect::<Vec<_>>();
+                        assert_eq!(values, evens);
+                    }
+                }
+            });
+        });
+
+        stack.compact();
+
+        assert_eq!(stack.nodes.load(Ordering::SeqCst), 500);
+        assert_eq!(stack.iter().map(|e| *e).collect::<Vec<_>>(), evens);
+    }
+
+    #[test]
+    fn test_pop_all_into() {
         let counter = Arc::new(AtomicUsize::new(0));
+        let mut stack = Stack::new();
 
-        stack.append(
-            vec![
-                CountOnDrop {
-                    counter: counter.clone(),
-                    val: 0,
-                },
-                CountOnDrop {
-                    counter: counter.clone(),
-                    val: 2,
-                },
-                CountOnDrop {
-                    counter: counter.clone(),
-                    val: 3,
-                },
-            ]
-            .into_iter()
-            .fold(Stack::new(), |stack, e| {
-                stack.push(e);
-                stack
-            }),
+        (0..4).for_each(|i| {
+            let val = CountOnDrop {
+                val: i,
+                counter: counter.clone(),
+            };
+
+            stack.push(val);
+        });
+        drop(stack.pop_where(|c| c.val == 1));
+
+        let mut popped = Vec::new();
+        stack.pop_all_into(&mut popped);
+
+        assert_eq!(
+            popped.iter().map(|c| c.val).collect::<Vec<_>>(),
+            vec![3, 2, 0]
         );
+        assert_eq!(*stack.nodes.get_mut(), 0);
+        assert!(stack.pop().is_none());
 
-        let top = stack.peek().unwrap();
+        // Only the taken value was dropped, and none of the popped ones twice.
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
 
-        let owned = stack.pop().unwrap();
+        drop((popped, stack));
 
-        assert_eq!(top.val, owned.val);
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+    }
 
-        drop(owned);
+    #[test]
+    fn test_drain_grouped_by() {
+        let mut stack = Stack::new();
+        (0..10).for_each(|i| stack.push(i));
 
-        assert_eq!(counter.load(Ordering::SeqCst), 0);
+        // A taken value is left out.
+        assert_eq!(stack.pop_where(|v| v % 3 == 1).map(|e| *e), Some(7));
 
-        assert!(top.val != -1);
+        let groups = stack.drain_grouped_by(|i| i % 3);
 
-        drop(top);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&0], vec![9, 6, 3, 0]);
+        assert_eq!(groups[&1], vec![4, 1]);
+        assert_eq!(groups[&2], vec![8, 5, 2]);
+        assert!(stack.drain_grouped_by(|i| *i).is_empty());
+    }
 
-        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    #[test]
+    fn test_rotate_left() {
+        let mut stack = (0..4).collect::<Stack<_>>();
 
-        stack.domain.eager_reclaim();
+        stack.push(4);
+        stack.push(5);
+        drop(stack.pop_where(|v| *v == 4));
 
-        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        // Wraps around the five values left.
+        stack.rotate_left(6);
 
-        stack.pop();
+        assert_eq!(*stack.nodes.get_mut(), 5);
 
-        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        stack.rotate_left(0);
+        stack.rotate_left(5);
 
-        stack.pop();
+        assert_eq!(stack.pop().map(|e| *e), Some(3));
 
-        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        stack.rotate_left(3);
 
-        stack.push(CountOnDrop {
-            val: 0,
-            counter: counter.clone(),
-        });
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![5, 2, 1, 0]);
 
-        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        let mut empty = Stack::<i32>::new();
+        empty.rotate_left(1);
 
-        stack.pop();
+        assert!(empty.pop().is_none());
+    }
 
-        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "ABA on node")]
+    fn test_generation_reused() {
+        let stack = Stack::new();
+        stack.push(0);
+
+        let head = stack.protect_head().unwrap();
+
+        // As if the node had been freed and its memory handed to a new one.
+        (*head).generation.fetch_add(1, Ordering::SeqCst);
+        head.check_generation();
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_assert_quiescent() {
+        let stack = Stack::named("quiescent");
+        (0..3).for_each(|i| stack.push(i));
+
+        let peeked = stack.peek();
+
+        let result = std::panic::catch_unwind(|| stack.assert_quiescent());
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+
+        assert!(message.starts_with("Stack { name: \"quiescent\" } is not quiescent: 1 of"));
+
+        drop(peeked);
+    }
+
+    #[test]
+    fn test_approx_bytes() {
+        let node = core::mem::size_of::<Node<u64>>();
+        let stack = Stack::new();
+        let base = stack.approx_bytes();
+
+        (0..10).for_each(|i| stack.push(i as u64));
+
+        assert_eq!(stack.approx_bytes(), base + 10 * node);
+
+        stack.append((0..5).collect());
+
+        assert_eq!(stack.approx_bytes(), base + 15 * node);
+
+        let top = stack.pop().unwrap();
+
+        // The popped node is held, so it is retired but not reclaimed. Other tests retire and
+        // reclaim nodes of their own meanwhile, but it stays counted.
+        assert_eq!(stack.approx_bytes(), base + 14 * node);
+        assert!(retired_bytes() >= node);
+
+        drop(top);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    // Declaring the stack before the value is the point: it is dropped after the value.
+    #[allow(clippy::needless_late_init)]
+    fn test_may_dangle() {
+        let stack;
+        let val = String::from("dangling");
+
+        stack = Stack::new();
+        stack.push(&val);
+
+        assert_eq!(**stack.peek().unwrap(), "dangling");
     }
 }
@@ -0,0 +1,785 @@
+//! A bounded stack that either rejects pushes or evicts its oldest elements once it is full.
+//!
+//! Every node records its `index`, one above the node it was pushed onto, and a `floor` below
+//! which all nodes underneath it are evicted, so the live elements are exactly those with
+//! `floor < index <= head.index`. Eviction only raises the floor of the newly pushed node; the
+//! pusher then cuts the evicted tail off and retires it.
+//!
+//! Since the tail can be cut while elements are popped, pop claims the head by marking the head
+//! pointer, flags the node as popped, and only then swings the head past it, carrying the floor
+//! down. A node that is not flagged as popped is therefore still linked, which is what makes the
+//! walk to the cut point safe.
+//!
+//! A [weighted](Stack::weighted) stack bounds the total weight of its elements instead of their
+//! number. Every node also records the `total` weight up to and including itself, and since such
+//! a stack only ever rejects pushes, its floor stays at zero and the head's total is exact.
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use crate::base::{UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+use crate::TryPushError;
+
+/// What to do with a push onto a full [Stack](Stack).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Drop the oldest element to make room.
+    Evict,
+    /// Hand the value back to the caller.
+    Reject,
+}
+
+struct Node<V> {
+    val: V,
+    next: AtomicPtr<Self>,
+    index: usize,
+    total: usize,
+    floor: AtomicUsize,
+    popped: AtomicBool,
+}
+
+unsafe impl<V: Send> Send for Node<V> {}
+
+impl<V> Node<V> {
+    fn new(val: V) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            val,
+            next: AtomicPtr::new(null_mut()),
+            index: 0,
+            total: 0,
+            floor: AtomicUsize::new(0),
+            popped: AtomicBool::new(false),
+        }))
+    }
+}
+
+fn is_marked<T>(ptr: *mut T) -> bool {
+    ptr as usize & 1 == 1
+}
+
+fn marked<T>(ptr: *mut T) -> *mut T {
+    (ptr as usize | 1) as *mut T
+}
+
+fn unmarked<T>(ptr: *mut T) -> *mut T {
+    (ptr as usize & !1) as *mut T
+}
+
+#[repr(transparent)]
+struct DropNode<V>(NonNull<Node<V>>);
+
+impl<V> Drop for DropNode<V> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.0.as_ptr())) }
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<V> crate::hazard::Pointer<Node<V>> for DropNode<V> {
+    unsafe fn from_raw(ptr: *mut Node<V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+/// Measures the weight of an element of a [weighted](Stack::weighted) stack.
+type Weigher<V> = Box<dyn Fn(&V) -> usize + Send + Sync>;
+
+pub struct Stack<V> {
+    head: AtomicPtr<Node<V>>,
+    domain: &'static Domain<UniqueFamily>,
+    capacity: usize,
+    policy: Policy,
+    weigher: Option<Weigher<V>>,
+}
+
+unsafe impl<V: Send + Sync> Send for Stack<V> {}
+unsafe impl<V: Send + Sync> Sync for Stack<V> {}
+
+impl<V> core::fmt::Debug for Stack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stack")
+            .field("capacity", &self.capacity)
+            .field("policy", &self.policy)
+            .field("weighted", &self.weigher.is_some())
+            .finish()
+    }
+}
+
+impl<V> Stack<V> {
+    /// Creates a stack holding at most `capacity` elements.
+    ///
+    /// # Panics: If `capacity` is zero.
+    pub fn new(capacity: usize, policy: Policy) -> Self {
+        assert!(capacity > 0, "a bounded stack needs room for at least one element");
+
+        Stack {
+            head: AtomicPtr::new(null_mut()),
+            domain: &UNIQUE_FAMILY,
+            capacity,
+            policy,
+            weigher: None,
+        }
+    }
+
+    /// Creates a stack whose elements weigh at most `budget` in total, as measured by `weigher`,
+    /// rather than one holding a fixed number of elements. Pushes that would exceed the budget
+    /// are always rejected.
+    ///
+    /// # Panics: If `budget` is zero.
+    /// ```
+    /// use unlink::bounded::Stack;
+    ///
+    /// let stack = Stack::weighted(8, |msg: &Vec<u8>| msg.len());
+    ///
+    /// assert!(stack.push(vec![0; 6]).is_ok());
+    /// assert!(stack.push(vec![0; 4]).is_err());
+    /// assert!(stack.push(vec![0; 2]).is_ok());
+    ///
+    /// assert_eq!(stack.len(), 2);
+    /// assert_eq!(stack.weight(), 8);
+    /// ```
+    pub fn weighted<F>(budget: usize, weigher: F) -> Self
+    where
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        let mut stack = Self::new(budget, Policy::Reject);
+        stack.weigher = Some(Box::new(weigher));
+        stack
+    }
+
+    /// The maximum number of elements, or the weight budget of a [weighted](Stack::weighted)
+    /// stack.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    /// Protects the node the head points to and returns the head, including its mark.
+    fn protect_head(&self, hazard: &mut Hazard<'_>) -> *mut Node<V> {
+        let mut head = self.head.load(Ordering::Acquire);
+
+        loop {
+            hazard.protect_raw(unmarked(head));
+
+            let now = self.head.load(Ordering::Acquire);
+
+            if now == head {
+                return head;
+            }
+
+            head = now;
+        }
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    /// Pushes `val`. When the stack is full, the oldest element is evicted under
+    /// [Policy::Evict](Policy::Evict) and `val` is handed back under
+    /// [Policy::Reject](Policy::Reject).
+    pub fn push(&self, val: V) -> Result<(), TryPushError<V>> {
+        self.push_with(val, self.policy)
+    }
+
+    /// Pushes `val` unless the stack is full, in which case it is handed back whatever the
+    /// stack's [Policy](Policy).
+    pub fn try_push(&self, val: V) -> Result<(), TryPushError<V>> {
+        self.push_with(val, Policy::Reject)
+    }
+
+    fn push_with(&self, val: V, policy: Policy) -> Result<(), TryPushError<V>> {
+        let weight = self.weigher.as_ref().map_or(1, |weigher| weigher(&val));
+        let node_ptr = Node::new(val);
+        let mut node_hazard = Hazard::new(self.domain);
+        node_hazard.protect_raw(node_ptr);
+
+        let mut hazard = Hazard::new(self.domain);
+
+        let evicted = loop {
+            let head = self.protect_head(&mut hazard);
+
+            if is_marked(head) {
+                unsafe { self.swing(unmarked(head)) };
+                continue;
+            }
+
+            let (index, total, mut floor) = match unsafe { head.as_ref() } {
+                Some(head) => (
+                    head.index + 1,
+                    head.total.saturating_add(weight),
+                    head.floor.load(Ordering::Acquire),
+                ),
+                None => (1, weight, 0),
+            };
+
+            let evicted = match self.weigher {
+                Some(_) => total > self.capacity,
+                None => index - floor > self.capacity,
+            };
+
+            if evicted {
+                if policy == Policy::Reject || self.weigher.is_some() {
+                    // # Safety: The node was never published.
+                    let node = unsafe { Box::from_raw(node_ptr) };
+                    return Err(TryPushError::Full(node.val));
+                }
+
+                floor = index - self.capacity;
+            }
+
+            unsafe {
+                (*node_ptr).index = index;
+                (*node_ptr).total = total;
+                (*node_ptr).floor.store(floor, Ordering::Relaxed);
+                (*node_ptr).next.store(head, Ordering::Relaxed);
+            }
+
+            if self
+                .head
+                .compare_exchange(head, node_ptr, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                break evicted;
+            }
+        };
+
+        if evicted {
+            unsafe { self.cut(node_ptr) };
+        }
+
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<Entry<'_, V>> {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.protect_head(&mut hazard);
+
+            if head.is_null() {
+                return None;
+            }
+
+            if is_marked(head) {
+                unsafe { self.swing(unmarked(head)) };
+                continue;
+            }
+
+            if self
+                .head
+                .compare_exchange(head, marked(head), Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+
+            unsafe {
+                self.swing(head);
+                self.cut_evicted_below(head);
+
+                domain::retire::<_, DropNode<_>>(self.domain, head);
+                self.domain.eager_reclaim();
+
+                return Some(Entry {
+                    node: NonNull::new_unchecked(head),
+                    _hazard: hazard,
+                });
+            }
+        }
+    }
+
+    pub fn peek(&self) -> Option<Entry<'_, V>> {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.protect_head(&mut hazard);
+
+            if is_marked(head) {
+                unsafe { self.swing(unmarked(head)) };
+                continue;
+            }
+
+            return NonNull::new(head).map(|node| Entry {
+                node,
+                _hazard: hazard,
+            });
+        }
+    }
+
+    /// The number of live elements. Unlike [Stack::len](crate::Stack::len) this is exact, as
+    /// it is derived from the head node alone.
+    pub fn len(&self) -> usize {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.protect_head(&mut hazard);
+
+            if is_marked(head) {
+                unsafe { self.swing(unmarked(head)) };
+                continue;
+            }
+
+            return match unsafe { head.as_ref() } {
+                Some(head) => head.index - head.floor.load(Ordering::Acquire),
+                None => 0,
+            };
+        }
+    }
+
+    /// The total weight of the live elements of a [weighted](Stack::weighted) stack, or their
+    /// number for any other stack.
+    pub fn weight(&self) -> usize {
+        if self.weigher.is_none() {
+            return self.len();
+        }
+
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.protect_head(&mut hazard);
+
+            if is_marked(head) {
+                unsafe { self.swing(unmarked(head)) };
+                continue;
+            }
+
+            return unsafe { head.as_ref() }.map_or(0, |head| head.total);
+        }
+    }
+
+    /// Completes the pop of the claimed node `node`, moving the head to the node below it or to
+    /// null if that node has been evicted.
+    ///
+    /// # Safety: `node` must be protected and the head must have been observed as `marked(node)`.
+    unsafe fn swing(&self, node: *mut Node<V>) {
+        let mut hazard = Hazard::new(self.domain);
+
+        let target = loop {
+            let next = (*node).next.load(Ordering::Acquire);
+
+            if next.is_null() {
+                break next;
+            }
+
+            hazard.protect_raw(next);
+
+            if (*node).next.load(Ordering::Acquire) != next {
+                continue;
+            }
+
+            let floor = (*node).floor.load(Ordering::Acquire);
+
+            if (*next).index <= floor {
+                break null_mut();
+            }
+
+            (*next).floor.fetch_max(floor, Ordering::AcqRel);
+
+            break next;
+        };
+
+        (*node).popped.store(true, Ordering::Release);
+
+        let _ = self.head.compare_exchange(
+            marked(node),
+            target,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Retires the evicted nodes hanging below the popped node `node`, unless a pusher already
+    /// cut them off.
+    ///
+    /// # Safety: `node` must be protected and already unlinked from the head.
+    unsafe fn cut_evicted_below(&self, node: *mut Node<V>) {
+        let mut hazard = Hazard::new(self.domain);
+
+        let next = (*node).next.load(Ordering::Acquire);
+
+        if next.is_null() {
+            return;
+        }
+
+        hazard.protect_raw(next);
+
+        if (*node).next.load(Ordering::Acquire) != next
+            || (*next).index > (*node).floor.load(Ordering::Acquire)
+        {
+            return;
+        }
+
+        if (*node)
+            .next
+            .compare_exchange(next, null_mut(), Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.retire_chain(next);
+        }
+    }
+
+    /// Walks down from the freshly pushed `node` to the lowest live node and cuts off the
+    /// evicted nodes below it. Gives up if a node on the way has been popped, in which case the
+    /// evicted nodes are cut off by whoever pops the lowest live node.
+    ///
+    /// # Safety: `node` must be protected.
+    unsafe fn cut(&self, node: *mut Node<V>) {
+        let mut hazard = Hazard::new(self.domain);
+        let mut next_hazard = Hazard::new(self.domain);
+
+        let floor = (*node).floor.load(Ordering::Acquire);
+        let mut curr = node;
+
+        while (*curr).index > floor + 1 {
+            let next = (*curr).next.load(Ordering::Acquire);
+
+            if next.is_null() {
+                return;
+            }
+
+            next_hazard.protect_raw(next);
+
+            // While `curr` is not popped, `next` is still linked below it and cannot be retired.
+            if (*curr).popped.load(Ordering::Acquire) || (*curr).next.load(Ordering::Acquire) != next
+            {
+                return;
+            }
+
+            core::mem::swap(&mut hazard, &mut next_hazard);
+            curr = next;
+        }
+
+        let evicted = (*curr).next.load(Ordering::Acquire);
+
+        if !evicted.is_null()
+            && (*curr)
+                .next
+                .compare_exchange(evicted, null_mut(), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.retire_chain(evicted);
+        }
+    }
+
+    /// Retires a detached chain of evicted nodes. Each link is swapped out before its node is
+    /// retired, so a concurrent cutter working further down the same chain can never retire a
+    /// node twice.
+    unsafe fn retire_chain(&self, mut curr: *mut Node<V>) {
+        while !curr.is_null() {
+            let next = (*curr).next.swap(null_mut(), Ordering::AcqRel);
+            domain::retire::<_, DropNode<_>>(self.domain, curr);
+            curr = next;
+        }
+
+        self.domain.eager_reclaim();
+    }
+}
+
+impl<V> crate::ConcurrentStack<V> for Stack<V>
+where
+    V: Send + Sync,
+{
+    type Entry<'a> = Entry<'a, V> where Self: 'a;
+
+    fn push(&self, val: V) -> Result<(), V> {
+        Stack::push(self, val).map_err(TryPushError::into_inner)
+    }
+
+    fn pop(&self) -> Option<Self::Entry<'_>> {
+        Stack::pop(self)
+    }
+
+    fn peek(&self) -> Option<Self::Entry<'_>> {
+        Stack::peek(self)
+    }
+
+    /// Pushes the elements of `other` from the bottom up, so the usual [Policy](Policy) applies
+    /// to each of them. Elements rejected by a full stack are dropped.
+    fn append(&self, other: Self) {
+        let values = other.into_iter().collect::<Vec<_>>();
+
+        for val in values.into_iter().rev() {
+            let _ = Stack::push(self, val);
+        }
+    }
+
+    fn len(&self) -> usize {
+        Stack::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        Stack::is_empty(self)
+    }
+}
+
+impl<V> Drop for Stack<V> {
+    fn drop(&mut self) {
+        self.domain.eager_reclaim();
+
+        let mut curr = unmarked(self.head.load(Ordering::Acquire));
+
+        // # Safety: We have exclusive ownership of self, and every node still reachable from the
+        // head, evicted or not, has not been retired.
+        unsafe {
+            while !curr.is_null() {
+                let node = Box::from_raw(curr);
+                curr = node.next.load(Ordering::Acquire);
+            }
+        }
+    }
+}
+
+/// [Entry](Entry) is a protected reference to a value of a bounded [Stack](Stack). It stays
+/// valid until it is dropped.
+pub struct Entry<'a, V> {
+    node: NonNull<Node<V>>,
+    _hazard: Hazard<'a>,
+}
+
+impl<'a, V> core::ops::Deref for Entry<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.node.as_ref().val }
+    }
+}
+
+/// [IntoIter](IntoIter) yields the live elements of a bounded [Stack](Stack) from the top down.
+pub struct IntoIter<V> {
+    stack: Stack<V>,
+    remaining: usize,
+}
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        // # Safety: We have exclusive ownership of the stack, and the live elements have not been
+        // retired. Any evicted nodes below them are left linked for the stack's drop to free.
+        unsafe {
+            let head = unmarked(*self.stack.head.get_mut());
+            let Node { val, next, .. } = *Box::from_raw(head);
+
+            *self.stack.head.get_mut() = next.into_inner();
+
+            Some(val)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<V> IntoIterator for Stack<V> {
+    type Item = V;
+    type IntoIter = IntoIter<V>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.domain.eager_reclaim();
+
+        let remaining = match unsafe { unmarked(*self.head.get_mut()).as_ref() } {
+            Some(head) => head.index - head.floor.load(Ordering::Acquire),
+            None => 0,
+        };
+
+        IntoIter {
+            stack: self,
+            remaining,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::Arc;
+
+    fn drain(stack: &Stack<i32>) -> Vec<i32> {
+        core::iter::from_fn(|| stack.pop().map(|e| *e)).collect()
+    }
+
+    #[test]
+    fn test_evict() {
+        let stack = Stack::new(3, Policy::Evict);
+
+        (0..10).for_each(|i| stack.push(i).unwrap());
+
+        assert_eq!(stack.len(), 3);
+        assert_eq!(drain(&stack), [9, 8, 7]);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_evicted_stay_evicted() {
+        let stack = Stack::new(3, Policy::Evict);
+
+        (0..5).for_each(|i| stack.push(i).unwrap());
+
+        assert_eq!(*stack.pop().unwrap(), 4);
+
+        stack.push(5).unwrap();
+
+        assert_eq!(stack.len(), 3);
+        assert_eq!(drain(&stack), [5, 3, 2]);
+    }
+
+    #[test]
+    fn test_reject() {
+        let stack = Stack::new(2, Policy::Reject);
+
+        assert!(stack.push(1).is_ok());
+        assert!(stack.push(2).is_ok());
+        assert_eq!(stack.push(3), Err(TryPushError::Full(3)));
+
+        stack.pop();
+
+        assert!(stack.push(4).is_ok());
+        assert_eq!(drain(&stack), [4, 1]);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new(4, Policy::Evict);
+
+        (0..10).for_each(|_| {
+            let _ = stack.push(DropCounter(counter.clone()));
+        });
+
+        stack.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 6);
+
+        drop(stack);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_push_pop_sync() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new(16, Policy::Evict);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                let (stack, counter) = (&stack, &counter);
+                s.spawn(move || {
+                    for i in 0..500 {
+                        if i % 3 == 0 {
+                            stack.pop();
+                        } else {
+                            let _ = stack.push(DropCounter(counter.clone()));
+                        }
+                        assert!(stack.len() <= 16);
+                    }
+                });
+            }
+        });
+
+        drop(stack);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 8 * 333);
+    }
+
+    #[test]
+    fn test_try_push() {
+        let stack = Stack::new(2, Policy::Evict);
+
+        assert!(stack.try_push(1).is_ok());
+        assert!(stack.try_push(2).is_ok());
+        assert_eq!(stack.try_push(3), Err(TryPushError::Full(3)));
+        assert!(stack.push(3).is_ok());
+        assert_eq!(drain(&stack), [3, 2]);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let stack = Stack::weighted(10, |s: &String| s.len());
+
+        assert!(stack.push("hello".into()).is_ok());
+        assert!(stack.push("hi".into()).is_ok());
+        assert_eq!(
+            stack.push("world".into()),
+            Err(TryPushError::Full("world".into()))
+        );
+        assert_eq!(
+            stack.push("a".repeat(11)).map_err(|e| e.into_inner().len()),
+            Err(11)
+        );
+        assert_eq!(stack.weight(), 7);
+
+        stack.pop();
+
+        assert_eq!(stack.weight(), 5);
+        assert!(stack.push("world".into()).is_ok());
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack.weight(), 10);
+    }
+
+    #[test]
+    fn test_weighted_sync() {
+        let stack = Stack::weighted(64, |v: &usize| *v);
+
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..500 {
+                        if i % 3 == 0 {
+                            stack.pop();
+                        } else {
+                            let _ = stack.push((t + i) % 16);
+                        }
+                        assert!(stack.weight() <= 64);
+                    }
+                });
+            }
+        });
+
+        let values = stack.into_iter().collect::<Vec<_>>();
+
+        assert!(values.iter().sum::<usize>() <= 64);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let stack = Stack::new(3, Policy::Evict);
+
+        (0..5).for_each(|i| stack.push(i).unwrap());
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), [4, 3, 2]);
+    }
+
+    #[test]
+    fn test_append() {
+        use crate::ConcurrentStack;
+
+        let stack = Stack::new(4, Policy::Reject);
+        let other = Stack::new(3, Policy::Evict);
+
+        stack.push(0).unwrap();
+        stack.push(1).unwrap();
+        (2..6).for_each(|i| other.push(i).unwrap());
+
+        ConcurrentStack::append(&stack, other);
+
+        assert_eq!(drain(&stack), [4, 3, 1, 0]);
+    }
+}
@@ -0,0 +1,160 @@
+use core::cell::UnsafeCell;
+
+use crate::Stack;
+
+/// A boxed value, which may be unsized. The cell is only ever accessed by the thread that popped
+/// its node, since [BoxedStack](BoxedStack) hands out no references to values still on the stack.
+struct Owned<T: ?Sized>(UnsafeCell<Option<Box<T>>>);
+
+// # Safety: See above, no two threads access the same cell.
+unsafe impl<T: ?Sized + Send> Sync for Owned<T> {}
+
+/// [BoxedStack](BoxedStack) is a lock free stack of boxed values, such as trait objects, that
+/// hands the boxes back when they are popped, rather than an [Entry](crate::base::Entry) to a
+/// value that other threads may still be reading. The box is popped out of its node, so the
+/// value can be consumed right away, as a boxed closure is by calling it.
+/// ```
+/// use unlink::BoxedStack;
+///
+/// let jobs = BoxedStack::<dyn FnOnce() -> u32 + Send>::new();
+///
+/// jobs.push_boxed(Box::new(|| 1));
+/// jobs.push_boxed(Box::new(move || 2));
+///
+/// assert_eq!(jobs.pop_boxed().map(|job| job()), Some(2));
+/// assert_eq!(jobs.pop_boxed().map(|job| job()), Some(1));
+/// assert!(jobs.pop_boxed().is_none());
+/// ```
+pub struct BoxedStack<T: ?Sized> {
+    inner: Stack<Owned<T>>,
+}
+
+impl<T: ?Sized + Send> Default for BoxedStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> core::fmt::Debug for BoxedStack<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoxedStack").finish()
+    }
+}
+
+impl<T: ?Sized + Send> BoxedStack<T> {
+    pub fn new() -> Self {
+        BoxedStack {
+            inner: Stack::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.peek().is_none()
+    }
+
+    /// Pushes `val`, which the call site can coerce from a box of any type implementing `T`.
+    pub fn push_boxed(&self, val: Box<T>) {
+        self.inner.push(Owned(UnsafeCell::new(Some(val))));
+    }
+
+    /// Pops the top value, moving its box out of the node.
+    pub fn pop_boxed(&self) -> Option<Box<T>> {
+        let entry = self.inner.pop()?;
+
+        // # Safety: We popped the node, so no other thread can access its cell.
+        unsafe { (*entry.0.get()).take() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    trait Job: Send {
+        fn run(self: Box<Self>) -> usize;
+    }
+
+    struct Add(usize, Arc<AtomicUsize>);
+
+    impl Job for Add {
+        fn run(self: Box<Self>) -> usize {
+            self.1.fetch_add(self.0, Ordering::SeqCst)
+        }
+    }
+
+    impl Job for DropCounter {
+        fn run(self: Box<Self>) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn test_trait_objects() {
+        let sum = Arc::new(AtomicUsize::new(0));
+        let stack = BoxedStack::<dyn Job>::new();
+
+        stack.push_boxed(Box::new(Add(1, sum.clone())));
+        stack.push_boxed(Box::new(DropCounter(Arc::new(AtomicUsize::new(0)))));
+        stack.push_boxed(Box::new(Add(2, sum.clone())));
+
+        assert_eq!(stack.len(), 3);
+
+        while let Some(job) = stack.pop_boxed() {
+            job.run();
+        }
+
+        assert_eq!(sum.load(Ordering::SeqCst), 3);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = BoxedStack::<dyn Job>::new();
+
+        (0..3).for_each(|_| stack.push_boxed(Box::new(DropCounter(counter.clone()))));
+
+        let popped = stack.pop_boxed();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        drop(popped);
+        drop(stack);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_sync() {
+        let sum = Arc::new(AtomicUsize::new(0));
+        let stack = BoxedStack::<dyn Job>::new();
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let (stack, sum) = (&stack, &sum);
+                s.spawn(move || {
+                    for i in 0..200 {
+                        stack.push_boxed(Box::new(Add(i, sum.clone())));
+
+                        if i % 2 == 0 {
+                            stack.pop_boxed().unwrap().run();
+                        }
+                    }
+                });
+            }
+        });
+
+        while let Some(job) = stack.pop_boxed() {
+            job.run();
+        }
+
+        assert_eq!(sum.load(Ordering::SeqCst), 4 * (0..200).sum::<usize>());
+    }
+}
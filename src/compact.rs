@@ -0,0 +1,644 @@
+//! A stack whose nodes live in a growable slab and are linked by 32-bit indices.
+//!
+//! The slab grows in segments of doubling size that are never moved or freed while the stack is
+//! alive, so following a stale index is always a valid (if pointless) read. The head and the
+//! slab's free list pack a 32-bit generation next to the index, which makes every compare
+//! exchange fail once the slot it observed has been recycled, ruling out ABA.
+//!
+//! A popped slot is retired through the crate's hazard domain like any other node, but instead
+//! of being deallocated it is returned to the free list once no [Entry](Entry) protects it.
+use alloc::alloc::{alloc, dealloc, handle_alloc_error};
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::base::{UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+
+const NIL: u32 = u32::MAX;
+
+/// The first segment holds `1 << BASE_SHIFT` slots, every following one twice as many as the
+/// one before.
+const BASE_SHIFT: u32 = 5;
+
+const SEGMENTS: usize = (32 - BASE_SHIFT) as usize + 1;
+
+fn pack(generation: u32, index: u32) -> u64 {
+    (generation as u64) << 32 | index as u64
+}
+
+fn unpack(word: u64) -> (u32, u32) {
+    ((word >> 32) as u32, word as u32)
+}
+
+/// Maps a slot index to its segment and its offset within that segment.
+fn locate(index: u32) -> (usize, usize) {
+    let shifted = index as u64 + (1 << BASE_SHIFT);
+    let segment = 63 - shifted.leading_zeros() - BASE_SHIFT;
+    let offset = shifted - ((1 << BASE_SHIFT) << segment);
+    (segment as usize, offset as usize)
+}
+
+struct Slot<V> {
+    val: UnsafeCell<MaybeUninit<V>>,
+    /// The index of the slot below, or of the next free one. Once the slot is popped, nothing
+    /// follows it any more, so it holds the slot's own index until [Recycle](Recycle) returns it
+    /// to the free list.
+    next: AtomicU32,
+}
+
+unsafe impl<V: Send> Send for Slot<V> {}
+
+/// Every segment starts with a pointer to its slab so that a retired slot can find its way back
+/// to the free list.
+fn segment_layout<V>(segment: usize) -> (Layout, usize) {
+    let len = (1 << BASE_SHIFT) << segment;
+    let (layout, offset) = Layout::new::<*const Slab<V>>()
+        .extend(Layout::array::<Slot<V>>(len).expect("segment size overflow"))
+        .expect("segment size overflow");
+    (layout.pad_to_align(), offset)
+}
+
+struct Slab<V> {
+    segments: [AtomicPtr<u8>; SEGMENTS],
+    free: AtomicU64,
+    fresh: AtomicU32,
+    /// One count for the owning stack and one for every retired slot not yet recycled.
+    refs: AtomicUsize,
+    _marker: PhantomData<V>,
+}
+
+impl<V> Slab<V> {
+    fn new() -> *mut Self {
+        Box::into_raw(Box::new(Slab {
+            segments: core::array::from_fn(|_| AtomicPtr::new(null_mut())),
+            free: AtomicU64::new(pack(0, NIL)),
+            fresh: AtomicU32::new(0),
+            refs: AtomicUsize::new(1),
+            _marker: PhantomData,
+        }))
+    }
+
+    /// # Safety: `index` must have been handed out by [alloc_slot](Slab::alloc_slot).
+    unsafe fn slot(&self, index: u32) -> *mut Slot<V> {
+        let (segment, offset) = locate(index);
+        let (_, slots) = segment_layout::<V>(segment);
+        let base = self.segments[segment].load(Ordering::Acquire);
+        base.add(slots).cast::<Slot<V>>().add(offset)
+    }
+
+    fn alloc_slot(&self) -> u32 {
+        let mut free = self.free.load(Ordering::Acquire);
+
+        loop {
+            let (generation, index) = unpack(free);
+
+            if index == NIL {
+                break;
+            }
+
+            let next = unsafe { (*self.slot(index)).next.load(Ordering::Acquire) };
+
+            match self.free.compare_exchange(
+                free,
+                pack(generation.wrapping_add(1), next),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return index,
+                Err(now) => free = now,
+            }
+        }
+
+        let index = self.fresh.fetch_add(1, Ordering::Relaxed);
+        assert!(index != NIL, "compact stack exhausted its index space");
+
+        self.ensure_segment(locate(index).0);
+
+        index
+    }
+
+    fn ensure_segment(&self, segment: usize) {
+        if !self.segments[segment].load(Ordering::Acquire).is_null() {
+            return;
+        }
+
+        let (layout, slots) = segment_layout::<V>(segment);
+
+        unsafe {
+            let base = alloc(layout);
+
+            if base.is_null() {
+                handle_alloc_error(layout);
+            }
+
+            base.cast::<*const Self>().write(self);
+
+            for offset in 0..(1 << BASE_SHIFT) << segment {
+                base.add(slots).cast::<Slot<V>>().add(offset).write(Slot {
+                    val: UnsafeCell::new(MaybeUninit::uninit()),
+                    next: AtomicU32::new(NIL),
+                });
+            }
+
+            if self.segments[segment]
+                .compare_exchange(null_mut(), base, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                dealloc(base, layout);
+            }
+        }
+    }
+
+    /// # Safety: The slot must be unreachable and its value already moved out or dropped.
+    unsafe fn free_slot(&self, index: u32) {
+        let slot = self.slot(index);
+        let mut free = self.free.load(Ordering::Acquire);
+
+        loop {
+            let (generation, next) = unpack(free);
+
+            (*slot).next.store(next, Ordering::Release);
+
+            match self.free.compare_exchange(
+                free,
+                pack(generation.wrapping_add(1), index),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(now) => free = now,
+            }
+        }
+    }
+
+    /// Gives up one count on `raw`, freeing the segments once the last one is gone. Values still
+    /// stored in the slab must have been dropped by then.
+    unsafe fn release(raw: *mut Self) {
+        if (*raw).refs.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+
+        let slab = Box::from_raw(raw);
+
+        for (segment, base) in slab.segments.iter().enumerate() {
+            let base = base.load(Ordering::Acquire);
+
+            if !base.is_null() {
+                dealloc(base, segment_layout::<V>(segment).0);
+            }
+        }
+    }
+}
+
+/// [Recycle](Recycle) drops the value of a reclaimed slot and returns the slot to its slab.
+#[repr(transparent)]
+struct Recycle<V>(NonNull<Slot<V>>);
+
+impl<V> Drop for Recycle<V> {
+    fn drop(&mut self) {
+        unsafe {
+            let slot = self.0.as_ptr();
+            let index = (*slot).next.load(Ordering::Acquire);
+            let (segment, offset) = locate(index);
+            let (_, slots) = segment_layout::<V>(segment);
+
+            let base = slot.sub(offset).cast::<u8>().sub(slots);
+            let slab = *base.cast::<*mut Slab<V>>();
+
+            (*(*slot).val.get()).assume_init_drop();
+            (*slab).free_slot(index);
+            Slab::release(slab);
+        }
+
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<V> crate::hazard::Pointer<Slot<V>> for Recycle<V> {
+    unsafe fn from_raw(ptr: *mut Slot<V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+pub struct Stack<V> {
+    head: AtomicU64,
+    slab: *mut Slab<V>,
+    domain: &'static Domain<UniqueFamily>,
+    len: AtomicUsize,
+}
+
+unsafe impl<V: Send + Sync> Send for Stack<V> {}
+unsafe impl<V: Send + Sync> Sync for Stack<V> {}
+
+impl<V> core::fmt::Debug for Stack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stack").finish()
+    }
+}
+
+impl<V> Default for Stack<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Stack<V> {
+    pub fn new() -> Self {
+        Stack {
+            head: AtomicU64::new(pack(0, NIL)),
+            slab: Slab::new(),
+            domain: &UNIQUE_FAMILY,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let len = self.len.load(Ordering::Relaxed);
+        if len > isize::MAX as usize {
+            0
+        } else {
+            len
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unpack(self.head.load(Ordering::Acquire)).1 == NIL
+    }
+
+    fn slab(&self) -> &Slab<V> {
+        unsafe { &*self.slab }
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    pub fn push(&self, val: V) {
+        let index = self.slab().alloc_slot();
+
+        unsafe {
+            let slot = self.slab().slot(index);
+            (*(*slot).val.get()).write(val);
+            self.link(index, index);
+        }
+
+        self.len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Links the chain running from `first` to `last` onto the head.
+    ///
+    /// # Safety: The chain must be private to the caller.
+    unsafe fn link(&self, first: u32, last: u32) {
+        let last = self.slab().slot(last);
+        let mut head = self.head.load(Ordering::Acquire);
+
+        loop {
+            let (generation, index) = unpack(head);
+
+            (*last).next.store(index, Ordering::Release);
+
+            match self.head.compare_exchange(
+                head,
+                pack(generation.wrapping_add(1), first),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(now) => head = now,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<Entry<'_, V>> {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let (generation, index) = unpack(head);
+
+            if index == NIL {
+                return None;
+            }
+
+            let slot = unsafe { self.slab().slot(index) };
+
+            hazard.protect_raw(slot);
+
+            if self.head.load(Ordering::Acquire) != head {
+                continue;
+            }
+
+            let next = unsafe { (*slot).next.load(Ordering::Acquire) };
+
+            if self
+                .head
+                .compare_exchange(
+                    head,
+                    pack(generation.wrapping_add(1), next),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                continue;
+            }
+
+            self.len.fetch_sub(1, Ordering::Relaxed);
+
+            unsafe {
+                // A thread that read the old successor fails to swap the head, as its generation
+                // changed.
+                (*slot).next.store(index, Ordering::Release);
+
+                self.slab().refs.fetch_add(1, Ordering::Relaxed);
+                domain::retire::<_, Recycle<_>>(self.domain, slot);
+                self.domain.eager_reclaim();
+
+                return Some(Entry {
+                    slot: NonNull::new_unchecked(slot),
+                    _hazard: hazard,
+                });
+            }
+        }
+    }
+
+    pub fn peek(&self) -> Option<Entry<'_, V>> {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let (_, index) = unpack(head);
+
+            if index == NIL {
+                return None;
+            }
+
+            let slot = unsafe { self.slab().slot(index) };
+
+            hazard.protect_raw(slot);
+
+            if self.head.load(Ordering::Acquire) == head {
+                return Some(Entry {
+                    slot: unsafe { NonNull::new_unchecked(slot) },
+                    _hazard: hazard,
+                });
+            }
+        }
+    }
+
+    /// Moves the contents of `other` on top of `self`, keeping their order. Since the two stacks
+    /// index different slabs, the values are copied into slots of `self` first and then linked
+    /// with a single compare exchange.
+    pub fn append(&self, other: Self) {
+        let mut values = other.into_iter();
+
+        let Some(val) = values.next() else {
+            return;
+        };
+
+        let first = self.slab().alloc_slot();
+        let mut last = first;
+        let mut len = 1;
+
+        unsafe {
+            (*(*self.slab().slot(first)).val.get()).write(val);
+
+            for val in values {
+                let index = self.slab().alloc_slot();
+                let slot = self.slab().slot(index);
+
+                (*(*slot).val.get()).write(val);
+                (*self.slab().slot(last)).next.store(index, Ordering::Relaxed);
+
+                last = index;
+                len += 1;
+            }
+
+            self.link(first, last);
+        }
+
+        self.len.fetch_add(len, Ordering::Relaxed);
+    }
+}
+
+impl<V> crate::ConcurrentStack<V> for Stack<V>
+where
+    V: Send + Sync,
+{
+    type Entry<'a> = Entry<'a, V> where Self: 'a;
+
+    fn push(&self, val: V) -> Result<(), V> {
+        Stack::push(self, val);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Self::Entry<'_>> {
+        Stack::pop(self)
+    }
+
+    fn peek(&self) -> Option<Self::Entry<'_>> {
+        Stack::peek(self)
+    }
+
+    fn append(&self, other: Self) {
+        Stack::append(self, other)
+    }
+
+    fn len(&self) -> usize {
+        Stack::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        Stack::is_empty(self)
+    }
+}
+
+impl<V> Drop for Stack<V> {
+    fn drop(&mut self) {
+        // Recycle slots that are no longer referred to.
+        self.domain.eager_reclaim();
+
+        let (_, mut index) = unpack(*self.head.get_mut());
+
+        // # Safety: We have exclusive ownership of self.
+        unsafe {
+            while index != NIL {
+                let slot = self.slab().slot(index);
+                (*(*slot).val.get()).assume_init_drop();
+                index = (*slot).next.load(Ordering::Acquire);
+            }
+
+            Slab::release(self.slab);
+        }
+    }
+}
+
+/// [Entry](Entry) is a protected reference to the value in a slot of a compact
+/// [Stack](Stack). The slot is not recycled before it is dropped.
+pub struct Entry<'a, V> {
+    slot: NonNull<Slot<V>>,
+    _hazard: Hazard<'a>,
+}
+
+impl<'a, V> core::ops::Deref for Entry<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        unsafe { (*self.slot.as_ref().val.get()).assume_init_ref() }
+    }
+}
+
+pub struct IntoIter<V> {
+    stack: Stack<V>,
+}
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (generation, index) = unpack(*self.stack.head.get_mut());
+
+        if index == NIL {
+            return None;
+        }
+
+        unsafe {
+            let slot = self.stack.slab().slot(index);
+            let val = (*(*slot).val.get()).assume_init_read();
+            let next = (*slot).next.load(Ordering::Acquire);
+
+            *self.stack.head.get_mut() = pack(generation.wrapping_add(1), next);
+            self.stack.slab().free_slot(index);
+
+            Some(val)
+        }
+    }
+}
+
+impl<V> IntoIterator for Stack<V> {
+    type Item = V;
+    type IntoIter = IntoIter<V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { stack: self }
+    }
+}
+
+impl<V> FromIterator<V> for Stack<V>
+where
+    V: Send + Sync,
+{
+    fn from_iter<T: IntoIterator<Item = V>>(iter: T) -> Self {
+        let stack = Stack::new();
+        for val in iter {
+            stack.push(val);
+        }
+
+        stack
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(31), (0, 31));
+        assert_eq!(locate(32), (1, 0));
+        assert_eq!(locate(95), (1, 63));
+        assert_eq!(locate(96), (2, 0));
+        assert_eq!(locate(NIL - 1).0, SEGMENTS - 1);
+    }
+
+    #[test]
+    fn test_slot_size() {
+        // A slot adds nothing to its value but a 32-bit link.
+        assert_eq!(core::mem::size_of::<Slot<u32>>(), 8);
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let stack = Stack::new();
+
+        (0..100).for_each(|i| stack.push(i));
+
+        assert_eq!(stack.len(), 100);
+        assert_eq!(*stack.peek().unwrap(), 99);
+        assert_eq!(*stack.pop().unwrap(), 99);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), (0..99).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_slot_reuse() {
+        let stack = Stack::new();
+
+        for i in 0..1000 {
+            stack.push(i);
+            stack.pop();
+        }
+
+        stack.domain.eager_reclaim();
+
+        assert!(stack.slab().fresh.load(Ordering::SeqCst) < 1000);
+    }
+
+    #[test]
+    fn test_append() {
+        let stack: Stack<i32> = vec![3, 4].into_iter().collect();
+        let other: Stack<i32> = vec![1, 2].into_iter().collect();
+
+        stack.append(other);
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), [2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn test_drop() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        (0..10).for_each(|_| stack.push(DropCounter(counter.clone())));
+
+        let held = stack.pop().unwrap();
+        stack.pop();
+        stack.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        drop(held);
+        drop(stack);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_push_pop_sync() {
+        let stack = Stack::new();
+
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        if i % 3 == 0 {
+                            stack.pop();
+                        } else {
+                            stack.push(t);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(stack.into_iter().all(|v| v < 8));
+    }
+}
@@ -0,0 +1,246 @@
+//! Bookkeeping around the hazard pointer domain shared by all collections of this crate.
+//!
+//! Every hazard pointer is taken out through [Hazard](Hazard) and every node is retired through
+//! [retire](retire), so that with the `stats` feature the domain can be inspected through
+//! [DomainStats](DomainStats). Without the feature the bookkeeping compiles down to nothing.
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use core::sync::atomic::AtomicPtr;
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::base::UniqueFamily;
+use crate::hazard::{Domain, HazardPointer, Pointer};
+
+#[cfg(feature = "stats")]
+static HAZARDS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static PROTECTED: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "stats")]
+static RETIRED: AtomicUsize = AtomicUsize::new(0);
+
+/// [DomainStats](DomainStats) is a point in time view of the hazard pointer domain.
+///
+/// A stalled reclamation shows up as a `retired_backlog` that keeps growing while `protected`
+/// stays above zero, typically because an `Entry` is being held on to.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DomainStats {
+    /// Hazard pointers currently held, whether protecting a node or not.
+    pub hazard_pointers: usize,
+    /// Hazard pointer slots allocated, whether held or free. Slots are reused but never freed, so
+    /// this is at least the most hazard pointers held at once, or the slots reserved through
+    /// [reserve_hazard_slots](crate::reserve_hazard_slots) if more.
+    pub hazard_slots: usize,
+    /// Hazard pointers currently protecting a node.
+    pub protected: usize,
+    /// Nodes that were retired but not yet reclaimed.
+    pub retired_backlog: usize,
+}
+
+#[cfg(feature = "stats")]
+impl DomainStats {
+    /// Reads the current state of the domain. The fields are read one after another while other
+    /// threads go on, so they need not add up exactly, but `hazard_slots` is read after
+    /// `hazard_pointers` and is never below it.
+    pub fn current() -> Self {
+        let hazard_pointers = HAZARDS.load(Ordering::Acquire);
+
+        DomainStats {
+            hazard_pointers,
+            hazard_slots: crate::base::UNIQUE_FAMILY.slots(),
+            protected: PROTECTED.load(Ordering::Relaxed),
+            retired_backlog: RETIRED.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// [Hazard](Hazard) is a hazard pointer in the shared domain that keeps track of whether it is
+/// currently protecting a node.
+pub(crate) struct Hazard<'d> {
+    inner: HazardPointer<'d, UniqueFamily>,
+    #[cfg(feature = "stats")]
+    protecting: bool,
+}
+
+impl<'d> Hazard<'d> {
+    pub(crate) fn new(domain: &'d Domain<UniqueFamily>) -> Self {
+        let inner = HazardPointer::new_in_domain(domain);
+
+        // Counted once its slot is allocated, so that the slots never fall behind the count.
+        #[cfg(feature = "stats")]
+        HAZARDS.fetch_add(1, Ordering::Release);
+
+        Hazard {
+            inner,
+            #[cfg(feature = "stats")]
+            protecting: false,
+        }
+    }
+
+    pub(crate) fn protect_ptr<'l, T>(
+        &'l mut self,
+        src: &AtomicPtr<T>,
+    ) -> Option<(NonNull<T>, PhantomData<&'l T>)> {
+        #[cfg(feature = "stats")]
+        track(&mut self.protecting, true);
+
+        let protected = self.inner.protect_ptr(src);
+
+        #[cfg(feature = "stats")]
+        if protected.is_none() {
+            track(&mut self.protecting, false);
+        }
+
+        protected
+    }
+
+    pub(crate) fn protect_raw<T>(&mut self, ptr: *mut T) {
+        #[cfg(feature = "stats")]
+        track(&mut self.protecting, !ptr.is_null());
+
+        self.inner.protect_raw(ptr);
+    }
+
+    pub(crate) fn reset_protection(&mut self) {
+        #[cfg(feature = "stats")]
+        track(&mut self.protecting, false);
+
+        self.inner.reset_protection();
+    }
+}
+
+/// Counts a hazard pointer that was `protecting` as now `protecting` a node or not.
+#[cfg(feature = "stats")]
+fn track(protecting: &mut bool, now: bool) {
+    match (*protecting, now) {
+        (false, true) => PROTECTED.fetch_add(1, Ordering::Relaxed),
+        (true, false) => PROTECTED.fetch_sub(1, Ordering::Relaxed),
+        _ => 0,
+    };
+
+    *protecting = now;
+}
+
+#[cfg(feature = "stats")]
+impl<'d> Drop for Hazard<'d> {
+    fn drop(&mut self) {
+        track(&mut self.protecting, false);
+        HAZARDS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Allocates hazard pointer slots in the domain shared by all collections, until there are at
+/// least `n` of them, and returns the number allocated. Operations claim a free slot for each
+/// hazard pointer they hold, and only allocate one when all slots are in use, so reserving as
+/// many slots as there will be threads up front keeps the first operations on each thread from
+/// paying for the allocation.
+/// ```
+/// let threads = 8;
+///
+/// unlink::reserve_hazard_slots(threads * 4);
+///
+/// assert_eq!(unlink::reserve_hazard_slots(threads * 4), 0);
+/// ```
+pub fn reserve_hazard_slots(n: usize) -> usize {
+    crate::base::UNIQUE_FAMILY.reserve(n)
+}
+
+/// Reclaims every node retired by any collection, waiting for entries that still protect some of
+/// them to be dropped, and returns the number reclaimed. Retired nodes are otherwise reclaimed in
+/// batches as operations go, and whatever is left when the process exits is never dropped, so
+/// test harnesses and long running processes can call this to run the destructors of popped
+/// values at a point of their choosing.
+///
+/// This returns once nothing retired is left, so it waits for as long as any thread holds on to
+/// an entry, including the calling one, or keeps popping values.
+/// ```
+/// use unlink::Stack;
+///
+/// let stack = Stack::new();
+/// stack.push(String::from("popped"));
+///
+/// drop(stack.pop());
+///
+/// // The popped value was dropped, rather than left for a later pass.
+/// unlink::reclaim_all();
+/// ```
+pub fn reclaim_all() -> usize {
+    crate::base::UNIQUE_FAMILY.reclaim_all()
+}
+
+/// Retires `ptr` into `domain`, to be dropped as a `P` once it is no longer protected. `P` must
+/// call [reclaimed](reclaimed) when it is dropped.
+///
+/// # Safety: As for [Domain::retire_ptr](Domain::retire_ptr).
+pub(crate) unsafe fn retire<T, P>(domain: &Domain<UniqueFamily>, ptr: *mut T)
+where
+    T: Send,
+    P: Pointer<T>,
+{
+    #[cfg(feature = "stats")]
+    RETIRED.fetch_add(1, Ordering::Relaxed);
+
+    domain.retire_ptr::<T, P>(ptr);
+}
+
+/// Accounts for a retired node having been reclaimed.
+#[inline(always)]
+pub(crate) fn reclaimed() {
+    #[cfg(feature = "stats")]
+    RETIRED.fetch_sub(1, Ordering::Relaxed);
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod test {
+    use super::*;
+    use crate::Stack;
+
+    #[test]
+    fn test_held_entry() {
+        let stack = Stack::new();
+
+        stack.push(1);
+
+        let entry = stack.pop().unwrap();
+        let stats = DomainStats::current();
+
+        // Other tests share the domain, so only lower bounds hold.
+        assert!(stats.hazard_pointers >= 1);
+        assert!(stats.hazard_slots >= stats.hazard_pointers);
+        assert!(stats.protected >= 1);
+        assert!(stats.retired_backlog >= 1);
+
+        drop(entry);
+    }
+
+    #[test]
+    fn test_hazard_slots() {
+        let slots = DomainStats::current().hazard_slots;
+
+        // Every slot counted was allocated, so none of them needs to be reserved again.
+        assert_eq!(crate::reserve_hazard_slots(slots), 0);
+
+        crate::reserve_hazard_slots(slots + 2);
+        assert!(DomainStats::current().hazard_slots >= slots + 2);
+    }
+
+    #[test]
+    fn test_protection() {
+        let node = AtomicPtr::new(Box::into_raw(Box::new(0)));
+        let mut hazard = Hazard::new(&crate::base::UNIQUE_FAMILY);
+
+        assert!(!hazard.protecting);
+
+        hazard.protect_ptr(&node);
+        assert!(hazard.protecting);
+
+        hazard.reset_protection();
+        assert!(!hazard.protecting);
+
+        hazard.protect_raw(core::ptr::null_mut::<i32>());
+        assert!(!hazard.protecting);
+
+        unsafe { drop(Box::from_raw(node.into_inner())) };
+    }
+}
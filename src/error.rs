@@ -0,0 +1,158 @@
+//! The errors of fallible operations. Errors of operations that take a value hand it back, so that
+//! the caller can retry, or dispose of it otherwise.
+use core::fmt;
+
+/// The error returned by [try_pop_weak](crate::Stack::try_pop_weak) when another thread changed
+/// the head first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Contended;
+
+impl fmt::Display for Contended {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the head of the stack was contended")
+    }
+}
+
+impl std::error::Error for Contended {}
+
+/// The error returned by [try_clone](crate::Stack::try_clone) when memory for a node could not
+/// be allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory allocation failed")
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// The error returned when sending a value to a collection that was closed, such as a
+/// [Mailbox](crate::Mailbox) that was dropped.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Closed<V>(pub V);
+
+impl<V> Closed<V> {
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+impl<V> fmt::Debug for Closed<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closed(..)")
+    }
+}
+
+impl<V> fmt::Display for Closed<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the collection was closed")
+    }
+}
+
+impl<V> std::error::Error for Closed<V> {}
+
+/// The error returned by a push that was refused, such as onto a full
+/// [bounded](crate::bounded::Stack) stack.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TryPushError<V> {
+    /// The collection had no room for the value.
+    Full(V),
+    /// The collection was closed.
+    Closed(V),
+}
+
+impl<V> TryPushError<V> {
+    pub fn into_inner(self) -> V {
+        match self {
+            TryPushError::Full(val) | TryPushError::Closed(val) => val,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        matches!(self, TryPushError::Full(_))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self, TryPushError::Closed(_))
+    }
+}
+
+impl<V> From<Closed<V>> for TryPushError<V> {
+    fn from(closed: Closed<V>) -> Self {
+        TryPushError::Closed(closed.0)
+    }
+}
+
+impl<V> fmt::Debug for TryPushError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryPushError::Full(_) => write!(f, "Full(..)"),
+            TryPushError::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<V> fmt::Display for TryPushError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryPushError::Full(_) => write!(f, "the collection was full"),
+            TryPushError::Closed(_) => write!(f, "the collection was closed"),
+        }
+    }
+}
+
+impl<V> std::error::Error for TryPushError<V> {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Contended {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "the head of the stack was contended");
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for AllocError {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "memory allocation failed");
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<V> defmt::Format for Closed<V> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "the collection was closed");
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<V> defmt::Format for TryPushError<V> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            TryPushError::Full(_) => defmt::write!(f, "the collection was full"),
+            TryPushError::Closed(_) => defmt::write!(f, "the collection was closed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_try_push_error() {
+        let full = TryPushError::Full(1);
+        let closed = TryPushError::from(Closed(2));
+
+        assert!(full.is_full() && !full.is_closed());
+        assert!(closed.is_closed() && !closed.is_full());
+        assert_eq!((full.into_inner(), closed.into_inner()), (1, 2));
+
+        // No bound on the value, so that any of them can be boxed as an error.
+        let boxed: Box<dyn std::error::Error> = Box::new(TryPushError::Full(vec![0u8]));
+
+        assert_eq!(boxed.to_string(), "the collection was full");
+        assert_eq!(format!("{:?}", Closed(0)), "Closed(..)");
+    }
+}
@@ -0,0 +1,75 @@
+//! Fault injection hooks for stress testing, enabled by the `fail-points` feature.
+//!
+//! The hooks sit at the critical points of the lock free algorithms, between loading the head
+//! and the compare exchange that publishes a change, and before a node is retired. With the
+//! feature enabled they randomly yield the thread or let a compare exchange fail spuriously,
+//! which makes rare interleavings common even on machines with few cores. Without the feature
+//! they compile down to nothing.
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// One in how many hook calls yields the current thread.
+#[cfg(feature = "fail-points")]
+const YIELD_ONE_IN: u32 = 4;
+
+/// One in how many compare exchanges fails spuriously.
+#[cfg(feature = "fail-points")]
+const FAIL_ONE_IN: u32 = 8;
+
+/// Returns true one in `n` times, using a per-thread xorshift generator.
+#[cfg(feature = "fail-points")]
+fn one_in(n: u32) -> bool {
+    use core::cell::Cell;
+
+    std::thread_local! {
+        static STATE: Cell<u32> = Cell::new({
+            use std::hash::{Hash, Hasher};
+
+            // Seed every thread differently from its id. The address of `STATE` would not do, as
+            // it is that of the key, which all threads share.
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            std::thread::current().id().hash(&mut hasher);
+
+            hasher.finish() as u32 | 1
+        });
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        state.set(x);
+
+        x % n == 0
+    })
+}
+
+/// Possibly yields the current thread.
+#[inline(always)]
+pub(crate) fn yield_point() {
+    #[cfg(feature = "fail-points")]
+    if one_in(YIELD_ONE_IN) {
+        std::thread::yield_now();
+    }
+}
+
+/// A strong compare exchange that may fail spuriously, reporting the current value as if
+/// another thread had changed it in the meantime. Every caller retries on failure, so an
+/// injected failure only ever costs another turn of its loop.
+#[inline(always)]
+pub(crate) fn compare_exchange<T>(
+    atomic: &AtomicPtr<T>,
+    current: *mut T,
+    new: *mut T,
+    success: Ordering,
+    failure: Ordering,
+) -> Result<*mut T, *mut T> {
+    yield_point();
+
+    #[cfg(feature = "fail-points")]
+    if one_in(FAIL_ONE_IN) {
+        return Err(atomic.load(failure));
+    }
+
+    atomic.compare_exchange(current, new, success, failure)
+}
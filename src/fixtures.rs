@@ -0,0 +1,13 @@
+//! Fixtures shared by the unit tests.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Counts how often it was dropped, to tell whether values are dropped exactly once.
+#[derive(Clone)]
+pub(crate) struct DropCounter(pub(crate) Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
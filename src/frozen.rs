@@ -0,0 +1,161 @@
+//! A read-only snapshot of a [Stack](crate::Stack) that is done being built.
+use core::ops::Index;
+
+/// [FrozenStack](FrozenStack) holds the values of a [Stack](crate::Stack) that will not be
+/// modified anymore, top first, in contiguous memory. Reads need no hazard pointers, and the
+/// values can be sorted once and then binary searched.
+/// ```
+/// use unlink::Stack;
+///
+/// let stack = Stack::new();
+/// (0..5).for_each(|i| stack.push(i * 2));
+///
+/// let mut frozen = stack.freeze();
+///
+/// assert_eq!(frozen[0], 8);
+/// assert_eq!(frozen.get(4), Some(&0));
+///
+/// frozen.sort();
+///
+/// assert_eq!(frozen.binary_search(&6), Ok(3));
+/// ```
+pub struct FrozenStack<V> {
+    values: Vec<V>,
+}
+
+impl<V> core::fmt::Debug for FrozenStack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrozenStack").finish()
+    }
+}
+
+impl<V> FrozenStack<V> {
+    pub(crate) fn new(values: Vec<V>) -> Self {
+        FrozenStack { values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The value `index` places below the top of the stack.
+    pub fn get(&self, index: usize) -> Option<&V> {
+        self.values.get(index)
+    }
+
+    /// Iterates the values from the top of the stack down.
+    pub fn iter(&self) -> core::slice::Iter<'_, V> {
+        self.values.iter()
+    }
+
+    pub fn as_slice(&self) -> &[V] {
+        &self.values
+    }
+
+    /// Sorts the values in ascending order, so that they can be binary searched.
+    pub fn sort(&mut self)
+    where
+        V: Ord,
+    {
+        self.values.sort();
+    }
+
+    pub fn sort_by_key<K, F>(&mut self, f: F)
+    where
+        F: FnMut(&V) -> K,
+        K: Ord,
+    {
+        self.values.sort_by_key(f);
+    }
+
+    /// Binary searches the values, which must be sorted, as for
+    /// [slice::binary_search](slice::binary_search).
+    pub fn binary_search(&self, val: &V) -> Result<usize, usize>
+    where
+        V: Ord,
+    {
+        self.values.binary_search(val)
+    }
+
+    pub fn binary_search_by_key<K, F>(&self, key: &K, f: F) -> Result<usize, usize>
+    where
+        F: FnMut(&V) -> K,
+        K: Ord,
+    {
+        self.values.binary_search_by_key(key, f)
+    }
+}
+
+impl<V> Index<usize> for FrozenStack<V> {
+    type Output = V;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.values[index]
+    }
+}
+
+impl<V> IntoIterator for FrozenStack<V> {
+    type Item = V;
+    type IntoIter = std::vec::IntoIter<V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a FrozenStack<V> {
+    type Item = &'a V;
+    type IntoIter = core::slice::Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Stack;
+
+    #[test]
+    fn test_freeze() {
+        let stack = Stack::new();
+        (0..4).for_each(|i| stack.push(i));
+
+        let frozen = stack.freeze();
+
+        assert_eq!(frozen.len(), 4);
+        assert_eq!(frozen.iter().copied().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+        assert_eq!(frozen[1], 2);
+        assert!(frozen.get(4).is_none());
+        assert_eq!(frozen.into_iter().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_sort_search() {
+        let stack = Stack::new();
+        ["b", "d", "a", "c"].into_iter().for_each(|s| stack.push(s));
+
+        let mut frozen = stack.freeze();
+        frozen.sort_by_key(|s| s.to_string());
+
+        assert_eq!(frozen.as_slice(), &["a", "b", "c", "d"]);
+        assert_eq!(frozen.binary_search(&"c"), Ok(2));
+        assert_eq!(frozen.binary_search(&"e"), Err(4));
+        assert_eq!(
+            frozen.binary_search_by_key(&String::from("b"), |s| s.to_string()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_freeze_empty() {
+        let frozen = Stack::<i32>::new().freeze();
+
+        assert!(frozen.is_empty());
+        assert_eq!((&frozen).into_iter().count(), 0);
+    }
+}
@@ -0,0 +1,125 @@
+use core::ops::Deref;
+use std::sync::Arc;
+
+use crate::Stack;
+
+/// [StackHandle](StackHandle) is a shared, reference counted [Stack](Stack), which derefs to it.
+/// Cloning a handle is cheap, and clones can be moved into threads, so producers and consumers
+/// can be spawned without wrapping the stack in an `Arc` first. The stack is dropped along with
+/// its last handle.
+/// ```
+/// use unlink::StackHandle;
+///
+/// let stack = StackHandle::new();
+///
+/// let producers = (0..4)
+///     .map(|t| {
+///         let stack = stack.clone();
+///         std::thread::spawn(move || (0..10).for_each(|i| stack.push(t * 10 + i)))
+///     })
+///     .collect::<Vec<_>>();
+///
+/// producers.into_iter().for_each(|p| p.join().unwrap());
+///
+/// let mut values = stack.into_inner().unwrap().into_iter().collect::<Vec<_>>();
+/// values.sort();
+///
+/// assert_eq!(values, (0..40).collect::<Vec<_>>());
+/// ```
+pub struct StackHandle<V> {
+    stack: Arc<Stack<V>>,
+}
+
+impl<V> core::fmt::Debug for StackHandle<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StackHandle").finish()
+    }
+}
+
+impl<V> Clone for StackHandle<V> {
+    fn clone(&self) -> Self {
+        StackHandle {
+            stack: Arc::clone(&self.stack),
+        }
+    }
+}
+
+impl<V: Send + Sync> Default for StackHandle<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Deref for StackHandle<V> {
+    type Target = Stack<V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stack
+    }
+}
+
+impl<V> From<Stack<V>> for StackHandle<V> {
+    fn from(stack: Stack<V>) -> Self {
+        StackHandle {
+            stack: Arc::new(stack),
+        }
+    }
+}
+
+impl<V> From<Arc<Stack<V>>> for StackHandle<V> {
+    fn from(stack: Arc<Stack<V>>) -> Self {
+        StackHandle { stack }
+    }
+}
+
+impl<V: Send + Sync> StackHandle<V> {
+    pub fn new() -> Self {
+        Stack::new().into()
+    }
+
+    /// Returns the stack if this is its last handle, so that it can be consumed.
+    pub fn into_inner(self) -> Option<Stack<V>> {
+        Arc::into_inner(self.stack)
+    }
+
+    /// Whether both handles refer to the same stack.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.stack, &other.stack)
+    }
+
+    /// Spawns consumers of the stack, as [spawn_consumers](Stack::spawn_consumers) does.
+    #[cfg(feature = "workers")]
+    pub fn spawn_consumers<F>(&self, n: usize, f: F) -> crate::workers::Consumers
+    where
+        V: 'static,
+        F: Fn(crate::base::Entry<'_, V>) + Send + Sync + 'static,
+    {
+        self.stack.spawn_consumers(n, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let stack = StackHandle::new();
+        let other = stack.clone();
+
+        assert!(stack.ptr_eq(&other));
+        assert!(!stack.ptr_eq(&StackHandle::new()));
+
+        std::thread::spawn(move || other.push(1)).join().unwrap();
+
+        assert_eq!(stack.peek().map(|e| *e), Some(1));
+
+        let clone = stack.clone();
+
+        assert!(clone.into_inner().is_none());
+        assert_eq!(
+            stack.into_inner().map(|s| s.into_iter().collect()),
+            Some(vec![1])
+        );
+    }
+}
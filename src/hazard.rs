@@ -0,0 +1,518 @@
+//! The hazard pointer domain used to reclaim the nodes of every collection in this crate.
+//!
+//! A [Domain](Domain) keeps a list of hazard slots and a list of retired pointers, both of which
+//! are lock free stacks. Slots are never freed while the domain lives, and a [HazardPointer]
+//! claims an inactive slot before allocating a new one, so the slot list only grows to the
+//! largest number of hazard pointers held at once, or to the number of slots
+//! [reserved](Domain::reserve) up front.
+//!
+//! Retiring a pointer pushes it onto the retired list. Reclamation takes the whole list, reads
+//! every slot, and drops the retired pointers that no slot protects, pushing the others back.
+//! It runs whenever [eager_reclaim](Domain::eager_reclaim) is called, and on retire once the
+//! backlog reaches [RECLAIM_THRESHOLD](RECLAIM_THRESHOLD).
+use core::marker::PhantomData;
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// The number of retired pointers at which retiring another one also runs a reclamation pass.
+const RECLAIM_THRESHOLD: usize = 1000;
+
+/// [Pointer](Pointer) is an owning pointer type that a retired raw pointer is converted back
+/// into and dropped as, once it is no longer protected.
+///
+/// # Safety
+///
+/// Dropping the result of `from_raw` must be sound for any pointer retired as `Self`.
+pub(crate) unsafe trait Pointer<T> {
+    /// # Safety: `ptr` must have been retired as a `Self`, and is reclaimed only once.
+    unsafe fn from_raw(ptr: *mut T) -> Self;
+}
+
+unsafe impl<T> Pointer<T> for Box<T> {
+    unsafe fn from_raw(ptr: *mut T) -> Self {
+        Box::from_raw(ptr)
+    }
+}
+
+struct Slot {
+    ptr: AtomicPtr<u8>,
+    active: AtomicBool,
+    next: *mut Slot,
+}
+
+struct Retired {
+    ptr: *mut u8,
+    delete: unsafe fn(*mut u8),
+    next: *mut Retired,
+}
+
+/// [Domain](Domain) tracks the hazard pointers of a family of collections and the pointers they
+/// retired. The family `F` ties hazard pointers to the domain they were taken out in.
+pub(crate) struct Domain<F> {
+    slots: AtomicPtr<Slot>,
+    retired: AtomicPtr<Retired>,
+    backlog: AtomicUsize,
+    _family: PhantomData<F>,
+}
+
+// # Safety: Slots and retired entries are only shared through atomics, and retired pointers
+// are required to be `Send`.
+unsafe impl<F> Send for Domain<F> {}
+unsafe impl<F> Sync for Domain<F> {}
+
+impl<F> Domain<F> {
+    pub(crate) const fn new(_family: &F) -> Self {
+        Domain {
+            slots: AtomicPtr::new(null_mut()),
+            retired: AtomicPtr::new(null_mut()),
+            backlog: AtomicUsize::new(0),
+            _family: PhantomData,
+        }
+    }
+
+    /// Claims an inactive slot, or adds a new one if all of them are in use.
+    fn acquire(&self) -> &Slot {
+        let mut curr = self.slots.load(Ordering::Acquire);
+
+        // # Safety: Slots are never freed while the domain is alive.
+        while let Some(slot) = unsafe { curr.as_ref() } {
+            if !slot.active.load(Ordering::Relaxed)
+                && slot
+                    .active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return slot;
+            }
+
+            curr = slot.next;
+        }
+
+        self.add_slot(true)
+    }
+
+    /// Adds inactive slots until the domain has at least `n`, so that as many hazard pointers can
+    /// be held at once before [acquire](Domain::acquire) allocates. Returns the number of slots
+    /// added.
+    pub(crate) fn reserve(&self, n: usize) -> usize {
+        let added = n.saturating_sub(self.slots());
+
+        for _ in 0..added {
+            self.add_slot(false);
+        }
+
+        added
+    }
+
+    /// Returns the number of slots allocated, whether they are in use or not.
+    pub(crate) fn slots(&self) -> usize {
+        let mut slots = 0;
+        let mut curr = self.slots.load(Ordering::Acquire);
+
+        // # Safety: Slots are never freed while the domain is alive.
+        while let Some(slot) = unsafe { curr.as_ref() } {
+            slots += 1;
+            curr = slot.next;
+        }
+
+        slots
+    }
+
+    fn add_slot(&self, active: bool) -> &Slot {
+        let slot = Box::into_raw(Box::new(Slot {
+            ptr: AtomicPtr::new(null_mut()),
+            active: AtomicBool::new(active),
+            next: null_mut(),
+        }));
+
+        let mut head = self.slots.load(Ordering::Acquire);
+
+        loop {
+            // # Safety: The slot is not published yet.
+            unsafe { (*slot).next = head };
+
+            match self
+                .slots
+                .compare_exchange(head, slot, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return unsafe { &*slot },
+                Err(now) => head = now,
+            }
+        }
+    }
+
+    /// Retires `ptr`, to be dropped as a `P` once no hazard pointer protects it. Returns the
+    /// number of pointers reclaimed if this pushed the backlog over the threshold.
+    ///
+    /// # Safety: `ptr` must no longer be reachable by threads that do not already protect it, and
+    /// must not be retired again.
+    pub(crate) unsafe fn retire_ptr<T, P>(&self, ptr: *mut T) -> usize
+    where
+        T: Send,
+        P: Pointer<T>,
+    {
+        unsafe fn delete<T, P: Pointer<T>>(ptr: *mut u8) {
+            drop(P::from_raw(ptr.cast()));
+        }
+
+        let retired = Box::into_raw(Box::new(Retired {
+            ptr: ptr.cast(),
+            delete: delete::<T, P>,
+            next: null_mut(),
+        }));
+
+        // Count the pointer before publishing it, so that a racing reclaim cannot take the
+        // backlog below zero.
+        let backlog = self.backlog.fetch_add(1, Ordering::Relaxed) + 1;
+
+        self.push_retired(retired, retired);
+
+        if backlog >= RECLAIM_THRESHOLD {
+            self.eager_reclaim()
+        } else {
+            0
+        }
+    }
+
+    /// Pushes the chain `first..=last` onto the retired list.
+    ///
+    /// # Safety: The chain must be owned by the caller.
+    unsafe fn push_retired(&self, first: *mut Retired, last: *mut Retired) {
+        let mut head = self.retired.load(Ordering::Acquire);
+
+        loop {
+            (*last).next = head;
+
+            match self
+                .retired
+                .compare_exchange(head, first, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return,
+                Err(now) => head = now,
+            }
+        }
+    }
+
+    /// The pointers currently protected by a hazard pointer, sorted, and listed once per hazard
+    /// pointer protecting them.
+    pub(crate) fn protected(&self) -> Vec<*mut u8> {
+        let mut protected = Vec::new();
+        let mut slot = self.slots.load(Ordering::Acquire);
+
+        // # Safety: Slots are never freed while the domain is alive.
+        while let Some(s) = unsafe { slot.as_ref() } {
+            let ptr = s.ptr.load(Ordering::Acquire);
+
+            if !ptr.is_null() {
+                protected.push(ptr);
+            }
+
+            slot = s.next;
+        }
+
+        protected.sort_unstable();
+        protected
+    }
+
+    /// The number of pointers retired and not yet reclaimed.
+    #[cfg(feature = "testing")]
+    pub(crate) fn backlog(&self) -> usize {
+        self.backlog.load(Ordering::Relaxed)
+    }
+
+    /// Drops every retired pointer that is not currently protected, returning how many were
+    /// dropped. Pointers retired while dropping are left for the next pass.
+    pub(crate) fn eager_reclaim(&self) -> usize {
+        let mut curr = self.retired.swap(null_mut(), Ordering::Acquire);
+
+        if curr.is_null() {
+            return 0;
+        }
+
+        // Pairs with the fence in `protect_ptr`: either the protecting thread sees the pointer
+        // unlinked, or we see its protection.
+        fence(Ordering::SeqCst);
+
+        let protected = self.protected();
+
+        let (mut kept_first, mut kept_last) = (null_mut::<Retired>(), null_mut::<Retired>());
+        let mut reclaimed = 0;
+
+        // # Safety: We took the list, so we own all of its entries.
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next;
+
+                if protected.binary_search(&(*curr).ptr).is_ok() {
+                    (*curr).next = kept_first;
+                    kept_first = curr;
+
+                    if kept_last.is_null() {
+                        kept_last = curr;
+                    }
+                } else {
+                    let retired = Box::from_raw(curr);
+                    (retired.delete)(retired.ptr);
+                    reclaimed += 1;
+                }
+
+                curr = next;
+            }
+
+            if !kept_first.is_null() {
+                self.push_retired(kept_first, kept_last);
+            }
+        }
+
+        self.backlog.fetch_sub(reclaimed, Ordering::Relaxed);
+
+        reclaimed
+    }
+
+    /// Reclaims until no retired pointer is left, waiting for the hazard pointers that still
+    /// protect some of them to be released. Returns the number of pointers reclaimed, which
+    /// includes those retired by other threads in the meantime.
+    pub(crate) fn reclaim_all(&self) -> usize {
+        let mut reclaimed = self.eager_reclaim();
+
+        while self.backlog.load(Ordering::Relaxed) > 0 {
+            crate::wait::yield_now();
+            reclaimed += self.eager_reclaim();
+        }
+
+        reclaimed
+    }
+}
+
+impl<F> Drop for Domain<F> {
+    fn drop(&mut self) {
+        // No hazard pointer can outlive the domain, so nothing is protected anymore.
+        while self.eager_reclaim() > 0 {}
+
+        let mut slot = *self.slots.get_mut();
+
+        // # Safety: We have exclusive ownership of the domain and its slots.
+        unsafe {
+            while !slot.is_null() {
+                let s = Box::from_raw(slot);
+                slot = s.next;
+            }
+        }
+    }
+}
+
+/// [HazardPointer](HazardPointer) protects at most one pointer at a time from being reclaimed
+/// by its domain.
+pub(crate) struct HazardPointer<'d, F> {
+    slot: &'d Slot,
+    _domain: PhantomData<&'d Domain<F>>,
+}
+
+impl<'d, F> HazardPointer<'d, F> {
+    pub(crate) fn new_in_domain(domain: &'d Domain<F>) -> Self {
+        HazardPointer {
+            slot: domain.acquire(),
+            _domain: PhantomData,
+        }
+    }
+
+    /// Loads `src` and protects the pointer it holds, returning it unless it is null.
+    pub(crate) fn protect_ptr<'l, T>(
+        &'l mut self,
+        src: &AtomicPtr<T>,
+    ) -> Option<(NonNull<T>, PhantomData<&'l T>)> {
+        let mut ptr = src.load(Ordering::Relaxed);
+
+        loop {
+            self.protect_raw(ptr);
+
+            let now = src.load(Ordering::Acquire);
+
+            if now == ptr {
+                return NonNull::new(ptr).map(|ptr| (ptr, PhantomData));
+            }
+
+            ptr = now;
+        }
+    }
+
+    /// Protects `ptr`. It is up to the caller to validate that `ptr` was not retired before the
+    /// protection became visible.
+    pub(crate) fn protect_raw<T>(&mut self, ptr: *mut T) {
+        self.slot.ptr.store(ptr.cast(), Ordering::Release);
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn reset_protection(&mut self) {
+        self.slot.ptr.store(null_mut(), Ordering::Release);
+    }
+}
+
+impl<'d, F> Drop for HazardPointer<'d, F> {
+    fn drop(&mut self) {
+        self.slot.ptr.store(null_mut(), Ordering::Release);
+        self.slot.active.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::Arc;
+
+    struct Family;
+
+    fn boxed(counter: &Arc<AtomicUsize>) -> *mut DropCounter {
+        Box::into_raw(Box::new(DropCounter(counter.clone())))
+    }
+
+    #[test]
+    fn test_protect() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let domain = Domain::new(&Family);
+        let src = AtomicPtr::new(boxed(&counter));
+
+        let mut hazard = HazardPointer::new_in_domain(&domain);
+        let (ptr, _) = hazard.protect_ptr(&src).unwrap();
+
+        src.store(null_mut(), Ordering::Release);
+
+        unsafe { domain.retire_ptr::<_, Box<_>>(ptr.as_ptr()) };
+
+        assert_eq!(domain.eager_reclaim(), 0);
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        hazard.reset_protection();
+
+        assert_eq!(domain.eager_reclaim(), 1);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        assert!(hazard.protect_ptr(&src).is_none());
+    }
+
+    #[test]
+    fn test_slot_reuse() {
+        let domain = Domain::<Family>::new(&Family);
+
+        let first = HazardPointer::new_in_domain(&domain);
+        let second = HazardPointer::new_in_domain(&domain);
+
+        assert!(!core::ptr::eq(first.slot, second.slot));
+
+        let slot = first.slot as *const Slot;
+        drop(first);
+
+        let third = HazardPointer::new_in_domain(&domain);
+
+        assert!(core::ptr::eq(third.slot, slot));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let domain = Domain::<Family>::new(&Family);
+
+        let held = HazardPointer::new_in_domain(&domain);
+
+        assert_eq!(domain.reserve(3), 2);
+        assert_eq!(domain.reserve(3), 0);
+
+        let slots = domain.slots.load(Ordering::Acquire);
+        let hazards = [(); 2].map(|_| HazardPointer::new_in_domain(&domain));
+
+        // Both were given reserved slots rather than new ones.
+        assert_eq!(domain.slots.load(Ordering::Acquire), slots);
+        assert!(hazards.iter().all(|h| !core::ptr::eq(h.slot, held.slot)));
+    }
+
+    #[test]
+    fn test_threshold() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let domain = Domain::new(&Family);
+
+        let reclaimed = (0..RECLAIM_THRESHOLD)
+            .map(|_| unsafe { domain.retire_ptr::<_, Box<_>>(boxed(&counter)) })
+            .sum::<usize>();
+
+        assert_eq!(reclaimed, RECLAIM_THRESHOLD);
+        assert_eq!(counter.load(Ordering::SeqCst), RECLAIM_THRESHOLD);
+    }
+
+    #[test]
+    fn test_reclaim_all() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let domain = Domain::new(&Family);
+        let src = AtomicPtr::new(boxed(&counter));
+        let protected = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut hazard = HazardPointer::new_in_domain(&domain);
+                hazard.protect_ptr(&src);
+                protected.store(true, Ordering::Release);
+
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            });
+
+            while !protected.load(Ordering::Acquire) {
+                std::thread::yield_now();
+            }
+
+            (0..10).for_each(|_| unsafe {
+                domain.retire_ptr::<_, Box<_>>(boxed(&counter));
+            });
+            unsafe { domain.retire_ptr::<_, Box<_>>(src.swap(null_mut(), Ordering::AcqRel)) };
+
+            // Waits for the other thread to release its hazard pointer.
+            assert_eq!(domain.reclaim_all(), 11);
+            assert_eq!(counter.load(Ordering::SeqCst), 11);
+        });
+
+        assert_eq!(domain.reclaim_all(), 0);
+    }
+
+    #[test]
+    fn test_drop_domain() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let domain = Domain::new(&Family);
+
+        (0..10).for_each(|_| unsafe {
+            domain.retire_ptr::<_, Box<_>>(boxed(&counter));
+        });
+
+        drop(domain);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_sync() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let domain = Domain::new(&Family);
+        let src = AtomicPtr::new(boxed(&counter));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut hazard = HazardPointer::new_in_domain(&domain);
+
+                    for _ in 0..500 {
+                        if let Some((ptr, _)) = hazard.protect_ptr(&src) {
+                            // Still valid, since it cannot be reclaimed while protected.
+                            assert!(Arc::strong_count(&unsafe { ptr.as_ref() }.0) > 1);
+                        }
+
+                        let old = src.swap(boxed(&counter), Ordering::AcqRel);
+                        unsafe { domain.retire_ptr::<_, Box<_>>(old) };
+
+                        hazard.reset_protection();
+                    }
+                });
+            }
+        });
+
+        unsafe { drop(Box::from_raw(src.into_inner())) };
+        drop(domain);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2001);
+    }
+}
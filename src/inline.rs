@@ -0,0 +1,365 @@
+//! A stack of small values, such as indices, that stores its bottom value in a link rather than
+//! in a node of its own.
+//!
+//! The head and the link of every node are words that either point to a node, or hold a value
+//! shifted left by one with the lowest bit set, which no node pointer has. A value pushed onto an
+//! empty stack is stored in the head this way, and stays in the link of the node pushed on top of
+//! it, so a stack that mostly holds one value allocates nothing, and popping that value reads it
+//! straight from the head. The other values live in nodes of just a value and a link, and are
+//! copied out as they are popped rather than handed out behind an entry.
+use core::marker::PhantomData;
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::base::{UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+
+/// [Inline](Inline) values fit in a word with its highest bit to spare, which makes room for the
+/// bit that tells them apart from node pointers.
+pub trait Inline: Copy {
+    /// The value as a word whose highest bit is clear.
+    fn into_bits(self) -> usize;
+
+    /// The value that [into_bits](Inline::into_bits) returned `bits` for.
+    fn from_bits(bits: usize) -> Self;
+}
+
+macro_rules! inline_as {
+    ($($ty:ty => $unsigned:ty),* $(,)?) => {
+        $(
+            impl Inline for $ty {
+                fn into_bits(self) -> usize {
+                    self as $unsigned as usize
+                }
+
+                fn from_bits(bits: usize) -> Self {
+                    bits as $unsigned as $ty
+                }
+            }
+        )*
+    };
+}
+
+inline_as!(u8 => u8, i8 => u8, u16 => u16, i16 => u16);
+
+#[cfg(target_pointer_width = "64")]
+inline_as!(u32 => u32, i32 => u32);
+
+impl Inline for bool {
+    fn into_bits(self) -> usize {
+        self as usize
+    }
+
+    fn from_bits(bits: usize) -> Self {
+        bits != 0
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl Inline for char {
+    fn into_bits(self) -> usize {
+        self as usize
+    }
+
+    fn from_bits(bits: usize) -> Self {
+        char::from_u32(bits as u32).expect("the bits were those of a char")
+    }
+}
+
+fn is_inline(word: usize) -> bool {
+    word & 1 == 1
+}
+
+fn inline<V: Inline>(val: V) -> usize {
+    val.into_bits() << 1 | 1
+}
+
+fn uninline<V: Inline>(word: usize) -> V {
+    V::from_bits(word >> 1)
+}
+
+struct Node<V> {
+    val: V,
+    /// Points to the node below, or holds the bottom value inline. Never changes once the node
+    /// is published.
+    next: usize,
+}
+
+#[repr(transparent)]
+struct DropNode<V>(NonNull<Node<V>>);
+
+impl<V> Drop for DropNode<V> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.0.as_ptr())) }
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<V> crate::hazard::Pointer<Node<V>> for DropNode<V> {
+    unsafe fn from_raw(ptr: *mut Node<V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+/// [Stack](Stack) is a lock free stack of [Inline](Inline) values, which [pop](Stack::pop) and
+/// [peek](Stack::peek) return by value.
+/// ```
+/// use unlink::inline::Stack;
+///
+/// let tasks = Stack::<u16>::new();
+///
+/// // Stored in the head, without allocating.
+/// tasks.push(7);
+/// tasks.push(8);
+///
+/// assert_eq!(tasks.pop(), Some(8));
+/// assert_eq!(tasks.peek(), Some(7));
+/// assert_eq!(tasks.pop(), Some(7));
+/// assert!(tasks.is_empty());
+/// ```
+pub struct Stack<V> {
+    head: AtomicUsize,
+    domain: &'static Domain<UniqueFamily>,
+    _marker: PhantomData<V>,
+}
+
+impl<V> core::fmt::Debug for Stack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stack").finish()
+    }
+}
+
+impl<V: Inline> Default for Stack<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Inline> Stack<V> {
+    pub const fn new() -> Self {
+        Stack {
+            head: AtomicUsize::new(0),
+            domain: &UNIQUE_FAMILY,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == 0
+    }
+
+    /// Protects the node the head points to, if it does, and returns the head.
+    fn protect_head(&self, hazard: &mut Hazard<'_>) -> usize {
+        let mut head = self.head.load(Ordering::Acquire);
+
+        loop {
+            if head == 0 || is_inline(head) {
+                return head;
+            }
+
+            hazard.protect_raw(head as *mut Node<V>);
+
+            let now = self.head.load(Ordering::Acquire);
+
+            if now == head {
+                return head;
+            }
+
+            head = now;
+        }
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Inline + Send + Sync,
+{
+    /// Pushes `val`, allocating a node for it unless the stack is empty.
+    pub fn push(&self, val: V) {
+        let mut node = null_mut::<Node<V>>();
+        let mut head = self.head.load(Ordering::Acquire);
+
+        // An inline head compares equal to any head holding the same value, which is as good as
+        // the one we saw, since values have no identity.
+        loop {
+            let new = if head == 0 {
+                inline(val)
+            } else {
+                if node.is_null() {
+                    node = Box::into_raw(Box::new(Node { val, next: head }));
+                }
+
+                // # Safety: The node is not published yet.
+                unsafe { (*node).next = head };
+                node as usize
+            };
+
+            match self
+                .head
+                .compare_exchange(head, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(now) => head = now,
+            }
+        }
+
+        // The stack was emptied after we allocated the node, so the value went inline instead.
+        if head == 0 && !node.is_null() {
+            // # Safety: The node was never published.
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+
+    pub fn pop(&self) -> Option<V> {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.protect_head(&mut hazard);
+
+            if head == 0 {
+                return None;
+            }
+
+            if is_inline(head) {
+                if self
+                    .head
+                    .compare_exchange(head, 0, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return Some(uninline(head));
+                }
+
+                continue;
+            }
+
+            let node = head as *mut Node<V>;
+
+            // # Safety: The node is protected, and its fields never change once published.
+            let (val, next) = unsafe { ((*node).val, (*node).next) };
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe { domain::retire::<_, DropNode<_>>(self.domain, node) };
+                self.domain.eager_reclaim();
+
+                return Some(val);
+            }
+        }
+    }
+
+    pub fn peek(&self) -> Option<V> {
+        let mut hazard = Hazard::new(self.domain);
+
+        match self.protect_head(&mut hazard) {
+            0 => None,
+            head if is_inline(head) => Some(uninline(head)),
+            // # Safety: The node is protected.
+            head => Some(unsafe { (*(head as *mut Node<V>)).val }),
+        }
+    }
+}
+
+impl<V> Drop for Stack<V> {
+    fn drop(&mut self) {
+        let mut curr = *self.head.get_mut();
+
+        // # Safety: We own the stack, so no other thread can access its nodes.
+        while curr != 0 && !is_inline(curr) {
+            let node = unsafe { Box::from_raw(curr as *mut Node<V>) };
+            curr = node.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_push_pop() {
+        let stack = Stack::<i16>::new();
+
+        assert_eq!(stack.pop(), None);
+        assert_eq!(stack.peek(), None);
+
+        stack.push(-1);
+
+        assert!(is_inline(stack.head.load(Ordering::Relaxed)));
+        assert_eq!(stack.peek(), Some(-1));
+
+        stack.push(i16::MAX);
+        stack.push(i16::MIN);
+
+        assert_eq!(stack.pop(), Some(i16::MIN));
+        assert_eq!(stack.pop(), Some(i16::MAX));
+        assert_eq!(stack.pop(), Some(-1));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_bits() {
+        let bools = Stack::new();
+        [true, false].into_iter().for_each(|b| bools.push(b));
+
+        assert_eq!(bools.pop(), Some(false));
+        assert_eq!(bools.pop(), Some(true));
+
+        #[cfg(target_pointer_width = "64")]
+        {
+            let ids = Stack::<u32>::new();
+            ids.push(u32::MAX);
+            ids.push(0);
+
+            let chars = Stack::new();
+            chars.push('\u{10FFFF}');
+
+            assert_eq!(ids.pop(), Some(0));
+            assert_eq!(ids.pop(), Some(u32::MAX));
+            assert_eq!(chars.pop(), Some('\u{10FFFF}'));
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        let stack = Stack::<u8>::new();
+        (0..10).for_each(|i| stack.push(i));
+        stack.pop();
+
+        // Frees the nodes below the head, but not the inline bottom value.
+        drop(stack);
+    }
+
+    #[test]
+    fn test_sync() {
+        let stack = Stack::<u16>::new();
+
+        let popped = thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || (0..500).for_each(|i| stack.push(t * 500 + i)));
+            }
+
+            let poppers = (0..2)
+                .map(|_| s.spawn(|| (0..800).filter_map(|_| stack.pop()).collect::<Vec<_>>()))
+                .collect::<Vec<_>>();
+
+            poppers
+                .into_iter()
+                .flat_map(|p| p.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut values = popped
+            .into_iter()
+            .chain(core::iter::from_fn(|| stack.pop()))
+            .collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..2000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::ConcurrentStack;
+
+/// The operations of a [ConcurrentStack](ConcurrentStack) that an
+/// [InstrumentedStack](InstrumentedStack) times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Push,
+    Pop,
+    Peek,
+    Append,
+}
+
+/// [Recorder](Recorder) receives the measurements of an [InstrumentedStack](InstrumentedStack),
+/// typically to feed them into histograms. It is called on the thread that ran the operation,
+/// right after it returned, so it should be cheap and must not block.
+pub trait Recorder {
+    /// Records how long an operation of the given kind took.
+    fn record_latency(&self, op: OpKind, latency: Duration);
+
+    /// Records the length of the stack, sampled after every push and pop. Ignored by default.
+    fn record_depth(&self, depth: usize) {
+        let _ = depth;
+    }
+}
+
+impl<R: Recorder + ?Sized> Recorder for &R {
+    fn record_latency(&self, op: OpKind, latency: Duration) {
+        (**self).record_latency(op, latency)
+    }
+
+    fn record_depth(&self, depth: usize) {
+        (**self).record_depth(depth)
+    }
+}
+
+impl<R: Recorder + ?Sized> Recorder for Arc<R> {
+    fn record_latency(&self, op: OpKind, latency: Duration) {
+        (**self).record_latency(op, latency)
+    }
+
+    fn record_depth(&self, depth: usize) {
+        (**self).record_depth(depth)
+    }
+}
+
+/// [InstrumentedStack](InstrumentedStack) wraps any [ConcurrentStack](ConcurrentStack), and
+/// reports the latency of every operation, and the depth of the stack after every push and pop,
+/// to a [Recorder](Recorder). It implements [ConcurrentStack](ConcurrentStack) itself, so code
+/// generic over the trait can be measured without touching its call sites.
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::time::Duration;
+/// use unlink::{ConcurrentStack, InstrumentedStack, OpKind, Recorder, Stack};
+///
+/// #[derive(Default)]
+/// struct Pushes(AtomicUsize);
+///
+/// impl Recorder for Pushes {
+///     fn record_latency(&self, op: OpKind, _: Duration) {
+///         if op == OpKind::Push {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+///
+/// let stack = InstrumentedStack::new(Stack::new(), Pushes::default());
+///
+/// (0..3).for_each(|i| stack.push(i).unwrap());
+/// stack.pop();
+///
+/// assert_eq!(stack.recorder().0.load(Ordering::Relaxed), 3);
+/// ```
+pub struct InstrumentedStack<S, R> {
+    stack: S,
+    recorder: R,
+}
+
+impl<S, R> core::fmt::Debug for InstrumentedStack<S, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedStack").finish()
+    }
+}
+
+impl<S, R> InstrumentedStack<S, R> {
+    pub fn new(stack: S, recorder: R) -> Self {
+        InstrumentedStack { stack, recorder }
+    }
+
+    /// The wrapped stack, for operations outside of [ConcurrentStack](ConcurrentStack), which
+    /// are not measured.
+    pub fn get_ref(&self) -> &S {
+        &self.stack
+    }
+
+    pub fn recorder(&self) -> &R {
+        &self.recorder
+    }
+
+    pub fn into_inner(self) -> (S, R) {
+        (self.stack, self.recorder)
+    }
+
+    /// Runs `f`, and records how long it took as an operation of kind `op`.
+    fn time<T>(&self, op: OpKind, f: impl FnOnce() -> T) -> T
+    where
+        R: Recorder,
+    {
+        let start = Instant::now();
+        let out = f();
+        self.recorder.record_latency(op, start.elapsed());
+
+        out
+    }
+}
+
+impl<V, S, R> ConcurrentStack<V> for InstrumentedStack<S, R>
+where
+    S: ConcurrentStack<V>,
+    R: Recorder,
+{
+    type Entry<'a>
+        = S::Entry<'a>
+    where
+        Self: 'a;
+
+    fn push(&self, val: V) -> Result<(), V> {
+        let pushed = self.time(OpKind::Push, || self.stack.push(val));
+        self.recorder.record_depth(self.stack.len());
+
+        pushed
+    }
+
+    fn pop(&self) -> Option<Self::Entry<'_>> {
+        let popped = self.time(OpKind::Pop, || self.stack.pop());
+        self.recorder.record_depth(self.stack.len());
+
+        popped
+    }
+
+    fn peek(&self) -> Option<Self::Entry<'_>> {
+        self.time(OpKind::Peek, || self.stack.peek())
+    }
+
+    fn append(&self, other: Self) {
+        self.time(OpKind::Append, || self.stack.append(other.stack));
+    }
+
+    fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Stack;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Log {
+        ops: Mutex<Vec<OpKind>>,
+        depths: Mutex<Vec<usize>>,
+    }
+
+    impl Recorder for Log {
+        fn record_latency(&self, op: OpKind, _: Duration) {
+            self.ops.lock().unwrap().push(op);
+        }
+
+        fn record_depth(&self, depth: usize) {
+            self.depths.lock().unwrap().push(depth);
+        }
+    }
+
+    #[test]
+    fn test_instrumented() {
+        let log = Log::default();
+        let stack = InstrumentedStack::new(crate::unsync::Stack::new(), &log);
+
+        let other = crate::unsync::Stack::new();
+        other.push(1);
+        other.push(2);
+
+        stack.push(0).unwrap();
+        stack.append(InstrumentedStack::new(other, &log));
+
+        assert_eq!(stack.peek().map(|e| *e), Some(2));
+        assert_eq!(stack.pop().map(|e| *e), Some(2));
+
+        let (inner, _) = stack.into_inner();
+
+        assert_eq!(inner.len(), 2);
+        assert_eq!(
+            *log.ops.lock().unwrap(),
+            vec![OpKind::Push, OpKind::Append, OpKind::Peek, OpKind::Pop]
+        );
+        assert_eq!(*log.depths.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_instrumented_sync() {
+        let log = Arc::new(Log::default());
+        let stack = InstrumentedStack::new(Stack::new(), Arc::clone(&log));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..100 {
+                        stack.push(i).unwrap();
+                        stack.pop();
+                    }
+                });
+            }
+        });
+
+        let ops = log.ops.lock().unwrap();
+
+        assert_eq!(ops.iter().filter(|op| **op == OpKind::Push).count(), 400);
+        assert_eq!(ops.iter().filter(|op| **op == OpKind::Pop).count(), 400);
+        assert_eq!(log.depths.lock().unwrap().len(), 800);
+    }
+}
@@ -1,3 +1,4 @@
+#![cfg_attr(feature = "nightly", feature(dropck_eyepatch))]
 //! This crate provides a lock free stack that supports concurrent `push`, `pop`, `peek`, and
 //! `extend`.
 //! ```
@@ -28,21 +29,149 @@
 //!
 //!     s.spawn(move || {
 //!         for i in 0..10_usize {
-//!             stack.append(vec![i.pow(2), i.pow(3), i.pow(4)].into_iter().collect());
+//!             stack.push_iter(vec![i.pow(2), i.pow(3), i.pow(4)]);
 //!         }
 //!     });
 //! });
 //!
 //! stack.into_iter().for_each(|v| print!("{}, ", v));
 //! ```
+//!
+//! An [AnyStack](AnyStack) holds values of different types, downcasting them as they are
+//! popped, and a [BoxedStack](BoxedStack) holds boxed trait objects, handing the boxes back as
+//! they are popped.
+//!
+//! A [Recycler](Recycler) pools objects such as buffers for reuse, handing them out behind a
+//! guard that returns them to the pool when it is dropped.
+//!
+//! A [Mailbox](Mailbox) collects messages from any number of [Senders](Sender), and hands them to
+//! its owner in batches, in the order they were sent.
+//!
+//! A [StackHandle](StackHandle) is a cheaply cloned, reference counted [Stack](Stack) that can be
+//! moved into spawned threads, without wrapping the stack in an `Arc` by hand.
+//!
+//! Once a stack is done being built, [Stack::freeze](Stack::freeze) turns it into a
+//! [FrozenStack](FrozenStack), which is read without hazard pointers.
+//!
+//! A [Slot](Slot) holds a single value that can be concurrently `put`, `take`n, and `swap`ped,
+//! sharing the stack's hazard pointer domain for reclamation. Latency sensitive programs can
+//! allocate the domain's hazard pointer slots up front with
+//! [reserve_hazard_slots](reserve_hazard_slots), and test harnesses can reclaim every retired node
+//! at once with [reclaim_all](reclaim_all). Threads that wait on other threads inside an async
+//! executor can do so through [set_yield_hook](set_yield_hook).
+//!
+//! The [persistent](persistent) module provides a stack variant supporting O(1) snapshots, and
+//! [SkipList](SkipList) is a lock free ordered map with range iteration. A [bounded](bounded) stack
+//! keeps at most a fixed number, or total weight, of elements, evicting the oldest or rejecting
+//! pushes when full, and the [compact](compact) stack links slab allocated nodes by 32-bit indices.
+//! The [ConcurrentStack](ConcurrentStack) trait abstracts over these stack variants, including the
+//! single threaded [unsync](unsync) stack, and an [InstrumentedStack](InstrumentedStack) wraps any
+//! of them to report the latency of every operation to a [Recorder](Recorder). The [inline](inline)
+//! stack keeps small values such as indices in two word nodes, and its bottom value in the head,
+//! without a node at all. The [waitfree](waitfree) stack bounds the steps of every pop, for threads
+//! that cannot afford to be starved by contention. The [txn](txn) module moves values between
+//! stacks in a single atomic step.
+//!
+//! The `verification` module holds bounded proof harnesses for the Kani model checker, which
+//! `cargo kani --features tags` runs over every short sequence of operations.
+//!
+//! # Features
+//!
+//! - `testing` adds the `testing` module, which runs randomized [Operations](Operation), or
+//!   [MultiOperations](MultiOperation) across several stacks, from several threads and records
+//!   their outcomes, and `Stack::assert_quiescent`, which checks that no entries are held.
+//! - `arbitrary`, along with `testing`, adds `testing::Weights`, which skews the operations
+//!   generated from fuzzer input toward the kinds chosen.
+//! - `proptest` adds `testing::StackStateMachine`, for shrinkable stateful tests with
+//!   `proptest-state-machine`.
+//! - `fail-points` randomly injects yields and spurious compare exchange failures into the
+//!   stack's critical sections.
+//! - `stats` counts retries, hazard pointer acquisitions, and retired and reclaimed nodes per
+//!   stack, and adds [DomainStats](DomainStats) for the domain shared by all collections.
+//! - `metrics` reports the pushes, pops, retries, and depth of every [Stack](Stack) through the
+//!   `metrics` facade, labelled with the name of the stack.
+//! - `peek-mut` adds [Stack::peek_mut](Stack::peek_mut), which locks the top value for writing.
+//!   Every node carries the lock then, which pops, peeks, and walks take for reading.
+//! - `tags` adds [Stack::push_tagged](Stack::push_tagged), and
+//!   [Stack::pop_matching](Stack::pop_matching), which pops the topmost value with a matching tag.
+//!   Every node carries its tag then.
+//! - `tokens` adds [Stack::push_token](Stack::push_token), which returns a [Token](Token) that
+//!   [Stack::remove](Stack::remove) takes the value out with, wherever it is in the stack by then.
+//!   Every node carries a count of the tokens that hold it then.
+//! - `age` timestamps every value pushed onto a [Stack](Stack), so that stale values can be popped
+//!   or discarded.
+//! - `workers` adds [Stack::spawn_consumers](Stack::spawn_consumers) and the [sched](sched)
+//!   module, a work stealing pool that runs jobs newest first.
+//! - `defmt` implements `defmt::Format` for [Stack](Stack) and the crate's error types.
+//! - `zeroize` wipes the memory of every node before it is freed. Values that own heap memory are
+//!   wiped as well if they are pushed as `zeroize::Zeroizing`.
+//! - `serde` deserializes a [Stack](Stack) from a sequence, and adds
+//!   `Stack::extend_from_deserializer`, which streams a sequence onto a live stack in chunks.
+//! - `wasm-bindgen` adds `wasm::ByteStack`, a stack of byte arrays shared between JavaScript on
+//!   the main thread and its workers.
+//! - `nightly` lets a [Stack](Stack) hold references that expire in the same scope as the stack
+//!   itself, as `Vec` does. It needs a nightly toolchain.
+mod any;
 mod base;
+pub mod bounded;
+mod boxed;
+pub mod compact;
+mod domain;
+mod error;
+mod fail;
+#[cfg(test)]
+mod fixtures;
+mod frozen;
+mod handle;
+mod hazard;
+pub mod inline;
+mod instrumented;
+mod mailbox;
+pub mod persistent;
+mod recycler;
+#[cfg(feature = "workers")]
+pub mod sched;
+pub mod skiplist;
+mod slot;
+mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod txn;
+pub mod unsync;
+#[cfg(kani)]
+mod verification;
+mod wait;
+pub mod waitfree;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+#[cfg(feature = "workers")]
+pub mod workers;
 
-pub use base::Stack;
+pub use any::AnyStack;
+#[cfg(feature = "tokens")]
+pub use base::Token;
+pub use base::{retired_bytes, Stack};
+pub use boxed::BoxedStack;
+#[cfg(feature = "stats")]
+pub use domain::DomainStats;
+pub use domain::{reclaim_all, reserve_hazard_slots};
+pub use error::{AllocError, Closed, Contended, TryPushError};
+pub use frozen::FrozenStack;
+pub use handle::StackHandle;
+pub use instrumented::{InstrumentedStack, OpKind, Recorder};
+pub use mailbox::{Mailbox, Sender};
+pub use recycler::{Recycled, Recycler};
+pub use skiplist::SkipList;
+pub use slot::Slot;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+pub use wait::{reset_yield_hook, set_yield_hook};
 
 extern crate alloc;
 
-/// [Operation](Operation) is used for fuzzing purposes to provide randomized input.
-#[cfg(feature = "arbitrary")]
+/// [Operation](Operation) is used for fuzzing purposes to provide randomized input, and is run
+/// against a stack by the [testing](testing) executor.
+#[cfg(any(feature = "arbitrary", feature = "testing"))]
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Operation<T> {
@@ -51,4 +180,81 @@ pub enum Operation<T> {
     PopPush,
     Append { items: Vec<T> },
     Peek,
+    Len,
+}
+
+/// [MultiOperation](MultiOperation) is an [Operation](Operation) on one of several stacks, or a
+/// move of values between two of them, run by [execute_multi](testing::execute_multi). Stacks are
+/// picked by index modulo the number of stacks.
+#[cfg(any(feature = "arbitrary", feature = "testing"))]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum MultiOperation<T> {
+    On {
+        stack: u8,
+        op: Operation<T>,
+    },
+    /// Moves the top value with [txn::transfer](txn::transfer).
+    Transfer {
+        from: u8,
+        to: u8,
+    },
+    TransferN {
+        from: u8,
+        to: u8,
+        n: u8,
+    },
+    /// Moves every value with [extract_if](Stack::extract_if) and [append](Stack::append).
+    Extend {
+        from: u8,
+        to: u8,
+    },
+}
+
+/// [ConcurrentStack](ConcurrentStack) captures the operations shared by the stack
+/// implementations of this crate, so that code can be written once and run against any of them.
+/// ```
+/// use unlink::{compact, ConcurrentStack, Stack};
+///
+/// fn fill<S: ConcurrentStack<usize>>(stack: &S) {
+///     (0..10).for_each(|i| stack.push(i).unwrap());
+/// }
+///
+/// let (stack, compact) = (Stack::new(), compact::Stack::new());
+///
+/// fill(&stack);
+/// fill(&compact);
+///
+/// assert_eq!(stack.peek().map(|e| *e), compact.peek().map(|e| *e));
+/// ```
+pub trait ConcurrentStack<V> {
+    /// A protected reference to an element, valid until it is dropped.
+    type Entry<'a>: core::ops::Deref<Target = V>
+    where
+        Self: 'a;
+
+    /// Pushes `val`, handing it back if the stack refused it.
+    fn push(&self, val: V) -> Result<(), V>;
+
+    /// Pops the top value, returning `None` if the stack was empty.
+    fn pop(&self) -> Option<Self::Entry<'_>>;
+
+    /// Returns the top value without popping it, or `None` if the stack was empty.
+    fn peek(&self) -> Option<Self::Entry<'_>>;
+
+    /// Moves the contents of `other` on top of `self`, keeping their order.
+    fn append(&self, other: Self)
+    where
+        Self: Sized;
+
+    /// The number of values on the stack. While other threads push or pop, it may be off by the
+    /// values that are being pushed or popped at that moment, but it is exact once they are done.
+    /// Values taken from below the top by methods outside of this trait, such as
+    /// [Stack::pop_matching](Stack::pop_matching), may be counted until their nodes are unlinked.
+    fn len(&self) -> usize;
+
+    /// Whether the stack holds no values.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
@@ -0,0 +1,251 @@
+//! A many to one channel in the shape of an actor's mailbox.
+use core::cell::UnsafeCell;
+use std::sync::atomic::{fence, AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use crate::{Closed, Slot, Stack};
+
+/// The longest the owner parks while waiting for messages, before it looks again.
+const MAX_PARK: Duration = Duration::from_millis(1);
+
+/// A message, which is only ever accessed by the owner once it is drained, as senders hand out no
+/// references to the messages they send.
+struct Message<M>(UnsafeCell<Option<M>>);
+
+// # Safety: See above, only the owner accesses the cell.
+unsafe impl<M: Send> Sync for Message<M> {}
+
+struct Shared<M> {
+    inbox: Stack<Message<M>>,
+    closed: AtomicBool,
+    /// The owner's thread, while it waits for messages.
+    waiting: Slot<Thread>,
+}
+
+/// [Mailbox](Mailbox) receives messages from any number of [Senders](Sender), and hands them to
+/// its owner in batches, in the order they were sent. Senders push onto a [Stack](Stack), and
+/// the owner takes all of it with a single compare exchange and reverses it, so neither side
+/// waits for the other. Once the mailbox is dropped, sending fails.
+/// ```
+/// use unlink::Mailbox;
+///
+/// let mut mailbox = Mailbox::new();
+///
+/// let handles = (0..4)
+///     .map(|t| {
+///         let sender = mailbox.sender();
+///         std::thread::spawn(move || (0..10).for_each(|i| sender.send(t * 10 + i).unwrap()))
+///     })
+///     .collect::<Vec<_>>();
+///
+/// let mut received = Vec::new();
+///
+/// // Returns `None` once every sender was dropped and every message received.
+/// while let Some(batch) = mailbox.recv() {
+///     received.extend(batch);
+/// }
+///
+/// handles.into_iter().for_each(|h| h.join().unwrap());
+///
+/// received.sort();
+/// assert_eq!(received, (0..40).collect::<Vec<_>>());
+/// ```
+pub struct Mailbox<M: Send> {
+    shared: Arc<Shared<M>>,
+}
+
+impl<M: Send> core::fmt::Debug for Mailbox<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mailbox").finish()
+    }
+}
+
+impl<M: Send> Default for Mailbox<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: Send> Mailbox<M> {
+    pub fn new() -> Self {
+        Mailbox {
+            shared: Arc::new(Shared {
+                inbox: Stack::new(),
+                closed: AtomicBool::new(false),
+                waiting: Slot::new(),
+            }),
+        }
+    }
+
+    /// A new sender, which can be cloned and sent to other threads.
+    pub fn sender(&self) -> Sender<M> {
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Takes every message sent so far, oldest first, without waiting for more. Messages from
+    /// the same sender are in the order it sent them.
+    pub fn drain(&mut self) -> Vec<M> {
+        let mut batch = Vec::new();
+
+        self.shared.inbox.pop_batch(usize::MAX, |message| {
+            // # Safety: Only the owner drains the inbox, so no other thread accesses the cell.
+            batch.extend(unsafe { (*message.0.get()).take() });
+        });
+
+        batch.reverse();
+        batch
+    }
+
+    /// Waits for messages and takes all of them, oldest first. Returns `None` once every sender
+    /// was dropped, and every message they sent was received.
+    pub fn recv(&mut self) -> Option<Vec<M>> {
+        let mut park = Duration::from_micros(1);
+
+        loop {
+            // Messages sent by the last sender before it was dropped are visible to the drain
+            // below, so none of them is left behind.
+            let orphaned = Arc::strong_count(&self.shared) == 1;
+            fence(Ordering::Acquire);
+
+            let batch = self.drain();
+
+            if !batch.is_empty() {
+                return Some(batch);
+            }
+
+            if orphaned {
+                return None;
+            }
+
+            self.shared.waiting.put(thread::current());
+
+            if self.shared.inbox.peek().is_none() {
+                thread::park_timeout(park);
+                park = (park * 2).min(MAX_PARK);
+            }
+
+            drop(self.shared.waiting.take());
+        }
+    }
+}
+
+impl<M: Send> Drop for Mailbox<M> {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+}
+
+/// [Sender](Sender) sends messages to a [Mailbox](Mailbox).
+pub struct Sender<M: Send> {
+    shared: Arc<Shared<M>>,
+}
+
+impl<M: Send> core::fmt::Debug for Sender<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sender").finish()
+    }
+}
+
+impl<M: Send> Clone for Sender<M> {
+    fn clone(&self) -> Self {
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<M: Send> Sender<M> {
+    /// Sends `msg`, waking the owner if it waits for messages, or hands it back if the mailbox
+    /// was dropped. A message sent while the mailbox is being dropped may still be accepted, and
+    /// is dropped along with the last sender.
+    pub fn send(&self, msg: M) -> Result<(), Closed<M>> {
+        if self.is_closed() {
+            return Err(Closed(msg));
+        }
+
+        self.shared.inbox.push(Message(UnsafeCell::new(Some(msg))));
+
+        if let Some(owner) = self.shared.waiting.take() {
+            owner.unpark();
+        }
+
+        Ok(())
+    }
+
+    /// Whether the mailbox was dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drain() {
+        let mut mailbox = Mailbox::new();
+        let sender = mailbox.sender();
+
+        assert!(mailbox.drain().is_empty());
+
+        (0..3).for_each(|i| sender.send(i).unwrap());
+
+        assert_eq!(mailbox.drain(), vec![0, 1, 2]);
+
+        sender.send(3).unwrap();
+
+        assert_eq!(mailbox.drain(), vec![3]);
+    }
+
+    #[test]
+    fn test_closed() {
+        let mailbox = Mailbox::new();
+        let sender = mailbox.sender();
+
+        sender.send(0).unwrap();
+        drop(mailbox);
+
+        assert!(sender.is_closed());
+        assert_eq!(sender.send(1), Err(Closed(1)));
+    }
+
+    #[test]
+    fn test_recv_sync() {
+        let mut mailbox = Mailbox::new();
+
+        let received = thread::scope(|s| {
+            for t in 0..4 {
+                let sender = mailbox.sender();
+                s.spawn(move || {
+                    for i in 0..250 {
+                        sender.send((t, i)).unwrap();
+
+                        if i % 50 == 0 {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+                });
+            }
+
+            let mut received = Vec::new();
+
+            while let Some(batch) = mailbox.recv() {
+                received.extend(batch);
+            }
+
+            received
+        });
+
+        // Every sender's messages arrive in the order they were sent.
+        for t in 0..4 {
+            let sent = received.iter().filter(|(s, _)| *s == t).map(|(_, i)| *i);
+
+            assert!(sent.eq(0..250));
+        }
+    }
+}
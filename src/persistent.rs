@@ -0,0 +1,403 @@
+//! A persistent stack whose nodes are shared between the live stack and any number of
+//! [Snapshots](Snapshot).
+//!
+//! Nodes are immutable once linked and reference counted: the stack holds one count on its
+//! head, every node holds one count on its successor, and every snapshot holds one count on the
+//! node it was taken at. Taking a snapshot is therefore a single hazard protected increment,
+//! regardless of the size of the stack.
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::base::{UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+
+struct Node<V> {
+    val: V,
+    next: *mut Self,
+    refs: AtomicUsize,
+}
+
+unsafe impl<V: Send> Send for Node<V> {}
+
+impl<V> Node<V> {
+    fn new(val: V, next: *mut Self) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            val,
+            next,
+            refs: AtomicUsize::new(1),
+        }))
+    }
+
+    /// Takes an additional count on `raw` unless it has already dropped to zero.
+    ///
+    /// # Safety: `raw` must be protected by a hazard pointer.
+    unsafe fn try_acquire(raw: *mut Self) -> bool {
+        let mut refs = (*raw).refs.load(Ordering::Acquire);
+
+        while refs != 0 {
+            match (*raw).refs.compare_exchange_weak(
+                refs,
+                refs + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(now) => refs = now,
+            }
+        }
+
+        false
+    }
+
+    /// Gives up one count on `raw`, retiring it once the last count is gone.
+    ///
+    /// # Safety: The caller must own one of the counts on `raw`.
+    unsafe fn release(raw: *mut Self)
+    where
+        V: Send,
+    {
+        if raw.is_null() {
+            return;
+        }
+
+        if (*raw).refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            domain::retire::<_, ReleaseNode<_>>(&UNIQUE_FAMILY, raw);
+        }
+    }
+}
+
+/// Reclaims retired nodes in a single pass. The successors whose last count was held by a
+/// reclaimed node are retired by then, and left to the next pass, since looping until nothing
+/// more can be freed would never end while other threads keep retiring nodes.
+fn reclaim(domain: &Domain<UniqueFamily>) {
+    domain.eager_reclaim();
+}
+
+/// [ReleaseNode](ReleaseNode) frees a node once it has been reclaimed and gives up the count it
+/// held on its successor.
+#[repr(transparent)]
+struct ReleaseNode<V: Send>(NonNull<Node<V>>);
+
+impl<V: Send> Drop for ReleaseNode<V> {
+    fn drop(&mut self) {
+        unsafe {
+            let node = Box::from_raw(self.0.as_ptr());
+            Node::release(node.next);
+        }
+
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<V: Send> crate::hazard::Pointer<Node<V>> for ReleaseNode<V> {
+    unsafe fn from_raw(ptr: *mut Node<V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+pub struct Stack<V: Send> {
+    head: AtomicPtr<Node<V>>,
+    domain: &'static Domain<UniqueFamily>,
+    len: AtomicUsize,
+}
+
+unsafe impl<V: Send + Sync> Send for Stack<V> {}
+unsafe impl<V: Send + Sync> Sync for Stack<V> {}
+
+impl<V: Send> core::fmt::Debug for Stack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stack").finish()
+    }
+}
+
+impl<V: Send> Default for Stack<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Send> Stack<V> {
+    pub fn new() -> Self {
+        Stack {
+            head: AtomicPtr::new(null_mut()),
+            domain: &UNIQUE_FAMILY,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let len = self.len.load(Ordering::Relaxed);
+        if len > isize::MAX as usize {
+            0
+        } else {
+            len
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    pub fn push(&self, val: V) {
+        let mut head_ptr = self.head.load(Ordering::Acquire);
+        let node_ptr = Node::new(val, head_ptr);
+
+        // The stack's count on the old head moves into the new node's `next`.
+        while let Err(now) =
+            self.head
+                .compare_exchange(head_ptr, node_ptr, Ordering::AcqRel, Ordering::Acquire)
+        {
+            head_ptr = now;
+            unsafe { (*node_ptr).next = head_ptr };
+        }
+
+        self.len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Pops the top value. Since the node may still be part of a [Snapshot](Snapshot), its value
+    /// is cloned out rather than moved.
+    pub fn pop(&self) -> Option<V>
+    where
+        V: Clone,
+    {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let old_head = hazard.protect_ptr(&self.head)?.0.as_ptr();
+
+            unsafe {
+                let next_ptr = (*old_head).next;
+
+                // `old_head` holds a count on `next_ptr` until it is reclaimed, which cannot
+                // happen while we protect it, so this increment can never resurrect a node.
+                if !next_ptr.is_null() {
+                    (*next_ptr).refs.fetch_add(1, Ordering::AcqRel);
+                }
+
+                match self.head.compare_exchange(
+                    old_head,
+                    next_ptr,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let val = (*old_head).val.clone();
+
+                        hazard.reset_protection();
+                        Node::release(old_head);
+                        self.len.fetch_sub(1, Ordering::Relaxed);
+                        reclaim(self.domain);
+
+                        return Some(val);
+                    }
+                    Err(_) => Node::release(next_ptr),
+                }
+            }
+        }
+    }
+
+    /// Captures the current contents of the stack in O(1). The snapshot is unaffected by any
+    /// later `push` or `pop`.
+    pub fn snapshot(&self) -> Snapshot<V> {
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let Some((head, _)) = hazard.protect_ptr(&self.head) else {
+                return Snapshot { head: null_mut() };
+            };
+
+            if unsafe { Node::try_acquire(head.as_ptr()) } {
+                return Snapshot {
+                    head: head.as_ptr(),
+                };
+            }
+        }
+    }
+}
+
+impl<V: Send> Drop for Stack<V> {
+    fn drop(&mut self) {
+        unsafe { Node::release(*self.head.get_mut()) };
+        reclaim(self.domain);
+    }
+}
+
+/// [Snapshot](Snapshot) is an immutable point in time view of a [Stack](Stack). Cloning a
+/// snapshot is O(1).
+pub struct Snapshot<V: Send> {
+    head: *mut Node<V>,
+}
+
+unsafe impl<V: Send + Sync> Send for Snapshot<V> {}
+unsafe impl<V: Send + Sync> Sync for Snapshot<V> {}
+
+impl<V: Send> Snapshot<V> {
+    pub fn peek(&self) -> Option<&V> {
+        self.iter().next()
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            next: self.head,
+            _snapshot: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<V: Send> Clone for Snapshot<V> {
+    fn clone(&self) -> Self {
+        if !self.head.is_null() {
+            unsafe { (*self.head).refs.fetch_add(1, Ordering::AcqRel) };
+        }
+
+        Snapshot { head: self.head }
+    }
+}
+
+impl<V: Send> Drop for Snapshot<V> {
+    fn drop(&mut self) {
+        unsafe { Node::release(self.head) };
+        reclaim(&UNIQUE_FAMILY);
+    }
+}
+
+impl<V: Send + core::fmt::Debug> core::fmt::Debug for Snapshot<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, V: Send> IntoIterator for &'a Snapshot<V> {
+    type Item = &'a V;
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// [Iter](Iter) walks a [Snapshot](Snapshot) from the top down. The chain is kept alive by the
+/// snapshot, so no hazard pointers are needed.
+pub struct Iter<'a, V: Send> {
+    next: *mut Node<V>,
+    _snapshot: core::marker::PhantomData<&'a Snapshot<V>>,
+}
+
+impl<'a, V: Send> Iterator for Iter<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let node = &*self.next;
+            self.next = node.next;
+            Some(&node.val)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let stack = Stack::new();
+
+        (0..5).for_each(|i| stack.push(i));
+
+        let snapshot = stack.snapshot();
+
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(3));
+        stack.push(10);
+
+        assert_eq!(snapshot.iter().copied().collect::<Vec<_>>(), [4, 3, 2, 1, 0]);
+        assert_eq!(stack.snapshot().iter().copied().collect::<Vec<_>>(), [10, 2, 1, 0]);
+        assert_eq!(stack.len(), 4);
+    }
+
+    #[test]
+    fn test_empty_snapshot() {
+        let stack = Stack::<i32>::new();
+
+        let snapshot = stack.snapshot();
+
+        assert!(snapshot.is_empty());
+        assert_eq!(snapshot.peek(), None);
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new();
+
+        (0..3).for_each(|_| stack.push(DropCounter(counter.clone())));
+
+        let snapshot = stack.snapshot();
+        let copy = snapshot.clone();
+
+        drop(stack);
+        drop(snapshot);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+        assert_eq!(copy.len(), 3);
+
+        drop(copy);
+
+        // Every pass frees one more node of the chain, whose successor it retires.
+        while counter.load(Ordering::SeqCst) < 3 {
+            UNIQUE_FAMILY.eager_reclaim();
+        }
+    }
+
+    #[test]
+    fn test_snapshot_sync() {
+        let stack = Stack::new();
+
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for j in 0..200 {
+                        if j % 3 == 0 {
+                            stack.pop();
+                        } else {
+                            stack.push(i);
+                        }
+                    }
+                });
+            }
+
+            for _ in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        let snapshot = stack.snapshot();
+                        assert!(snapshot.iter().all(|&v| v < 4));
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,162 @@
+//! Reuse of objects that are expensive to create, such as buffers.
+use core::ops::{Deref, DerefMut};
+
+use crate::BoxedStack;
+
+/// [Recycler](Recycler) hands out objects from a lock free pool, creating them with its factory
+/// while the pool is empty, and takes them back once their [Recycled](Recycled) guard is dropped.
+/// Objects come back as they were left, so clearing them is up to the caller.
+/// ```
+/// use unlink::Recycler;
+///
+/// let buffers = Recycler::new(|| Vec::<u8>::with_capacity(1024));
+///
+/// {
+///     let mut buffer = buffers.get();
+///     buffer.extend_from_slice(b"request");
+///     buffer.clear();
+/// }
+///
+/// // The buffer, and its capacity, are reused.
+/// assert!(!buffers.is_empty());
+/// assert!(buffers.get().capacity() >= 1024);
+/// ```
+pub struct Recycler<T: Send, F = fn() -> T> {
+    pool: BoxedStack<T>,
+    factory: F,
+}
+
+impl<T: Send, F> core::fmt::Debug for Recycler<T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recycler").finish()
+    }
+}
+
+impl<T, F> Recycler<T, F>
+where
+    T: Send,
+    F: Fn() -> T,
+{
+    pub fn new(factory: F) -> Self {
+        Recycler {
+            pool: BoxedStack::new(),
+            factory,
+        }
+    }
+
+    /// Whether no object is waiting to be reused, so that [get](Recycler::get) creates one.
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+
+    /// Takes an object from the pool, or creates one if the pool is empty.
+    pub fn get(&self) -> Recycled<'_, T, F> {
+        let val = self
+            .pool
+            .pop_boxed()
+            .unwrap_or_else(|| Box::new((self.factory)()));
+
+        Recycled {
+            val: Some(val),
+            recycler: self,
+        }
+    }
+
+    /// Adds `val` to the pool, as if it had been handed out and returned.
+    pub fn put(&self, val: T) {
+        self.pool.push_boxed(Box::new(val));
+    }
+}
+
+/// [Recycled](Recycled) is an object handed out by a [Recycler](Recycler), which returns to its
+/// pool when this is dropped.
+pub struct Recycled<'a, T: Send, F = fn() -> T> {
+    val: Option<Box<T>>,
+    recycler: &'a Recycler<T, F>,
+}
+
+impl<'a, T: Send, F> Recycled<'a, T, F> {
+    /// Keeps the object out of the pool for good.
+    pub fn into_inner(mut self) -> T {
+        *self.val.take().expect("the value is only taken on drop")
+    }
+}
+
+impl<'a, T: Send, F> Deref for Recycled<'a, T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.val.as_ref().expect("the value is only taken on drop")
+    }
+}
+
+impl<'a, T: Send, F> DerefMut for Recycled<'a, T, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.val.as_mut().expect("the value is only taken on drop")
+    }
+}
+
+impl<'a, T: Send, F> Drop for Recycled<'a, T, F> {
+    fn drop(&mut self) {
+        if let Some(val) = self.val.take() {
+            self.recycler.pool.push_boxed(val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_reuse() {
+        let created = AtomicUsize::new(0);
+        let recycler = Recycler::new(|| created.fetch_add(1, Ordering::SeqCst));
+
+        let (first, second) = (recycler.get(), recycler.get());
+
+        assert_eq!((*first, *second), (0, 1));
+
+        drop(first);
+
+        assert_eq!(*recycler.get(), 0);
+        assert_eq!(second.into_inner(), 1);
+
+        // Only the first one was returned.
+        let held = recycler.get();
+
+        assert_eq!(*held, 0);
+        assert!(recycler.is_empty());
+        assert_eq!(*recycler.get(), 2);
+        assert!(!recycler.is_empty());
+    }
+
+    #[test]
+    fn test_sync() {
+        let created = AtomicUsize::new(0);
+        let recycler = Recycler::new(|| {
+            created.fetch_add(1, Ordering::SeqCst);
+            Vec::new()
+        });
+
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let recycler = &recycler;
+                s.spawn(move || {
+                    for i in 0..200 {
+                        let mut buffer = recycler.get();
+
+                        assert!(buffer.is_empty());
+
+                        buffer.push(t * 200 + i);
+                        buffer.clear();
+                    }
+                });
+            }
+        });
+
+        // No more objects were created than were in use at once.
+        assert!(created.load(Ordering::SeqCst) <= 4);
+    }
+}
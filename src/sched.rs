@@ -0,0 +1,261 @@
+//! A pool of worker threads that run jobs from lock free stacks.
+//!
+//! Every worker has its own [BoxedStack](crate::BoxedStack) of jobs, which it runs newest first,
+//! so that jobs spawned by a job run while their data is still in cache. Jobs submitted from
+//! outside the pool go onto a shared stack instead, and a worker that runs out of jobs takes
+//! them from there, or steals from the other workers.
+use core::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::BoxedStack;
+
+/// The longest an idle worker parks, before it looks for jobs again.
+const MAX_PARK: Duration = Duration::from_millis(1);
+
+type Job = dyn FnOnce() + Send;
+
+struct Shared {
+    injector: BoxedStack<Job>,
+    locals: Vec<BoxedStack<Job>>,
+    closed: AtomicBool,
+}
+
+std::thread_local! {
+    /// The pool the current thread works for, and its index there.
+    static WORKER: Cell<Option<(*const Shared, usize)>> = const { Cell::new(None) };
+}
+
+/// [Pool](Pool) runs jobs on a fixed number of worker threads. Dropping it shuts it down, as
+/// [shutdown](Pool::shutdown) does.
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use unlink::sched::Pool;
+///
+/// let pool = Arc::new(Pool::new(4));
+/// let sum = Arc::new(AtomicUsize::new(0));
+///
+/// for i in 1..=10 {
+///     let (pool2, sum) = (Arc::clone(&pool), Arc::clone(&sum));
+///
+///     pool.execute(move || {
+///         // Runs on the same worker, unless another one steals it.
+///         pool2.execute(move || {
+///             sum.fetch_add(i, Ordering::Relaxed);
+///         });
+///     });
+/// }
+///
+/// // Waits for the jobs, including the ones spawned by jobs.
+/// while sum.load(Ordering::Relaxed) != 55 {
+///     std::thread::yield_now();
+/// }
+/// ```
+pub struct Pool {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+    next: AtomicUsize,
+}
+
+impl core::fmt::Debug for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pool").finish()
+    }
+}
+
+impl Pool {
+    /// Spawns `workers` threads, at least one.
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+
+        let shared = Arc::new(Shared {
+            injector: BoxedStack::new(),
+            locals: (0..workers).map(|_| BoxedStack::new()).collect(),
+            closed: AtomicBool::new(false),
+        });
+
+        let threads = (0..workers)
+            .map(|index| {
+                let shared = Arc::clone(&shared);
+
+                thread::spawn(move || work(&shared, index))
+            })
+            .collect();
+
+        Pool {
+            shared,
+            threads,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Queues `job` to run on one of the workers. Jobs queued by a job of this pool run on the
+    /// same worker, newest first, unless other workers steal them.
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let shared = Arc::as_ptr(&self.shared);
+
+        match WORKER.with(Cell::get) {
+            Some((pool, index)) if pool == shared => {
+                self.shared.locals[index].push_boxed(Box::new(job));
+            }
+            _ => self.shared.injector.push_boxed(Box::new(job)),
+        }
+
+        let next = self.next.fetch_add(1, Ordering::Relaxed) % self.threads.len();
+        self.threads[next].thread().unpark();
+    }
+
+    /// Lets the workers run the jobs already queued, and waits for them to finish. Returns the
+    /// panic of the first job that panicked, if any did. A panicking job takes its worker down,
+    /// and the jobs left on its stack are run by the others.
+    pub fn shutdown(mut self) -> thread::Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> thread::Result<()> {
+        self.shared.closed.store(true, Ordering::Release);
+
+        self.threads.iter().for_each(|t| t.thread().unpark());
+
+        let mut result = Ok(());
+
+        // Join every worker, even after one of them panicked. A job may drop the pool itself,
+        // in which case its own worker finishes once the job returns.
+        for thread in self.threads.drain(..) {
+            if thread.thread().id() == thread::current().id() {
+                continue;
+            }
+
+            let joined = thread.join();
+
+            if result.is_ok() {
+                result = joined;
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Runs jobs until none are left anywhere after the pool was closed.
+fn work(shared: &Shared, index: usize) {
+    WORKER.with(|worker| worker.set(Some((shared as *const Shared, index))));
+
+    let mut park = Duration::from_micros(1);
+
+    loop {
+        // Jobs queued before the pool was closed are visible to the search below, so none of
+        // them is left behind.
+        let closing = shared.closed.load(Ordering::Acquire);
+
+        match find(shared, index) {
+            Some(job) => {
+                job();
+                park = Duration::from_micros(1);
+            }
+            None if closing => return,
+            None => {
+                thread::park_timeout(park);
+                park = (park * 2).min(MAX_PARK);
+            }
+        }
+    }
+}
+
+/// Takes a job from the worker's own stack, the shared one, or another worker's, in that order.
+fn find(shared: &Shared, index: usize) -> Option<Box<Job>> {
+    let workers = shared.locals.len();
+
+    shared.locals[index]
+        .pop_boxed()
+        .or_else(|| shared.injector.pop_boxed())
+        .or_else(|| (1..workers).find_map(|i| shared.locals[(index + i) % workers].pop_boxed()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_execute() {
+        let pool = Pool::new(4);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        for i in 0..1000 {
+            let seen = Arc::clone(&seen);
+            pool.execute(move || seen.lock().unwrap().push(i));
+        }
+
+        pool.shutdown().unwrap();
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_nested() {
+        fn spawn(pool: &Arc<Pool>, count: &Arc<AtomicUsize>, depth: usize) {
+            count.fetch_add(1, Ordering::SeqCst);
+
+            if depth > 0 {
+                for _ in 0..2 {
+                    let (next, count) = (Arc::clone(pool), Arc::clone(count));
+                    pool.execute(move || spawn(&next, &count, depth - 1));
+                }
+            }
+        }
+
+        let pool = Arc::new(Pool::new(2));
+        let count = Arc::new(AtomicUsize::new(0));
+
+        spawn(&pool, &count, 8);
+
+        while count.load(Ordering::SeqCst) != (1 << 9) - 1 {
+            thread::yield_now();
+        }
+
+        // The last jobs may not have dropped their handles yet.
+        while Arc::strong_count(&pool) > 1 {
+            thread::yield_now();
+        }
+
+        Arc::into_inner(pool).unwrap().shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_panicking_job() {
+        let pool = Pool::new(2);
+        let count = Arc::new(AtomicUsize::new(0));
+
+        pool.execute(|| panic!("job failed"));
+
+        for _ in 0..100 {
+            let count = Arc::clone(&count);
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        assert!(pool.shutdown().is_err());
+        assert_eq!(count.load(Ordering::SeqCst), 100);
+    }
+}
@@ -0,0 +1,695 @@
+//! A lock free ordered map built as a skip list.
+//!
+//! Removal first marks a node's `next` pointers from the top level down, the mark on level 0
+//! deciding which remover wins, after which any traversal that encounters the marked node
+//! unlinks it. Every node counts the levels it is currently linked at plus one for the inserter
+//! that is still building its tower, and is retired through the crate's hazard domain once that
+//! count drops to zero.
+use core::cmp::Ordering as CmpOrdering;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::base::{UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+
+const MAX_HEIGHT: usize = 12;
+
+struct Node<K, V> {
+    key: K,
+    val: V,
+    next: Box<[AtomicPtr<Self>]>,
+    refs: AtomicUsize,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(key: K, val: V, height: usize) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            key,
+            val,
+            next: (0..height).map(|_| AtomicPtr::new(null_mut())).collect(),
+            refs: AtomicUsize::new(1),
+        }))
+    }
+
+    fn height(&self) -> usize {
+        self.next.len()
+    }
+
+    fn is_removed(&self) -> bool {
+        is_marked(self.next[0].load(Ordering::Acquire))
+    }
+
+    /// Gives up one count on `raw`, retiring it once it is neither linked nor being built.
+    ///
+    /// # Safety: The caller must own one of the counts on `raw`.
+    unsafe fn release(raw: *mut Self)
+    where
+        K: Send,
+        V: Send,
+    {
+        if (*raw).refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            domain::retire::<_, DropNode<_, _>>(&UNIQUE_FAMILY, raw);
+        }
+    }
+}
+
+fn is_marked<T>(ptr: *mut T) -> bool {
+    ptr as usize & 1 == 1
+}
+
+fn marked<T>(ptr: *mut T) -> *mut T {
+    (ptr as usize | 1) as *mut T
+}
+
+fn unmarked<T>(ptr: *mut T) -> *mut T {
+    (ptr as usize & !1) as *mut T
+}
+
+/// Draws a tower height from a geometric distribution with p = 1/2.
+fn random_height() -> usize {
+    static SEED: AtomicUsize = AtomicUsize::new(0);
+
+    // splitmix64 over a shared counter is plenty for picking heights.
+    let mut x = (SEED.fetch_add(1, Ordering::Relaxed) as u64).wrapping_add(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^= x >> 31;
+
+    (x | 1 << (MAX_HEIGHT - 1)).trailing_zeros() as usize + 1
+}
+
+#[repr(transparent)]
+struct DropNode<K, V>(NonNull<Node<K, V>>);
+
+impl<K, V> Drop for DropNode<K, V> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.0.as_ptr())) }
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<K, V> crate::hazard::Pointer<Node<K, V>> for DropNode<K, V> {
+    unsafe fn from_raw(ptr: *mut Node<K, V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+/// [Position](Position) is the result of a search: for every level the link that precedes the
+/// searched key and the node that follows it, all of which stay protected while it is alive.
+struct Position<'a, K, V> {
+    preds: [*const AtomicPtr<Node<K, V>>; MAX_HEIGHT],
+    succs: [*mut Node<K, V>; MAX_HEIGHT],
+    _pred_hazards: [Hazard<'a>; MAX_HEIGHT],
+    _succ_hazards: [Hazard<'a>; MAX_HEIGHT],
+}
+
+pub struct SkipList<K, V> {
+    head: [AtomicPtr<Node<K, V>>; MAX_HEIGHT],
+    domain: &'static Domain<UniqueFamily>,
+    len: AtomicUsize,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> core::fmt::Debug for SkipList<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SkipList").finish()
+    }
+}
+
+impl<K, V> Default for SkipList<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> SkipList<K, V> {
+    pub fn new() -> Self {
+        SkipList {
+            head: core::array::from_fn(|_| AtomicPtr::new(null_mut())),
+            domain: &UNIQUE_FAMILY,
+            len: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let len = self.len.load(Ordering::Relaxed);
+        if len > isize::MAX as usize {
+            0
+        } else {
+            len
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unmarked(self.head[0].load(Ordering::Acquire)).is_null()
+    }
+}
+
+impl<K, V> SkipList<K, V>
+where
+    K: Ord + Send + Sync,
+    V: Send + Sync,
+{
+    /// Inserts `val` under `key`. Returns `false`, dropping both, if the key is already present.
+    pub fn insert(&self, key: K, val: V) -> bool {
+        let mut pos = self.find(|k| k < &key);
+
+        if Self::found(&pos, &key) {
+            return false;
+        }
+
+        let height = random_height();
+        let node_ptr = Node::new(key, val, height);
+        let node = unsafe { &*node_ptr };
+
+        loop {
+            for (level, next) in node.next.iter().enumerate() {
+                next.store(pos.succs[level], Ordering::Relaxed);
+            }
+
+            node.refs.fetch_add(1, Ordering::Relaxed);
+
+            let linked = unsafe {
+                (*pos.preds[0]).compare_exchange(
+                    pos.succs[0],
+                    node_ptr,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+            };
+
+            if linked.is_ok() {
+                break;
+            }
+
+            node.refs.fetch_sub(1, Ordering::Relaxed);
+
+            pos = self.find(|k| k < &node.key);
+
+            if Self::found(&pos, &node.key) {
+                // # Safety: The node was never published.
+                unsafe { drop(Box::from_raw(node_ptr)) };
+                return false;
+            }
+        }
+
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        'build: for level in 1..height {
+            loop {
+                let next = node.next[level].load(Ordering::Acquire);
+
+                if is_marked(next) {
+                    break 'build;
+                }
+
+                if next != pos.succs[level]
+                    && node.next[level]
+                        .compare_exchange(next, pos.succs[level], Ordering::AcqRel, Ordering::Relaxed)
+                        .is_err()
+                {
+                    break 'build;
+                }
+
+                node.refs.fetch_add(1, Ordering::Relaxed);
+
+                let linked = unsafe {
+                    (*pos.preds[level]).compare_exchange(
+                        pos.succs[level],
+                        node_ptr,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    )
+                };
+
+                if linked.is_ok() {
+                    break;
+                }
+
+                node.refs.fetch_sub(1, Ordering::Relaxed);
+
+                pos = self.find(|k| k < &node.key);
+
+                if pos.succs[0] != node_ptr {
+                    break 'build;
+                }
+            }
+        }
+
+        // # Safety: We own the build count taken in `Node::new`.
+        unsafe { Node::release(node_ptr) };
+
+        true
+    }
+
+    pub fn get(&self, key: &K) -> Option<Entry<'_, K, V>> {
+        let pos = self.find(|k| k < key);
+
+        if !Self::found(&pos, key) {
+            return None;
+        }
+
+        Some(Entry::from_ptr(pos.succs[0]))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        Self::found(&self.find(|k| k < key), key)
+    }
+
+    /// Removes `key`, returning a protected [Entry](Entry) to the removed node.
+    pub fn remove(&self, key: &K) -> Option<Entry<'_, K, V>> {
+        let pos = self.find(|k| k < key);
+
+        if !Self::found(&pos, key) {
+            return None;
+        }
+
+        let node = unsafe { &*pos.succs[0] };
+
+        for level in (1..node.height()).rev() {
+            let mut next = node.next[level].load(Ordering::Acquire);
+
+            while !is_marked(next) {
+                match node.next[level].compare_exchange(
+                    next,
+                    marked(next),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => break,
+                    Err(now) => next = now,
+                }
+            }
+        }
+
+        let mut next = node.next[0].load(Ordering::Acquire);
+
+        loop {
+            if is_marked(next) {
+                return None;
+            }
+
+            match node.next[0].compare_exchange(
+                next,
+                marked(next),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(now) => next = now,
+            }
+        }
+
+        self.len.fetch_sub(1, Ordering::Relaxed);
+
+        let entry = Entry::from_ptr(pos.succs[0]);
+
+        drop(pos);
+
+        // Physically unlink the node from every level.
+        self.find(|k| k < key);
+        self.domain.eager_reclaim();
+
+        Some(entry)
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V, core::ops::RangeFull> {
+        self.range(..)
+    }
+
+    /// Iterates over the entries whose keys lie in `range`, in ascending order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, R> {
+        Range {
+            list: self,
+            range,
+            last: None,
+            done: false,
+        }
+    }
+
+    fn found(pos: &Position<'_, K, V>, key: &K) -> bool {
+        !pos.succs[0].is_null() && unsafe { &(*pos.succs[0]).key } == key
+    }
+
+    /// Finds, on every level, the last link whose node satisfies `before` and the node following
+    /// it, unlinking any marked nodes along the way.
+    fn find(&self, before: impl Fn(&K) -> bool) -> Position<'_, K, V> {
+        let mut pred_hazards: [_; MAX_HEIGHT] = core::array::from_fn(|_| Hazard::new(self.domain));
+        let mut succ_hazards: [_; MAX_HEIGHT] = core::array::from_fn(|_| Hazard::new(self.domain));
+        let mut hazard = Hazard::new(self.domain);
+
+        let mut preds: [*const AtomicPtr<Node<K, V>>; MAX_HEIGHT] = [core::ptr::null(); MAX_HEIGHT];
+        let mut succs = [null_mut(); MAX_HEIGHT];
+
+        'retry: loop {
+            let mut pred: *mut Node<K, V> = null_mut();
+
+            for level in (0..MAX_HEIGHT).rev() {
+                // # Safety: `pred` is either the head or protected by the level above.
+                let mut link = if pred.is_null() {
+                    &self.head[level]
+                } else {
+                    pred_hazards[level].protect_raw(pred);
+                    unsafe { &(*pred).next[level] }
+                };
+
+                let mut curr = link.load(Ordering::Acquire);
+
+                if is_marked(curr) {
+                    continue 'retry;
+                }
+
+                if !curr.is_null() {
+                    succ_hazards[level].protect_raw(curr);
+
+                    if link.load(Ordering::Acquire) != curr {
+                        continue 'retry;
+                    }
+                }
+
+                while !curr.is_null() {
+                    let next = unsafe { (*curr).next[level].load(Ordering::Acquire) };
+
+                    if is_marked(next) {
+                        let next = unmarked(next);
+
+                        if link
+                            .compare_exchange(curr, next, Ordering::AcqRel, Ordering::Relaxed)
+                            .is_err()
+                        {
+                            continue 'retry;
+                        }
+
+                        // # Safety: Unlinking `curr` gives up the count its link held.
+                        unsafe { Node::release(curr) };
+
+                        curr = next;
+
+                        if !curr.is_null() {
+                            succ_hazards[level].protect_raw(curr);
+
+                            if link.load(Ordering::Acquire) != curr {
+                                continue 'retry;
+                            }
+                        }
+
+                        continue;
+                    }
+
+                    if !before(unsafe { &(*curr).key }) {
+                        break;
+                    }
+
+                    if !next.is_null() {
+                        hazard.protect_raw(next);
+
+                        if unsafe { (*curr).next[level].load(Ordering::Acquire) } != next {
+                            continue 'retry;
+                        }
+                    }
+
+                    pred = curr;
+                    link = unsafe { &(*pred).next[level] };
+                    core::mem::swap(&mut pred_hazards[level], &mut succ_hazards[level]);
+                    core::mem::swap(&mut succ_hazards[level], &mut hazard);
+                    curr = next;
+                }
+
+                preds[level] = link;
+                succs[level] = curr;
+            }
+
+            return Position {
+                preds,
+                succs,
+                _pred_hazards: pred_hazards,
+                _succ_hazards: succ_hazards,
+            };
+        }
+    }
+}
+
+impl<K, V> Drop for SkipList<K, V> {
+    fn drop(&mut self) {
+        self.domain.eager_reclaim();
+
+        // # Safety: We have exclusive ownership of self. Walking from the top level down, a node
+        // is freed when its last link is visited, so it is never visited again afterwards.
+        unsafe {
+            for level in (0..MAX_HEIGHT).rev() {
+                let mut curr = unmarked(self.head[level].load(Ordering::Acquire));
+
+                while !curr.is_null() {
+                    let next = unmarked((*curr).next[level].load(Ordering::Acquire));
+
+                    if (*curr).refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        drop(Box::from_raw(curr));
+                    }
+
+                    curr = next;
+                }
+            }
+        }
+    }
+}
+
+/// [Entry](Entry) is a protected reference to a key-value pair of a [SkipList](SkipList). It
+/// stays valid until it is dropped, even if the pair is removed in the meantime.
+pub struct Entry<'a, K, V> {
+    node: NonNull<Node<K, V>>,
+    _hazard: Hazard<'a>,
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    /// `ptr` must already be protected by a hazard pointer, since it is only re-protected here.
+    fn from_ptr(ptr: *mut Node<K, V>) -> Self {
+        let mut _hazard = Hazard::new(&UNIQUE_FAMILY);
+
+        _hazard.protect_raw(ptr);
+
+        let node = NonNull::new(ptr).expect("entries point to nodes");
+
+        Entry { node, _hazard }
+    }
+
+    pub fn key(&self) -> &K {
+        unsafe { &self.node.as_ref().key }
+    }
+
+    pub fn value(&self) -> &V {
+        unsafe { &self.node.as_ref().val }
+    }
+}
+
+impl<'a, K, V> core::ops::Deref for Entry<'a, K, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        self.value()
+    }
+}
+
+impl<'a, K: core::fmt::Debug, V: core::fmt::Debug> core::fmt::Debug for Entry<'a, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Entry")
+            .field(self.key())
+            .field(self.value())
+            .finish()
+    }
+}
+
+/// [Range](Range) walks level 0 of a [SkipList](SkipList) hand-over-hand. If the node it stands
+/// on is removed, it searches again for the first key after it.
+pub struct Range<'a, K, V, R> {
+    list: &'a SkipList<K, V>,
+    range: R,
+    last: Option<Entry<'a, K, V>>,
+    done: bool,
+}
+
+impl<'a, K, V, R> Iterator for Range<'a, K, V, R>
+where
+    K: Ord + Send + Sync,
+    V: Send + Sync,
+    R: RangeBounds<K>,
+{
+    type Item = Entry<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let succ = match &self.last {
+                None => {
+                    let pos = match self.range.start_bound() {
+                        Bound::Included(start) => self.list.find(|k| k < start),
+                        Bound::Excluded(start) => self.list.find(|k| k <= start),
+                        Bound::Unbounded => self.list.find(|_| false),
+                    };
+                    (!pos.succs[0].is_null()).then(|| Entry::from_ptr(pos.succs[0]))
+                }
+                Some(last) => {
+                    let node = unsafe { last.node.as_ref() };
+                    let next = node.next[0].load(Ordering::Acquire);
+
+                    if is_marked(next) {
+                        let pos = self.list.find(|k| k <= &node.key);
+                        (!pos.succs[0].is_null()).then(|| Entry::from_ptr(pos.succs[0]))
+                    } else if next.is_null() {
+                        None
+                    } else {
+                        let mut hazard = Hazard::new(self.list.domain);
+                        hazard.protect_raw(next);
+
+                        if node.next[0].load(Ordering::Acquire) != next {
+                            continue;
+                        }
+
+                        Some(Entry {
+                            node: unsafe { NonNull::new_unchecked(next) },
+                            _hazard: hazard,
+                        })
+                    }
+                }
+            };
+
+            let Some(succ) = succ else {
+                self.done = true;
+                break;
+            };
+
+            let in_range = match self.range.end_bound() {
+                Bound::Included(end) => succ.key().cmp(end) != CmpOrdering::Greater,
+                Bound::Excluded(end) => succ.key() < end,
+                Bound::Unbounded => true,
+            };
+
+            if !in_range {
+                self.done = true;
+                break;
+            }
+
+            let removed = unsafe { succ.node.as_ref().is_removed() };
+
+            if removed {
+                self.last = Some(succ);
+                continue;
+            }
+
+            self.last = Some(Entry::from_ptr(succ.node.as_ptr()));
+
+            return Some(succ);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let list = SkipList::new();
+
+        for k in [5, 1, 9, 3, 7] {
+            assert!(list.insert(k, k * 10));
+        }
+
+        assert!(!list.insert(3, 0));
+        assert_eq!(list.len(), 5);
+        assert_eq!(*list.get(&9).unwrap(), 90);
+        assert!(list.get(&4).is_none());
+
+        assert_eq!(*list.remove(&3).unwrap(), 30);
+        assert!(list.remove(&3).is_none());
+        assert!(!list.contains_key(&3));
+        assert_eq!(list.len(), 4);
+    }
+
+    #[test]
+    fn test_range() {
+        let list = SkipList::new();
+
+        (0..100).rev().for_each(|k| {
+            list.insert(k, ());
+        });
+
+        fn keys<R: RangeBounds<i32>>(r: Range<'_, i32, (), R>) -> Vec<i32> {
+            r.map(|e| *e.key()).collect()
+        }
+
+        assert_eq!(keys(list.range(10..15)), [10, 11, 12, 13, 14]);
+        assert_eq!(keys(list.range(97..)), [97, 98, 99]);
+        assert_eq!(keys(list.range(..=2)), [0, 1, 2]);
+        assert_eq!(list.iter().count(), 100);
+    }
+
+    #[test]
+    fn test_iter_during_remove() {
+        let list = SkipList::new();
+
+        (0..10).for_each(|k| {
+            list.insert(k, ());
+        });
+
+        let mut iter = list.iter();
+
+        assert_eq!(*iter.next().unwrap().key(), 0);
+
+        list.remove(&0);
+        list.remove(&1);
+
+        assert_eq!(*iter.next().unwrap().key(), 2);
+    }
+
+    #[test]
+    fn test_drop() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let list = SkipList::new();
+
+        (0..50).for_each(|k| {
+            list.insert(k, DropCounter(counter.clone()));
+        });
+
+        let held = list.remove(&10).unwrap();
+        drop(held);
+        list.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        drop(list);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 50);
+    }
+
+    #[test]
+    fn test_insert_remove_sync() {
+        let list = SkipList::new();
+
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..200 {
+                        let k = (i * 7 + t) % 64;
+                        if i % 2 == 0 {
+                            list.insert(k, t);
+                        } else {
+                            list.remove(&k);
+                        }
+                    }
+                });
+            }
+        });
+
+        let keys = list.iter().map(|e| *e.key()).collect::<Vec<_>>();
+
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(keys.len(), list.len());
+    }
+}
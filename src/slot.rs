@@ -0,0 +1,167 @@
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::base::{DropNode, Entry, Node, NodeRef, UniqueFamily, UNIQUE_FAMILY};
+use crate::hazard::Domain;
+
+/// [Slot](Slot) is a lock free cell holding at most one value. Values that are swapped out are
+/// retired through the same hazard domain as the [Stack](crate::Stack), so [Entries](Entry)
+/// handed out by [peek](Slot::peek) stay valid until they are dropped.
+pub struct Slot<V> {
+    val: AtomicPtr<Node<V>>,
+    domain: &'static Domain<UniqueFamily>,
+}
+
+impl<V> core::fmt::Debug for Slot<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Slot").finish()
+    }
+}
+
+impl<V> Default for Slot<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Slot<V> {
+    pub fn new() -> Self {
+        Slot {
+            val: AtomicPtr::new(null_mut()),
+            domain: &UNIQUE_FAMILY,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.val.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<V> Slot<V>
+where
+    V: Send + Sync,
+{
+    /// Stores `val` in the slot, dropping the previous value once it is no longer protected.
+    pub fn put(&self, val: V) {
+        self.swap(val);
+    }
+
+    /// Removes the current value from the slot, leaving it empty.
+    pub fn take(&self) -> Option<Entry<'_, V>> {
+        self.exchange(null_mut())
+    }
+
+    /// Stores `val` in the slot and returns the value it replaced.
+    pub fn swap(&self, val: V) -> Option<Entry<'_, V>> {
+        self.exchange(Node::new(val))
+    }
+
+    pub fn peek(&self) -> Option<Entry<'_, V>> {
+        NodeRef::from_atomic_ptr(&self.val).map(|n| n.into())
+    }
+
+    fn exchange(&self, new: *mut Node<V>) -> Option<Entry<'_, V>> {
+        let old = self.val.swap(new, Ordering::AcqRel);
+
+        if old.is_null() {
+            return None;
+        }
+
+        // We are the only thread that can retire `old`, so protecting it after the swap is sound.
+        let old = NodeRef::from_ptr(old);
+
+        unsafe {
+            DropNode::retire(self.domain, old.as_ptr());
+            self.domain.eager_reclaim();
+        }
+
+        Some(old.into())
+    }
+}
+
+impl<V> Drop for Slot<V> {
+    fn drop(&mut self) {
+        self.domain.eager_reclaim();
+
+        let curr = self.val.load(Ordering::Acquire);
+
+        // # Safety: We have exclusive ownership of self.
+        if !curr.is_null() {
+            unsafe { Node::drop(curr) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_put_take() {
+        let slot = Slot::new();
+
+        assert!(slot.take().is_none());
+
+        slot.put(1);
+        slot.put(2);
+
+        assert_eq!(*slot.peek().unwrap(), 2);
+        assert_eq!(*slot.take().unwrap(), 2);
+        assert!(slot.is_empty());
+    }
+
+    #[test]
+    fn test_swap() {
+        let slot = Slot::new();
+
+        assert!(slot.swap(1).is_none());
+        assert_eq!(*slot.swap(2).unwrap(), 1);
+        assert_eq!(*slot.peek().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let slot = Slot::new();
+
+        slot.put(DropCounter(counter.clone()));
+
+        let held = slot.peek().unwrap();
+
+        slot.put(DropCounter(counter.clone()));
+
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        drop(held);
+        slot.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        drop(slot);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_swap_sync() {
+        let slot = Slot::new();
+
+        std::thread::scope(|s| {
+            for i in 0..8 {
+                let slot = &slot;
+                s.spawn(move || {
+                    for j in 0..100 {
+                        if let Some(e) = slot.swap(i * 100 + j) {
+                            assert!(*e < 800);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(slot.take().is_some());
+    }
+}
@@ -0,0 +1,218 @@
+//! Contention and reclamation counters, enabled by the `stats` feature.
+//!
+//! Every stack carries a set of [Counters](Counters). Without the feature they are zero sized
+//! and recording into them compiles down to nothing, so the algorithms can record
+//! unconditionally.
+//!
+//! The `metrics` feature reports pushes, pops, their retries, and the depth of every stack through
+//! the [metrics](https://docs.rs/metrics) facade as well, labelled with the name of the stack, so
+//! that whichever recorder the application installs exports them.
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// [Stats](Stats) is a point in time copy of the counters of a stack.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The name of the stack, if it was created [named](crate::Stack::named).
+    pub name: Option<&'static str>,
+    /// Compare exchanges on the head that failed and had to be retried.
+    pub cas_retries: u64,
+    /// Hazard pointers that were acquired to protect a node.
+    pub hazard_acquisitions: u64,
+    /// Nodes handed to the hazard pointer domain for reclamation.
+    pub retired: u64,
+    /// Nodes freed by reclamation passes this stack ran. The domain is shared by all stacks, so
+    /// these may include nodes retired by other stacks.
+    pub reclaimed: u64,
+    /// The number of compare exchange retries each operation needed.
+    pub retries_per_op: Histogram,
+    /// The number of nodes each reclamation pass freed.
+    pub reclaim_batches: Histogram,
+}
+
+/// [Histogram](Histogram) counts values in power of two buckets: bucket `0` counts zeros,
+/// bucket `i` counts values in `2^(i - 1)..2^i`, and the last bucket also counts everything
+/// above.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub buckets: [u64; Histogram::BUCKETS],
+}
+
+#[cfg(feature = "stats")]
+impl Histogram {
+    pub const BUCKETS: usize = 8;
+
+    /// The bucket `value` is counted in.
+    pub fn bucket(value: u64) -> usize {
+        ((u64::BITS - value.leading_zeros()) as usize).min(Self::BUCKETS - 1)
+    }
+
+    /// The total number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+}
+
+#[cfg(feature = "stats")]
+struct AtomicHistogram {
+    buckets: [AtomicU64; Histogram::BUCKETS],
+}
+
+#[cfg(feature = "stats")]
+impl AtomicHistogram {
+    const fn new() -> Self {
+        AtomicHistogram {
+            buckets: [const { AtomicU64::new(0) }; Histogram::BUCKETS],
+        }
+    }
+
+    fn record(&self, value: u64) {
+        self.buckets[Histogram::bucket(value)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Histogram {
+        Histogram {
+            buckets: core::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The `stack` label of stacks that were not created [named](crate::Stack::named).
+#[cfg(feature = "metrics")]
+const UNNAMED: &str = "unnamed";
+
+/// An operation reported through the `metrics` facade.
+#[derive(Clone, Copy)]
+pub(crate) enum Event {
+    Push,
+    Pop,
+}
+
+/// Reports an `event` on the stack called `name`, which retried its compare exchange `retries`
+/// times and left `depth` nodes linked. Compiles down to nothing without the `metrics` feature.
+#[inline(always)]
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn report(name: Option<&'static str>, event: Event, retries: u64, depth: usize) {
+    #[cfg(feature = "metrics")]
+    {
+        let name = name.unwrap_or(UNNAMED);
+
+        match event {
+            Event::Push => metrics::counter!("unlink_pushes_total", "stack" => name).increment(1),
+            Event::Pop => metrics::counter!("unlink_pops_total", "stack" => name).increment(1),
+        }
+
+        if retries > 0 {
+            metrics::counter!("unlink_cas_retries_total", "stack" => name).increment(retries);
+        }
+
+        metrics::gauge!("unlink_depth", "stack" => name).set(depth as f64);
+    }
+}
+
+pub(crate) struct Counters {
+    #[cfg(feature = "stats")]
+    cas_retries: AtomicU64,
+    #[cfg(feature = "stats")]
+    hazard_acquisitions: AtomicU64,
+    #[cfg(feature = "stats")]
+    retired: AtomicU64,
+    #[cfg(feature = "stats")]
+    reclaimed: AtomicU64,
+    #[cfg(feature = "stats")]
+    retries_per_op: AtomicHistogram,
+    #[cfg(feature = "stats")]
+    reclaim_batches: AtomicHistogram,
+}
+
+#[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Counters {
+            #[cfg(feature = "stats")]
+            cas_retries: AtomicU64::new(0),
+            #[cfg(feature = "stats")]
+            hazard_acquisitions: AtomicU64::new(0),
+            #[cfg(feature = "stats")]
+            retired: AtomicU64::new(0),
+            #[cfg(feature = "stats")]
+            reclaimed: AtomicU64::new(0),
+            #[cfg(feature = "stats")]
+            retries_per_op: AtomicHistogram::new(),
+            #[cfg(feature = "stats")]
+            reclaim_batches: AtomicHistogram::new(),
+        }
+    }
+
+    /// Records an operation that retried its compare exchange `retries` times.
+    #[inline(always)]
+    pub(crate) fn operation(&self, retries: u64) {
+        #[cfg(feature = "stats")]
+        {
+            self.cas_retries.fetch_add(retries, Ordering::Relaxed);
+            self.retries_per_op.record(retries);
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn hazard_acquired(&self) {
+        #[cfg(feature = "stats")]
+        self.hazard_acquisitions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn retired(&self, nodes: usize) {
+        #[cfg(feature = "stats")]
+        self.retired.fetch_add(nodes as u64, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn reclaimed(&self, nodes: usize) {
+        #[cfg(feature = "stats")]
+        {
+            self.reclaimed.fetch_add(nodes as u64, Ordering::Relaxed);
+            self.reclaim_batches.record(nodes as u64);
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            name: None,
+            cas_retries: self.cas_retries.load(Ordering::Relaxed),
+            hazard_acquisitions: self.hazard_acquisitions.load(Ordering::Relaxed),
+            retired: self.retired.load(Ordering::Relaxed),
+            reclaimed: self.reclaimed.load(Ordering::Relaxed),
+            retries_per_op: self.retries_per_op.snapshot(),
+            reclaim_batches: self.reclaim_batches.snapshot(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        let buckets = [0, 1, 2, 3, 4, 7, 8, 63, 64, u64::MAX].map(Histogram::bucket);
+
+        assert_eq!(buckets, [0, 1, 2, 2, 3, 3, 4, 6, 7, 7]);
+    }
+
+    #[test]
+    fn test_record() {
+        let histogram = AtomicHistogram::new();
+
+        [0, 0, 1, 5, 100]
+            .into_iter()
+            .for_each(|v| histogram.record(v));
+
+        let snapshot = histogram.snapshot();
+
+        assert_eq!(snapshot.buckets, [2, 1, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(snapshot.count(), 5);
+    }
+}
@@ -0,0 +1,1529 @@
+//! Reusable randomized-operation machinery for exercising a [Stack](crate::Stack) from several
+//! threads, as done by the bundled fuzz targets.
+//! ```
+//! use unlink::{testing, Operation};
+//!
+//! let ops = (0..100)
+//!     .map(|i| match i % 3 {
+//!         0 => Operation::Pop,
+//!         _ => Operation::Push { item: i },
+//!     })
+//!     .collect();
+//!
+//! let report = testing::execute(ops, 4);
+//!
+//! assert!(report.is_conserved());
+//! ```
+//!
+//! [ModelStack](ModelStack) is a sequential reference implementation with the same API, for
+//! running the same operations against both and comparing the results.
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{ConcurrentStack, MultiOperation, Operation, Stack};
+
+/// The observable result of a single [Operation](Operation).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<T> {
+    Pushed(T),
+    Popped(Option<T>),
+    /// The value that was popped and pushed back, if the stack was not empty.
+    PopPushed(Option<T>),
+    Appended(Vec<T>),
+    Peeked(Option<T>),
+    Len(usize),
+}
+
+/// [Report](Report) records what every thread observed while running its share of the
+/// operations, along with whatever was left on the stack afterwards.
+#[derive(Clone, Debug)]
+pub struct Report<T> {
+    /// For every thread, the index of each operation it ran in the original list and its outcome.
+    pub threads: Vec<Vec<(usize, Outcome<T>)>>,
+    /// The contents of the stack after all threads finished, top first.
+    pub remaining: Vec<T>,
+}
+
+impl<T> Report<T> {
+    pub fn outcomes(&self) -> impl Iterator<Item = &(usize, Outcome<T>)> {
+        self.threads.iter().flatten()
+    }
+
+    /// Checks that every value that went onto the stack was either popped exactly once or is
+    /// still on it.
+    pub fn is_conserved(&self) -> bool
+    where
+        T: Ord + Clone,
+    {
+        let mut pushed = Vec::new();
+        let mut taken = self.remaining.clone();
+
+        for (_, outcome) in self.outcomes() {
+            match outcome {
+                Outcome::Pushed(item) => pushed.push(item.clone()),
+                Outcome::Appended(items) => pushed.extend(items.iter().cloned()),
+                Outcome::Popped(Some(item)) => taken.push(item.clone()),
+                Outcome::Popped(None)
+                | Outcome::PopPushed(_)
+                | Outcome::Peeked(_)
+                | Outcome::Len(_) => {}
+            }
+        }
+
+        pushed.sort();
+        taken.sort();
+
+        pushed == taken
+    }
+}
+
+/// Runs `op` against `stack` and records its outcome.
+pub fn apply<S, T>(stack: &S, op: Operation<T>) -> Outcome<T>
+where
+    S: ConcurrentStack<T> + FromIterator<T>,
+    T: Clone,
+{
+    match op {
+        Operation::Push { item } => {
+            let _ = stack.push(item.clone());
+            Outcome::Pushed(item)
+        }
+        Operation::Pop => Outcome::Popped(stack.pop().map(|e| (*e).clone())),
+        Operation::PopPush => {
+            let popped = stack.pop().map(|e| (*e).clone());
+            if let Some(item) = &popped {
+                let _ = stack.push(item.clone());
+            }
+            Outcome::PopPushed(popped)
+        }
+        Operation::Append { items } => {
+            stack.append(items.iter().cloned().collect());
+            Outcome::Appended(items)
+        }
+        Operation::Peek => Outcome::Peeked(stack.peek().map(|e| (*e).clone())),
+        Operation::Len => Outcome::Len(stack.len()),
+    }
+}
+
+/// Splits `ops` into `threads` contiguous chunks and runs each chunk on its own thread against
+/// a fresh stack.
+pub fn execute<T>(ops: Vec<Operation<T>>, threads: usize) -> Report<T>
+where
+    T: Send + Sync + Clone,
+{
+    let stack = Stack::new();
+    let threads = execute_on(&stack, ops, threads);
+
+    Report {
+        threads,
+        remaining: stack.into_iter().collect(),
+    }
+}
+
+/// Like [execute](execute), but runs against an existing stack and returns only the per-thread
+/// outcomes.
+pub fn execute_on<T>(
+    stack: &Stack<T>,
+    ops: Vec<Operation<T>>,
+    threads: usize,
+) -> Vec<Vec<(usize, Outcome<T>)>>
+where
+    T: Send + Sync + Clone,
+{
+    run_chunks(ops, threads, |i, op| (i, apply(stack, op)))
+}
+
+/// The observable result of a single [MultiOperation](MultiOperation), with stack indices
+/// already reduced to the number of stacks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MultiOutcome<T> {
+    On {
+        stack: usize,
+        outcome: Outcome<T>,
+    },
+    /// The number of values a transfer reported as moved.
+    Transferred {
+        from: usize,
+        to: usize,
+        moved: usize,
+    },
+    Extended {
+        from: usize,
+        to: usize,
+    },
+}
+
+/// [MultiReport](MultiReport) is the [Report](Report) of operations run against several stacks.
+#[derive(Clone, Debug)]
+pub struct MultiReport<T> {
+    /// For every thread, the index of each operation it ran in the original list and its outcome.
+    pub threads: Vec<Vec<(usize, MultiOutcome<T>)>>,
+    /// The contents of every stack after all threads finished, top first.
+    pub remaining: Vec<Vec<T>>,
+}
+
+impl<T> MultiReport<T> {
+    pub fn outcomes(&self) -> impl Iterator<Item = &(usize, MultiOutcome<T>)> {
+        self.threads.iter().flatten()
+    }
+
+    /// Checks that every value that went onto one of the stacks was either popped exactly once
+    /// or is still on one of them, however it moved between them.
+    pub fn is_conserved(&self) -> bool
+    where
+        T: Ord + Clone,
+    {
+        let single = Report {
+            threads: vec![self
+                .outcomes()
+                .filter_map(|(i, outcome)| match outcome {
+                    MultiOutcome::On { outcome, .. } => Some((*i, outcome.clone())),
+                    _ => None,
+                })
+                .collect()],
+            remaining: self.remaining.iter().flatten().cloned().collect(),
+        };
+
+        single.is_conserved()
+    }
+}
+
+/// Runs `op` against `stacks` and records its outcome.
+pub fn apply_multi<T>(stacks: &[Stack<T>], op: MultiOperation<T>) -> MultiOutcome<T>
+where
+    T: Send + Sync + Clone,
+{
+    let index = |i: u8| i as usize % stacks.len();
+
+    match op {
+        MultiOperation::On { stack, op } => MultiOutcome::On {
+            stack: index(stack),
+            outcome: apply(&stacks[index(stack)], op),
+        },
+        MultiOperation::Transfer { from, to } => {
+            let (from, to) = (index(from), index(to));
+            let moved = crate::txn::transfer(&stacks[from], &stacks[to]) as usize;
+
+            MultiOutcome::Transferred { from, to, moved }
+        }
+        MultiOperation::TransferN { from, to, n } => {
+            let (from, to) = (index(from), index(to));
+            let moved = stacks[from].transfer_n(&stacks[to], n as usize);
+
+            MultiOutcome::Transferred { from, to, moved }
+        }
+        MultiOperation::Extend { from, to } => {
+            let (from, to) = (index(from), index(to));
+            stacks[to].append(stacks[from].extract_if(|_| true));
+
+            MultiOutcome::Extended { from, to }
+        }
+    }
+}
+
+/// Like [execute](execute), but runs the operations against `stacks` fresh stacks, at least one.
+pub fn execute_multi<T>(
+    ops: Vec<MultiOperation<T>>,
+    stacks: usize,
+    threads: usize,
+) -> MultiReport<T>
+where
+    T: Send + Sync + Clone,
+{
+    let stacks = (0..stacks.max(1)).map(|_| Stack::new()).collect::<Vec<_>>();
+    let threads = run_chunks(ops, threads, |i, op| (i, apply_multi(&stacks, op)));
+
+    MultiReport {
+        threads,
+        remaining: stacks
+            .into_iter()
+            .map(|stack| stack.into_iter().collect())
+            .collect(),
+    }
+}
+
+/// Splits `ops` into `threads` contiguous chunks and runs `f` on every operation of a chunk on
+/// that chunk's thread, in order.
+fn run_chunks<O, R, F>(ops: Vec<O>, threads: usize, f: F) -> Vec<Vec<R>>
+where
+    O: Send,
+    R: Send,
+    F: Fn(usize, O) -> R + Sync,
+{
+    let chunk = core::cmp::max(ops.len().div_ceil(threads.max(1)), 1);
+
+    let mut chunks: Vec<Vec<(usize, O)>> = Vec::new();
+    for (i, op) in ops.into_iter().enumerate() {
+        if i % chunk == 0 {
+            chunks.push(Vec::with_capacity(chunk));
+        }
+        chunks.last_mut().unwrap().push((i, op));
+    }
+
+    let f = &f;
+
+    std::thread::scope(|s| {
+        let handles = chunks
+            .into_iter()
+            .map(|ops| s.spawn(move || ops.into_iter().map(|(i, op)| f(i, op)).collect()))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// [Event](Event) is a single operation of a [History](History), stamped from a shared logical
+/// clock when it was invoked and when it returned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event<T> {
+    pub thread: usize,
+    /// The index of the operation in the original list.
+    pub index: usize,
+    pub invoked: u64,
+    pub responded: u64,
+    pub outcome: Outcome<T>,
+}
+
+/// [History](History) is a timestamped trace of a concurrent execution that can be checked for
+/// linearizability against a sequential stack.
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    pub events: Vec<Event<T>>,
+    /// The contents of the stack after all threads finished, top first.
+    pub remaining: Vec<T>,
+}
+
+/// Like [execute](execute), but records when every operation was invoked and when it returned.
+/// A [PopPush](Operation::PopPush) is recorded as a pop followed by a push.
+pub fn execute_traced<T>(ops: Vec<Operation<T>>, threads: usize) -> History<T>
+where
+    T: Send + Sync + Clone,
+{
+    let stack = Stack::new();
+    let clock = AtomicU64::new(0);
+
+    let traced = |index, op: Operation<T>| {
+        let invoked = clock.fetch_add(1, Ordering::SeqCst);
+        let outcome = apply(&stack, op);
+        let responded = clock.fetch_add(1, Ordering::SeqCst);
+
+        (index, invoked, responded, outcome)
+    };
+
+    // Pop-push is two separate steps on the stack, so it is traced as two events.
+    let traces = run_chunks(ops, threads, |index, op| match op {
+        Operation::PopPush => {
+            let pop = traced(index, Operation::Pop);
+            match &pop.3 {
+                Outcome::Popped(Some(item)) => {
+                    let item = item.clone();
+                    vec![pop, traced(index, Operation::Push { item })]
+                }
+                _ => vec![pop],
+            }
+        }
+        op => vec![traced(index, op)],
+    });
+
+    let events = traces
+        .into_iter()
+        .enumerate()
+        .flat_map(|(thread, trace)| {
+            trace
+                .into_iter()
+                .flatten()
+                .map(move |(index, invoked, responded, outcome)| Event {
+                    thread,
+                    index,
+                    invoked,
+                    responded,
+                    outcome,
+                })
+        })
+        .collect();
+
+    History {
+        events,
+        remaining: stack.into_iter().collect(),
+    }
+}
+
+impl<T> History<T>
+where
+    T: Clone + Eq + Hash,
+{
+    /// Searches for a sequential order of the events that respects their real-time order and
+    /// in which every outcome matches what a sequential stack would have produced, ending in
+    /// the remaining contents.
+    ///
+    /// The search is exponential in the worst case and meant for histories of at most a few
+    /// hundred operations. A [Len](Outcome::Len) outcome may be off from the length of the model
+    /// by as many values as the events overlapping it push or pop, as
+    /// [ConcurrentStack::len](ConcurrentStack::len) allows.
+    pub fn is_linearizable(&self) -> bool {
+        let mut done = vec![false; self.events.len()];
+        let mut seen = HashSet::new();
+
+        self.linearize(&mut done, Vec::new(), &mut seen)
+    }
+
+    fn linearize(
+        &self,
+        done: &mut Vec<bool>,
+        model: Vec<T>,
+        seen: &mut HashSet<(Vec<bool>, Vec<T>)>,
+    ) -> bool {
+        let pending = (0..self.events.len()).filter(|&i| !done[i]);
+
+        let Some(first_response) = pending.clone().map(|i| self.events[i].responded).min() else {
+            return model.iter().rev().eq(self.remaining.iter());
+        };
+
+        if !seen.insert((done.clone(), model.clone())) {
+            return false;
+        }
+
+        let candidates = pending
+            .filter(|&i| self.events[i].invoked < first_response)
+            .collect::<Vec<_>>();
+
+        for i in candidates {
+            if let Some(next) = step(&model, &self.events[i].outcome, self.slack(i)) {
+                done[i] = true;
+
+                if self.linearize(done, next, seen) {
+                    return true;
+                }
+
+                done[i] = false;
+            }
+        }
+
+        false
+    }
+
+    /// How many values the events overlapping event `i` push or pop, each of which its length
+    /// may or may not count yet.
+    fn slack(&self, i: usize) -> usize {
+        let event = &self.events[i];
+
+        self.events
+            .iter()
+            .enumerate()
+            .filter(|&(j, other)| {
+                j != i && other.invoked < event.responded && event.invoked < other.responded
+            })
+            .map(|(_, other)| match &other.outcome {
+                Outcome::Pushed(_) | Outcome::Popped(Some(_)) => 1,
+                Outcome::PopPushed(Some(_)) => 2,
+                Outcome::Appended(items) => items.len(),
+                Outcome::Popped(None)
+                | Outcome::PopPushed(None)
+                | Outcome::Peeked(_)
+                | Outcome::Len(_) => 0,
+            })
+            .sum()
+    }
+}
+
+/// Applies `outcome` to the sequential `model`, bottom first, if it is consistent with it. A
+/// [Len](Outcome::Len) outcome may be off by up to `slack`.
+fn step<T: Clone + Eq>(model: &[T], outcome: &Outcome<T>, slack: usize) -> Option<Vec<T>> {
+    let mut model = model.to_vec();
+
+    match outcome {
+        Outcome::Pushed(item) => model.push(item.clone()),
+        Outcome::Popped(item) => {
+            if model.last() != item.as_ref() {
+                return None;
+            }
+            model.pop();
+        }
+        Outcome::Appended(items) => model.extend(items.iter().cloned()),
+        Outcome::PopPushed(item) | Outcome::Peeked(item) => {
+            if model.last() != item.as_ref() {
+                return None;
+            }
+        }
+        Outcome::Len(len) => {
+            if len.abs_diff(model.len()) > slack {
+                return None;
+            }
+        }
+    }
+
+    Some(model)
+}
+
+impl<T: Clone> Outcome<T> {
+    /// The operation that produced this outcome.
+    pub fn operation(&self) -> Operation<T> {
+        match self {
+            Outcome::Pushed(item) => Operation::Push { item: item.clone() },
+            Outcome::Popped(_) => Operation::Pop,
+            Outcome::PopPushed(_) => Operation::PopPush,
+            Outcome::Appended(items) => Operation::Append {
+                items: items.clone(),
+            },
+            Outcome::Peeked(_) => Operation::Peek,
+            Outcome::Len(_) => Operation::Len,
+        }
+    }
+}
+
+impl<T: Clone> History<T> {
+    /// The interleaving this history was recorded with, ordered by invocation.
+    pub fn schedule(&self) -> Schedule<T> {
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by_key(|e| e.invoked);
+
+        Schedule {
+            steps: events
+                .into_iter()
+                .map(|e| Step {
+                    thread: e.thread,
+                    yields: 0,
+                    op: e.outcome.operation(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// [Handoff](Handoff) passes the turn of a [replay](Schedule::replay) on to the next step once
+/// dropped, even if the step panicked, so that the other threads do not wait for it forever.
+struct Handoff<'a> {
+    turn: &'a AtomicUsize,
+    next: usize,
+}
+
+impl Drop for Handoff<'_> {
+    fn drop(&mut self) {
+        self.turn.store(self.next, Ordering::Release);
+    }
+}
+
+/// [Step](Step) assigns one operation of a [Schedule](Schedule) to a thread. Once it is its turn,
+/// the thread hands the turn on and yields `yields` times before running the operation, so that
+/// the steps after it may run first.
+#[derive(Clone, Debug)]
+pub struct Step<T> {
+    pub thread: usize,
+    pub yields: usize,
+    pub op: Operation<T>,
+}
+
+/// [Schedule](Schedule) is a multi-threaded interleaving of operations that can be written out,
+/// read back, [replayed](Schedule::replay) deterministically, and [shrunk](Schedule::shrink).
+///
+/// Schedules are written one step per line as the thread followed by the operation:
+/// ```text
+/// 0 push 4
+/// 1 yield 2 append 1,2,3
+/// 0 pop
+/// 2 poppush
+/// 1 peek
+/// 2 len
+/// ```
+#[derive(Clone, Debug)]
+pub struct Schedule<T> {
+    pub steps: Vec<Step<T>>,
+}
+
+impl<T> Schedule<T> {
+    /// Deals `ops` out to `threads` threads round-robin.
+    pub fn round_robin(ops: Vec<Operation<T>>, threads: usize) -> Self {
+        Schedule {
+            steps: ops
+                .into_iter()
+                .enumerate()
+                .map(|(i, op)| Step {
+                    thread: i % threads.max(1),
+                    yields: 0,
+                    op,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.steps.iter().map(|s| s.thread + 1).max().unwrap_or(0)
+    }
+
+    /// Runs the steps against a fresh stack, each on its own thread but strictly one after the
+    /// other in schedule order, so every replay of a schedule without yields produces the same
+    /// [Report](Report). A step that yields races the steps after it instead. Outcomes are
+    /// indexed by their step.
+    pub fn replay(&self) -> Report<T>
+    where
+        T: Send + Sync + Clone,
+    {
+        let stack = Stack::new();
+        let turn = AtomicUsize::new(0);
+
+        let threads = std::thread::scope(|s| {
+            let handles = (0..self.threads())
+                .map(|thread| {
+                    let (stack, turn) = (&stack, &turn);
+                    s.spawn(move || {
+                        let mut outcomes = Vec::new();
+
+                        for (i, step) in self.steps.iter().enumerate() {
+                            if step.thread != thread {
+                                continue;
+                            }
+
+                            while turn.load(Ordering::Acquire) != i {
+                                std::thread::yield_now();
+                            }
+
+                            let handoff = Handoff { turn, next: i + 1 };
+
+                            if step.yields > 0 {
+                                drop(handoff);
+                                (0..step.yields).for_each(|_| std::thread::yield_now());
+                                outcomes.push((i, apply(stack, step.op.clone())));
+                            } else {
+                                outcomes.push((i, apply(stack, step.op.clone())));
+                                drop(handoff);
+                            }
+                        }
+
+                        outcomes
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        Report {
+            threads,
+            remaining: stack.into_iter().collect(),
+        }
+    }
+
+    /// Greedily removes steps, moves steps onto thread 0, and drops injected yields and appended
+    /// items for as long as `fails` keeps holding, returning a locally minimal schedule.
+    pub fn shrink(mut self, fails: impl Fn(&Self) -> bool) -> Self
+    where
+        T: Clone,
+    {
+        assert!(fails(&self), "only failing schedules can be shrunk");
+
+        loop {
+            let mut progress = false;
+
+            // Remove runs of steps, from half the schedule down to single steps.
+            let mut chunk = self.steps.len().div_ceil(2);
+            while chunk > 0 {
+                let mut start = 0;
+                while start < self.steps.len() {
+                    let mut candidate = self.clone();
+                    let end = (start + chunk).min(candidate.steps.len());
+                    candidate.steps.drain(start..end);
+
+                    if fails(&candidate) {
+                        self = candidate;
+                        progress = true;
+                    } else {
+                        start += chunk;
+                    }
+                }
+                chunk /= 2;
+            }
+
+            for i in 0..self.steps.len() {
+                if self.steps[i].thread != 0 {
+                    let mut candidate = self.clone();
+                    candidate.steps[i].thread = 0;
+
+                    if fails(&candidate) {
+                        self = candidate;
+                        progress = true;
+                    }
+                }
+
+                if self.steps[i].yields != 0 {
+                    let mut candidate = self.clone();
+                    candidate.steps[i].yields = 0;
+
+                    if fails(&candidate) {
+                        self = candidate;
+                        progress = true;
+                    }
+                }
+
+                let mut j = 0;
+                while let Operation::Append { items } = &self.steps[i].op {
+                    if j >= items.len() {
+                        break;
+                    }
+
+                    let mut candidate = self.clone();
+                    if let Operation::Append { items } = &mut candidate.steps[i].op {
+                        items.remove(j);
+                    }
+
+                    if fails(&candidate) {
+                        self = candidate;
+                        progress = true;
+                    } else {
+                        j += 1;
+                    }
+                }
+            }
+
+            if !progress {
+                return self;
+            }
+        }
+    }
+}
+
+impl<T: core::fmt::Display> core::fmt::Display for Schedule<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for Step { thread, yields, op } in &self.steps {
+            write!(f, "{thread} ")?;
+
+            if *yields > 0 {
+                write!(f, "yield {yields} ")?;
+            }
+
+            match op {
+                Operation::Push { item } => writeln!(f, "push {item}")?,
+                Operation::Pop => writeln!(f, "pop")?,
+                Operation::PopPush => writeln!(f, "poppush")?,
+                Operation::Append { items } => {
+                    write!(f, "append ")?;
+                    for (i, item) in items.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ",")?;
+                        }
+                        write!(f, "{item}")?;
+                    }
+                    writeln!(f)?;
+                }
+                Operation::Peek => writeln!(f, "peek")?,
+                Operation::Len => writeln!(f, "len")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The error returned when a [Schedule](Schedule) cannot be parsed, carrying the offending line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseScheduleError {
+    pub line: usize,
+}
+
+impl core::fmt::Display for ParseScheduleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid schedule step on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseScheduleError {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ParseScheduleError {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "invalid schedule step on line {=usize}", self.line);
+    }
+}
+
+impl<T: core::str::FromStr> core::str::FromStr for Schedule<T> {
+    type Err = ParseScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let steps = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let err = ParseScheduleError { line: i + 1 };
+                let mut words = line.split_whitespace();
+
+                let thread = words.next().and_then(|w| w.parse().ok()).ok_or(err.clone())?;
+
+                let mut words = words.peekable();
+                let yields = match words.next_if_eq(&"yield") {
+                    Some(_) => words.next().and_then(|w| w.parse().ok()).ok_or(err.clone())?,
+                    None => 0,
+                };
+
+                let op = match (words.next(), words.next()) {
+                    (Some("push"), Some(item)) => Operation::Push {
+                        item: item.parse().map_err(|_| err.clone())?,
+                    },
+                    (Some("append"), items) => Operation::Append {
+                        items: items
+                            .into_iter()
+                            .flat_map(|items| items.split(','))
+                            .map(|item| item.parse().map_err(|_| err.clone()))
+                            .collect::<Result<_, _>>()?,
+                    },
+                    (Some("pop"), None) => Operation::Pop,
+                    (Some("poppush"), None) => Operation::PopPush,
+                    (Some("peek"), None) => Operation::Peek,
+                    (Some("len"), None) => Operation::Len,
+                    _ => return Err(err),
+                };
+
+                if words.next().is_some() {
+                    return Err(err);
+                }
+
+                Ok(Step { thread, yields, op })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Schedule { steps })
+    }
+}
+
+/// [Weights](Weights) sets how likely each kind of [Operation](Operation) is to be generated from
+/// fuzzer input, in proportion to the other weights. The derived `Arbitrary` implementation picks
+/// every kind equally often, which rarely builds deep stacks, or keeps a stack near empty for long.
+/// ```
+/// use arbitrary::Unstructured;
+/// use unlink::testing::{self, Weights};
+///
+/// // Mostly pushes, for deep stacks.
+/// let weights = Weights {
+///     push: 7,
+///     pop: 2,
+///     append: 1,
+///     ..Weights::NONE
+/// };
+///
+/// let mut u = Unstructured::new(&[0x5a; 64]);
+/// let ops = weights.operations::<i32>(&mut u).unwrap();
+///
+/// assert!(testing::execute(ops, 4).is_conserved());
+/// ```
+#[cfg(feature = "arbitrary")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    pub push: u32,
+    pub pop: u32,
+    pub pop_push: u32,
+    pub append: u32,
+    pub peek: u32,
+    pub len: u32,
+}
+
+#[cfg(feature = "arbitrary")]
+impl Default for Weights {
+    /// Every kind of operation equally likely, as with the derived `Arbitrary` implementation.
+    fn default() -> Self {
+        Weights {
+            push: 1,
+            pop: 1,
+            pop_push: 1,
+            append: 1,
+            peek: 1,
+            len: 1,
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl Weights {
+    /// No operation at all, to be overridden for the kinds that should be generated.
+    pub const NONE: Weights = Weights {
+        push: 0,
+        pop: 0,
+        pop_push: 0,
+        append: 0,
+        peek: 0,
+        len: 0,
+    };
+
+    /// Generates one operation, failing if all weights are zero.
+    pub fn operation<'a, T>(
+        &self,
+        u: &mut arbitrary::Unstructured<'a>,
+    ) -> arbitrary::Result<Operation<T>>
+    where
+        T: arbitrary::Arbitrary<'a>,
+    {
+        let weights = [
+            self.push,
+            self.pop,
+            self.pop_push,
+            self.append,
+            self.peek,
+            self.len,
+        ];
+        let total = weights.iter().map(|&w| w as u64).sum::<u64>();
+
+        if total == 0 {
+            return Err(arbitrary::Error::IncorrectFormat);
+        }
+
+        let mut pick = u.int_in_range(0..=total - 1)?;
+        let kind = weights
+            .iter()
+            .position(|&w| match pick.checked_sub(w as u64) {
+                Some(rest) => {
+                    pick = rest;
+                    false
+                }
+                None => true,
+            })
+            .unwrap_or_default();
+
+        Ok(match kind {
+            0 => Operation::Push {
+                item: u.arbitrary()?,
+            },
+            1 => Operation::Pop,
+            2 => Operation::PopPush,
+            3 => Operation::Append {
+                items: u.arbitrary()?,
+            },
+            4 => Operation::Peek,
+            _ => Operation::Len,
+        })
+    }
+
+    /// Generates as many operations as the input calls for, as the `Arbitrary` implementation of
+    /// a `Vec` would.
+    pub fn operations<'a, T>(
+        &self,
+        u: &mut arbitrary::Unstructured<'a>,
+    ) -> arbitrary::Result<Vec<Operation<T>>>
+    where
+        T: arbitrary::Arbitrary<'a>,
+    {
+        // Generating an operation consumes no input when only one weight is set, so the input
+        // running out cannot bound the number of them.
+        let len = u.arbitrary_len::<Operation<T>>()?;
+
+        (0..len).map(|_| self.operation(u)).collect()
+    }
+}
+
+/// [StackStateMachine](StackStateMachine) plugs a [Stack](Stack) into `proptest-state-machine`,
+/// with the `proptest` feature. As the reference state machine it generates
+/// [Operations](Operation) and tracks the values they leave on a `Vec`, along with the outcome
+/// each should have, and as the state machine test it runs them against a [Stack](Stack) and
+/// checks every outcome and the top of the stack. Failing sequences shrink to the fewest
+/// operations that still fail.
+/// ```
+/// use proptest_state_machine::prop_state_machine;
+/// use unlink::testing::StackStateMachine;
+///
+/// type U8Stack = StackStateMachine<u8>;
+///
+/// prop_state_machine! {
+///     fn stack_matches_vec(sequential 1..32 => U8Stack);
+/// }
+///
+/// stack_matches_vec();
+/// ```
+#[cfg(feature = "proptest")]
+pub struct StackStateMachine<T>(PhantomData<T>);
+
+/// The state of [StackStateMachine](StackStateMachine)'s reference: the values on the stack,
+/// bottom first, and the outcome the last operation should have had.
+#[cfg(feature = "proptest")]
+#[derive(Clone, Debug)]
+pub struct StackState<T> {
+    pub values: Vec<T>,
+    /// `None` before the first operation.
+    pub outcome: Option<Outcome<T>>,
+}
+
+#[cfg(feature = "proptest")]
+impl<T> proptest_state_machine::ReferenceStateMachine for StackStateMachine<T>
+where
+    T: proptest::arbitrary::Arbitrary + Clone + 'static,
+{
+    type State = StackState<T>;
+    type Transition = Operation<T>;
+
+    fn init_state() -> proptest::strategy::BoxedStrategy<Self::State> {
+        use proptest::strategy::{Just, Strategy};
+
+        Just(StackState {
+            values: Vec::new(),
+            outcome: None,
+        })
+        .boxed()
+    }
+
+    fn transitions(_: &Self::State) -> proptest::strategy::BoxedStrategy<Self::Transition> {
+        use proptest::prelude::*;
+
+        prop_oneof![
+            any::<T>().prop_map(|item| Operation::Push { item }),
+            Just(Operation::Pop),
+            Just(Operation::PopPush),
+            proptest::collection::vec(any::<T>(), 0..8)
+                .prop_map(|items| Operation::Append { items }),
+            Just(Operation::Peek),
+            Just(Operation::Len),
+        ]
+        .boxed()
+    }
+
+    fn apply(mut state: Self::State, transition: &Self::Transition) -> Self::State {
+        let values = &mut state.values;
+
+        state.outcome = match transition.clone() {
+            Operation::Push { item } => {
+                values.push(item.clone());
+                Some(Outcome::Pushed(item))
+            }
+            Operation::Pop => Some(Outcome::Popped(values.pop())),
+            Operation::PopPush => Some(Outcome::PopPushed(values.last().cloned())),
+            Operation::Append { items } => {
+                values.extend(items.iter().cloned());
+                Some(Outcome::Appended(items))
+            }
+            Operation::Peek => Some(Outcome::Peeked(values.last().cloned())),
+            Operation::Len => Some(Outcome::Len(values.len())),
+        };
+
+        state
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl<T> proptest_state_machine::StateMachineTest for StackStateMachine<T>
+where
+    T: proptest::arbitrary::Arbitrary + Clone + PartialEq + Send + Sync + 'static,
+{
+    type SystemUnderTest = Stack<T>;
+    type Reference = Self;
+
+    fn init_test(_: &StackState<T>) -> Self::SystemUnderTest {
+        Stack::new()
+    }
+
+    fn apply(
+        stack: Self::SystemUnderTest,
+        ref_state: &StackState<T>,
+        transition: Operation<T>,
+    ) -> Self::SystemUnderTest {
+        let outcome = crate::testing::apply(&stack, transition);
+
+        if let Some(expected) = &ref_state.outcome {
+            assert_eq!(&outcome, expected);
+        }
+
+        stack
+    }
+
+    fn check_invariants(stack: &Self::SystemUnderTest, ref_state: &StackState<T>) {
+        assert_eq!(
+            stack.peek().map(|e| (*e).clone()),
+            ref_state.values.last().cloned()
+        );
+    }
+}
+
+/// [ModelStack](ModelStack) is a mutex guarded `Vec` with the same API as [Stack](Stack), to
+/// serve as the reference in differential tests.
+pub struct ModelStack<V> {
+    items: Mutex<Vec<Arc<V>>>,
+}
+
+impl<V> core::fmt::Debug for ModelStack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModelStack").finish()
+    }
+}
+
+impl<V> Default for ModelStack<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> ModelStack<V> {
+    pub fn new() -> Self {
+        ModelStack {
+            items: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn items(&self) -> MutexGuard<'_, Vec<Arc<V>>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn len(&self) -> usize {
+        self.items().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items().is_empty()
+    }
+
+    pub fn push(&self, val: V) {
+        self.items().push(Arc::new(val));
+    }
+
+    pub fn pop(&self) -> Option<ModelEntry<'_, V>> {
+        self.items().pop().map(ModelEntry::new)
+    }
+
+    pub fn peek(&self) -> Option<ModelEntry<'_, V>> {
+        self.items().last().cloned().map(ModelEntry::new)
+    }
+
+    pub fn append(&self, other: Self) {
+        let other = other.items.into_inner().unwrap_or_else(|e| e.into_inner());
+        self.items().extend(other);
+    }
+}
+
+impl<V> ConcurrentStack<V> for ModelStack<V> {
+    type Entry<'a> = ModelEntry<'a, V> where Self: 'a;
+
+    fn push(&self, val: V) -> Result<(), V> {
+        ModelStack::push(self, val);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Self::Entry<'_>> {
+        ModelStack::pop(self)
+    }
+
+    fn peek(&self) -> Option<Self::Entry<'_>> {
+        ModelStack::peek(self)
+    }
+
+    fn append(&self, other: Self) {
+        ModelStack::append(self, other)
+    }
+
+    fn len(&self) -> usize {
+        ModelStack::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        ModelStack::is_empty(self)
+    }
+}
+
+impl<V> IntoIterator for ModelStack<V> {
+    type Item = V;
+    type IntoIter = std::iter::Map<std::iter::Rev<std::vec::IntoIter<Arc<V>>>, fn(Arc<V>) -> V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let items = self.items.into_inner().unwrap_or_else(|e| e.into_inner());
+
+        // Entries borrow the stack, so none can outlive it and every count is unique here.
+        items
+            .into_iter()
+            .rev()
+            .map(|item| Arc::try_unwrap(item).ok().expect("entry outlived its stack"))
+    }
+}
+
+impl<V> FromIterator<V> for ModelStack<V> {
+    fn from_iter<T: IntoIterator<Item = V>>(iter: T) -> Self {
+        ModelStack {
+            items: Mutex::new(iter.into_iter().map(Arc::new).collect()),
+        }
+    }
+}
+
+/// [ModelEntry](ModelEntry) is the value returned by [ModelStack::pop](ModelStack::pop) and
+/// [ModelStack::peek](ModelStack::peek), dereferencing to the element like a stack `Entry`.
+pub struct ModelEntry<'a, V> {
+    val: Arc<V>,
+    _stack: PhantomData<&'a ModelStack<V>>,
+}
+
+impl<'a, V> ModelEntry<'a, V> {
+    fn new(val: Arc<V>) -> Self {
+        ModelEntry {
+            val,
+            _stack: PhantomData,
+        }
+    }
+}
+
+impl<'a, V> core::ops::Deref for ModelEntry<'a, V> {
+    type Target = V;
+
+    fn deref(&self) -> &Self::Target {
+        &self.val
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_weights() {
+        let mut u = arbitrary::Unstructured::new(&[0xa5; 32]);
+
+        let pops = Weights {
+            pop: 1,
+            ..Weights::NONE
+        };
+        let ops = pops.operations::<i32>(&mut u).unwrap();
+
+        assert!(!ops.is_empty());
+        assert!(ops.iter().all(|op| matches!(op, Operation::Pop)));
+        assert!(Weights::NONE.operation::<i32>(&mut u).is_err());
+    }
+
+    #[test]
+    fn test_execute_sequential() {
+        let ops = vec![
+            Operation::Push { item: 1 },
+            Operation::Append { items: vec![2, 3] },
+            Operation::Peek,
+            Operation::Pop,
+            Operation::PopPush,
+            Operation::Len,
+        ];
+
+        let report = execute(ops, 1);
+
+        assert_eq!(
+            report.threads[0],
+            [
+                (0, Outcome::Pushed(1)),
+                (1, Outcome::Appended(vec![2, 3])),
+                (2, Outcome::Peeked(Some(3))),
+                (3, Outcome::Popped(Some(3))),
+                (4, Outcome::PopPushed(Some(2))),
+                (5, Outcome::Len(2)),
+            ]
+        );
+        assert_eq!(report.remaining, [2, 1]);
+        assert!(report.is_conserved());
+    }
+
+    #[test]
+    fn test_execute_sync() {
+        let ops = (0..1000)
+            .map(|i| match i % 4 {
+                0 => Operation::Pop,
+                1 => Operation::PopPush,
+                2 => Operation::Append {
+                    items: vec![i, i + 1],
+                },
+                _ => Operation::Push { item: i },
+            })
+            .collect();
+
+        let report = execute(ops, 8);
+
+        assert_eq!(report.threads.len(), 8);
+        assert_eq!(report.outcomes().count(), 1000);
+        assert!(report.is_conserved());
+    }
+
+    #[test]
+    fn test_execute_multi_sequential() {
+        let ops = vec![
+            MultiOperation::On {
+                stack: 0,
+                op: Operation::Append {
+                    items: vec![1, 2, 3],
+                },
+            },
+            MultiOperation::Transfer { from: 0, to: 1 },
+            MultiOperation::TransferN {
+                from: 2,
+                to: 1,
+                n: 2,
+            },
+            MultiOperation::Extend { from: 1, to: 2 },
+            MultiOperation::On {
+                stack: 3,
+                op: Operation::Pop,
+            },
+        ];
+
+        let report = execute_multi(ops, 2, 1);
+
+        assert_eq!(
+            report.threads[0][1..],
+            [
+                (
+                    1,
+                    MultiOutcome::Transferred {
+                        from: 0,
+                        to: 1,
+                        moved: 1
+                    }
+                ),
+                (
+                    2,
+                    MultiOutcome::Transferred {
+                        from: 0,
+                        to: 1,
+                        moved: 2
+                    }
+                ),
+                (3, MultiOutcome::Extended { from: 1, to: 0 }),
+                (
+                    4,
+                    MultiOutcome::On {
+                        stack: 1,
+                        outcome: Outcome::Popped(None)
+                    }
+                ),
+            ]
+        );
+        assert_eq!(report.remaining, [vec![2, 1, 3], vec![]]);
+        assert!(report.is_conserved());
+    }
+
+    #[test]
+    fn test_execute_multi_sync() {
+        let ops = (0..1000)
+            .map(|i| {
+                let stack = (i / 5) as u8;
+
+                match i % 5 {
+                    0 => MultiOperation::On {
+                        stack,
+                        op: Operation::Append {
+                            items: vec![i, i + 1000],
+                        },
+                    },
+                    1 => MultiOperation::Transfer {
+                        from: stack,
+                        to: stack + 1,
+                    },
+                    2 => MultiOperation::TransferN {
+                        from: stack,
+                        to: stack + 2,
+                        n: 3,
+                    },
+                    3 => MultiOperation::Extend {
+                        from: stack + 1,
+                        to: stack,
+                    },
+                    _ => MultiOperation::On {
+                        stack,
+                        op: Operation::Pop,
+                    },
+                }
+            })
+            .collect();
+
+        let report = execute_multi(ops, 3, 8);
+
+        assert_eq!(report.remaining.len(), 3);
+        assert_eq!(report.outcomes().count(), 1000);
+        assert!(report.is_conserved());
+    }
+
+    fn ops(len: i32) -> Vec<Operation<i32>> {
+        (0..len)
+            .map(|i| match i % 6 {
+                0 | 3 => Operation::Pop,
+                5 => Operation::PopPush,
+                1 => Operation::Peek,
+                2 => Operation::Append {
+                    items: vec![i, -i],
+                },
+                _ => Operation::Push { item: i },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_linearizable() {
+        for _ in 0..20 {
+            assert!(execute_traced(ops(60), 4).is_linearizable());
+        }
+
+        // Lengths read while other threads push and pop are checked as well.
+        let ops = || ops(40).into_iter().flat_map(|op| [op, Operation::Len]);
+
+        for _ in 0..20 {
+            assert!(execute_traced(ops().collect(), 4).is_linearizable());
+        }
+    }
+
+    #[test]
+    fn test_not_linearizable() {
+        let mut history = execute_traced(ops(10), 1);
+
+        assert!(history.is_linearizable());
+
+        // A pop that returns a value nobody pushed.
+        history.events[0].outcome = Outcome::Popped(Some(100));
+
+        assert!(!history.is_linearizable());
+
+        // The same pop, claiming to have completed before the push it observed.
+        let mut history = History {
+            events: vec![
+                Event {
+                    thread: 0,
+                    index: 0,
+                    invoked: 0,
+                    responded: 1,
+                    outcome: Outcome::Popped(Some(1)),
+                },
+                Event {
+                    thread: 1,
+                    index: 1,
+                    invoked: 2,
+                    responded: 3,
+                    outcome: Outcome::Pushed(1),
+                },
+            ],
+            remaining: vec![],
+        };
+
+        assert!(!history.is_linearizable());
+
+        history.events[1].invoked = 0;
+
+        assert!(history.is_linearizable());
+
+        // A length that counts the value of a push it overlaps, but not one that completed.
+        let len = |invoked, responded, len| Event {
+            thread: 2,
+            index: 2,
+            invoked,
+            responded,
+            outcome: Outcome::Len(len),
+        };
+
+        history.events[0].outcome = Outcome::Pushed(0);
+        history.remaining = vec![1, 0];
+        history.events.push(len(0, 4, 1));
+
+        assert!(history.is_linearizable());
+
+        history.events[2] = len(4, 5, 1);
+
+        assert!(!history.is_linearizable());
+
+        history.events[2] = len(4, 5, 2);
+
+        assert!(history.is_linearizable());
+    }
+
+    #[test]
+    fn test_schedule_roundtrip() {
+        let text = "0 push 4\n1 yield 2 append 1,2,3\n0 pop\n2 poppush\n1 peek\n2 len\n1 append \n";
+
+        let schedule: Schedule<i32> = text.parse().unwrap();
+
+        assert_eq!(schedule.threads(), 3);
+        assert_eq!(schedule.to_string(), text);
+        assert_eq!(
+            "0 push x".parse::<Schedule<i32>>().unwrap_err(),
+            ParseScheduleError { line: 1 }
+        );
+    }
+
+    #[test]
+    fn test_replay_deterministic() {
+        let schedule = Schedule::round_robin(ops(40), 4);
+
+        let first = schedule.replay();
+
+        for _ in 0..5 {
+            let again = schedule.replay();
+            assert_eq!(first.threads, again.threads);
+            assert_eq!(first.remaining, again.remaining);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replay_panicking_step() {
+        #[derive(Debug, PartialEq, Eq)]
+        struct PanicOnClone;
+
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                panic!("cloned");
+            }
+        }
+
+        // The other thread still gets its turn, so the panic surfaces instead of a hang.
+        let schedule = Schedule {
+            steps: vec![
+                Step {
+                    thread: 0,
+                    yields: 0,
+                    op: Operation::Push {
+                        item: PanicOnClone,
+                    },
+                },
+                Step {
+                    thread: 1,
+                    yields: 0,
+                    op: Operation::Pop,
+                },
+            ],
+        };
+
+        schedule.replay();
+    }
+
+    #[test]
+    fn test_recorded_schedule() {
+        let history = execute_traced(ops(30), 3);
+        let schedule = history.schedule();
+
+        assert_eq!(schedule.steps.len(), history.events.len());
+        assert!(schedule.replay().is_conserved());
+    }
+
+    #[test]
+    fn test_shrink() {
+        let schedule = Schedule::round_robin(ops(40), 4);
+
+        // Fails whenever a pop observes the value pushed by operation 4.
+        let fails = |s: &Schedule<i32>| {
+            s.replay()
+                .outcomes()
+                .any(|(_, o)| *o == Outcome::Popped(Some(4)))
+        };
+
+        let shrunk = schedule.shrink(fails);
+
+        assert_eq!(shrunk.to_string(), "0 push 4\n0 pop\n");
+    }
+
+    #[test]
+    fn test_model_differential() {
+        let stack = Stack::new();
+        let model = ModelStack::new();
+
+        for op in ops(200) {
+            match (apply(&stack, op.clone()), apply(&model, op)) {
+                // The lock free stack does not count pops in its length.
+                (Outcome::Len(_), Outcome::Len(_)) => {}
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            model.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
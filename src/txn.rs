@@ -0,0 +1,578 @@
+//! Operations spanning several [Stacks](crate::Stack) that other threads observe as one atomic
+//! step.
+//!
+//! A [transfer](transfer) claims the head node of the source stack with a descriptor, installs
+//! the same descriptor, a marked pointer in place of the head, on the destination stack, and then
+//! decides its outcome with a single compare exchange on the descriptor's status. Until it is
+//! decided, the transfer has not happened. Once it has succeeded, the source node counts as
+//! taken and is unlinked like any other taken node, and the destination head is swung to the new
+//! node. If it failed, the source node is free again and the destination head is swung back.
+//!
+//! No thread ever waits on a stalled transfer. An operation that finds a descriptor installed at
+//! a head finishes the transfer for it: the descriptor is only installed while it still claims
+//! the source node, so it decides the transfer as succeeded. Only an operation that needs the
+//! claimed value itself, such as a pop of the source node, aborts the transfer, which then starts
+//! over. Transfers in opposite directions therefore finish each other rather than aborting each
+//! other.
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+
+use crate::base::{Node, UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+use crate::Stack;
+
+/// The claim of a node whose value is in the stack.
+pub(crate) const FREE: usize = 0;
+/// The claim of a node whose value was taken. Any other claim is a transfer's [Descriptor].
+pub(crate) const TAKEN: usize = 1;
+
+const UNDECIDED: u8 = 0;
+const SUCCEEDED: u8 = 1;
+const FAILED: u8 = 2;
+
+/// [Descriptor](Descriptor) moves the value of the node it claims to `new`, which goes on top of
+/// `prev` at the destination, or replaces it for [replace_head](replace_head). A descriptor that
+/// takes several values at once, for [take_all](take_all), has neither. The status comes first,
+/// so that it can be read from a claim without knowing `V`.
+#[repr(C)]
+struct Descriptor<V> {
+    status: AtomicU8,
+    new: *mut Node<V>,
+    prev: AtomicPtr<Node<V>>,
+}
+
+unsafe impl<V: Send> Send for Descriptor<V> {}
+
+impl<V> Descriptor<V> {
+    /// Swings `head` from this descriptor to the node it stands for now that it is decided.
+    ///
+    /// # Safety: The descriptor must be protected and decided.
+    unsafe fn complete(&self, head: &AtomicPtr<Node<V>>, marked: *mut Node<V>) {
+        let now = if self.status.load(Ordering::Acquire) == SUCCEEDED {
+            self.new
+        } else {
+            self.prev.load(Ordering::Acquire)
+        };
+
+        let _ = head.compare_exchange(marked, now, Ordering::AcqRel, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn is_marked<V>(head: *mut Node<V>) -> bool {
+    head as usize & 1 == 1
+}
+
+fn mark<V>(desc: *mut Descriptor<V>) -> *mut Node<V> {
+    (desc as usize | 1) as *mut Node<V>
+}
+
+fn unmark<V>(head: *mut Node<V>) -> *mut Descriptor<V> {
+    (head as usize & !1) as *mut Descriptor<V>
+}
+
+/// Finishes the transfer installed at `head`, if there is one, deciding it as succeeded unless it
+/// was aborted first.
+pub(crate) fn help<V>(head: &AtomicPtr<Node<V>>) {
+    let mut hazard = Hazard::new(&UNIQUE_FAMILY);
+    let mut marked = head.load(Ordering::Acquire);
+
+    loop {
+        if !is_marked(marked) {
+            return;
+        }
+
+        hazard.protect_raw(unmark(marked));
+
+        let now = head.load(Ordering::Acquire);
+
+        if now == marked {
+            break;
+        }
+
+        marked = now;
+    }
+
+    // # Safety: The descriptor is protected, and was installed after we protected it, so it has
+    // not been retired.
+    unsafe {
+        let desc = &*unmark(marked);
+
+        // Only a descriptor that still claims its node is installed, and only an abort takes the
+        // claim away, so an undecided one may succeed.
+        let _ =
+            desc.status
+                .compare_exchange(UNDECIDED, SUCCEEDED, Ordering::AcqRel, Ordering::Acquire);
+        desc.complete(head, marked);
+    }
+}
+
+fn abort(status: &AtomicU8) {
+    let _ = status.compare_exchange(UNDECIDED, FAILED, Ordering::AcqRel, Ordering::Acquire);
+}
+
+/// Reads the status of the transfer holding `claim`, first aborting it if `abort` is set, or
+/// returns `None` if the claim changed in the meantime.
+fn status(claim: &AtomicUsize, desc: usize, hazard: &mut Hazard<'_>, abort: bool) -> Option<u8> {
+    hazard.protect_raw(desc as *mut AtomicU8);
+
+    if claim.load(Ordering::Acquire) != desc {
+        return None;
+    }
+
+    // # Safety: The descriptor is protected, and was still the claim after we protected it. Its
+    // status is its first field.
+    let status = unsafe { &*(desc as *const AtomicU8) };
+
+    if abort {
+        self::abort(status);
+    }
+
+    Some(status.load(Ordering::Acquire))
+}
+
+/// Whether the value of the node with `claim` was taken. A value claimed by an undecided transfer
+/// has not been taken yet.
+pub(crate) fn is_taken(claim: &AtomicUsize) -> bool {
+    let mut hazard = Hazard::new(&UNIQUE_FAMILY);
+
+    loop {
+        match claim.load(Ordering::Acquire) {
+            FREE => return false,
+            TAKEN => return true,
+            desc => match status(claim, desc, &mut hazard, false) {
+                Some(status) => return status == SUCCEEDED,
+                None => continue,
+            },
+        }
+    }
+}
+
+/// Claims the value of the node with `claim` as `to`, aborting any undecided transfer of it.
+/// Returns `false` if the value was already taken.
+pub(crate) fn claim(claim: &AtomicUsize, to: usize) -> bool {
+    let mut hazard = Hazard::new(&UNIQUE_FAMILY);
+
+    loop {
+        let curr = claim.load(Ordering::Acquire);
+
+        match curr {
+            FREE => {}
+            TAKEN => return false,
+            desc => match status(claim, desc, &mut hazard, true) {
+                Some(SUCCEEDED) => return false,
+                Some(_) => {}
+                None => continue,
+            },
+        }
+
+        if claim
+            .compare_exchange(curr, to, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            return true;
+        }
+    }
+}
+
+/// Pops the top value of `a` and pushes it onto `b` as one atomic step: no thread observes the
+/// value in both stacks, or in neither. Returns `false` if `a` was empty.
+///
+/// The value is cloned into a new node on `b`, since entries of `a` may still refer to the old
+/// one, which is reclaimed like a popped node. The new node keeps the value's tag.
+/// ```
+/// use unlink::{txn, Stack};
+///
+/// let a = Stack::new();
+/// let b = Stack::new();
+///
+/// a.push(1);
+///
+/// assert!(txn::transfer(&a, &b));
+/// assert!(!txn::transfer(&a, &b));
+///
+/// assert!(a.pop().is_none());
+/// assert_eq!(b.pop().map(|e| *e), Some(1));
+/// ```
+pub fn transfer<V>(a: &Stack<V>, b: &Stack<V>) -> bool
+where
+    V: Clone + Send + Sync,
+{
+    if ptr::eq(a, b) {
+        return a.peek().is_some();
+    }
+
+    loop {
+        let Some(head) = a.protect_head() else {
+            return false;
+        };
+
+        head.read();
+        let new = Node::tagged(head.val.clone(), head.tag());
+        head.unread();
+
+        let desc = Box::into_raw(Box::new(Descriptor {
+            status: AtomicU8::new(UNDECIDED),
+            new,
+            prev: AtomicPtr::new(ptr::null_mut()),
+        }));
+
+        if !claim(&head.claim, desc as usize) {
+            // # Safety: Neither the descriptor nor the new node were published.
+            unsafe {
+                drop(Box::from_raw(desc));
+                Node::drop(new);
+            }
+            continue;
+        }
+
+        unsafe {
+            // # Safety: Only we retire the descriptor, which we do last.
+            let desc_ref = &*desc;
+
+            if install(desc_ref, b) {
+                let _ = desc_ref.status.compare_exchange(
+                    UNDECIDED,
+                    SUCCEEDED,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+
+                desc_ref.complete(&b.head, mark(desc));
+            } else {
+                abort(&desc_ref.status);
+            }
+
+            let succeeded = desc_ref.status.load(Ordering::Acquire) == SUCCEEDED;
+
+            let _ = head.claim.compare_exchange(
+                desc as usize,
+                if succeeded { TAKEN } else { FREE },
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+
+            // Neither a head nor a claim refers to the descriptor anymore, though other threads
+            // may still hold it.
+            retire(a.domain, desc);
+
+            if !succeeded {
+                // The new node was never published, since helpers only swing the head to it once
+                // the transfer succeeded.
+                Node::drop(new);
+                continue;
+            }
+        }
+
+        b.nodes.fetch_add(1, Ordering::Relaxed);
+        b.len.fetch_add(1, Ordering::Relaxed);
+
+        // Unlink the taken node right away if nothing was pushed on top of it.
+        drop(head);
+        drop(a.protect_head());
+
+        return true;
+    }
+}
+
+/// Replaces `node`, which must be protected, with a new node holding `val` if it is still the
+/// head of `stack`, as one atomic step: the value of `node` is taken exactly when the new one
+/// becomes visible. Hands `val` back otherwise.
+///
+/// On success, the caller is responsible for retiring `node`, which is no longer linked and stays
+/// frozen.
+pub(crate) fn replace_head<V>(stack: &Stack<V>, node: &Node<V>, val: V) -> Result<(), V>
+where
+    V: Send + Sync,
+{
+    let old = node as *const _ as *mut Node<V>;
+    let new = Node::tagged(val, 0);
+
+    // # Safety: The new node was never published.
+    let give_back = |new: *mut Node<V>| unsafe {
+        let val = ptr::read(&(*new).val);
+        Node::dealloc(new);
+        Err(val)
+    };
+
+    if stack.head.load(Ordering::Acquire) != old || !node.freeze() {
+        return give_back(new);
+    }
+
+    unsafe {
+        (*new)
+            .next
+            .store(node.next.load(Ordering::Acquire), Ordering::Relaxed)
+    };
+
+    let desc = Box::into_raw(Box::new(Descriptor {
+        status: AtomicU8::new(UNDECIDED),
+        new,
+        prev: AtomicPtr::new(old),
+    }));
+
+    if !claim(&node.claim, desc as usize) {
+        node.thaw();
+
+        // # Safety: The descriptor was never published.
+        unsafe { drop(Box::from_raw(desc)) };
+        return give_back(new);
+    }
+
+    unsafe {
+        // # Safety: Only we retire the descriptor, which we do last.
+        let desc_ref = &*desc;
+
+        if stack
+            .head
+            .compare_exchange(old, mark(desc), Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            let _ = desc_ref.status.compare_exchange(
+                UNDECIDED,
+                SUCCEEDED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+
+            desc_ref.complete(&stack.head, mark(desc));
+        } else {
+            abort(&desc_ref.status);
+        }
+
+        let succeeded = desc_ref.status.load(Ordering::Acquire) == SUCCEEDED;
+
+        let _ = node.claim.compare_exchange(
+            desc as usize,
+            if succeeded { TAKEN } else { FREE },
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+
+        retire(stack.domain, desc);
+
+        if succeeded {
+            Ok(())
+        } else {
+            node.thaw();
+            give_back(new)
+        }
+    }
+}
+
+/// Takes the values of the nodes with `claims`, which must be protected, as one atomic step:
+/// every value is claimed by the same descriptor, and deciding it takes all of them at once.
+/// Returns `false`, having taken none, if one of them was already taken, or if another thread
+/// aborted the descriptor to claim one of them first.
+pub(crate) fn take_all(domain: &Domain<UniqueFamily>, claims: &[&AtomicUsize]) -> bool {
+    let desc = Box::into_raw(Box::new(Descriptor::<()> {
+        status: AtomicU8::new(UNDECIDED),
+        new: ptr::null_mut(),
+        prev: AtomicPtr::new(ptr::null_mut()),
+    }));
+
+    let claimed = claims
+        .iter()
+        .take_while(|c| claim(c, desc as usize))
+        .count();
+
+    unsafe {
+        // # Safety: Only we retire the descriptor, which we do last.
+        let desc_ref = &*desc;
+
+        if claimed == claims.len() {
+            let _ = desc_ref.status.compare_exchange(
+                UNDECIDED,
+                SUCCEEDED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+        } else {
+            abort(&desc_ref.status);
+        }
+
+        let succeeded = desc_ref.status.load(Ordering::Acquire) == SUCCEEDED;
+
+        // A claim that another thread took over after aborting the descriptor stays as it is.
+        for claim in &claims[..claimed] {
+            let _ = claim.compare_exchange(
+                desc as usize,
+                if succeeded { TAKEN } else { FREE },
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+        }
+
+        retire(domain, desc);
+
+        succeeded
+    }
+}
+
+/// Installs `desc` at the head of `b`, unless the transfer is aborted first.
+///
+/// # Safety: `desc` must stay valid for the duration of the call.
+unsafe fn install<V>(desc: &Descriptor<V>, b: &Stack<V>) -> bool {
+    let marked = mark(desc as *const _ as *mut Descriptor<V>);
+
+    while desc.status.load(Ordering::Acquire) == UNDECIDED {
+        let prev = b.head.load(Ordering::Acquire);
+
+        if is_marked(prev) {
+            help(&b.head);
+            continue;
+        }
+
+        desc.prev.store(prev, Ordering::Release);
+        (*desc.new).next.store(prev, Ordering::Release);
+
+        if b.head
+            .compare_exchange(prev, marked, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Retires `desc` into `domain`.
+///
+/// # Safety: `desc` must no longer be installed at any head.
+unsafe fn retire<V: Send>(domain: &Domain<UniqueFamily>, desc: *mut Descriptor<V>) {
+    domain::retire::<_, DropDescriptor<_>>(domain, desc);
+}
+
+#[repr(transparent)]
+struct DropDescriptor<V>(NonNull<Descriptor<V>>);
+
+impl<V> Drop for DropDescriptor<V> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.0.as_ptr())) }
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<V> crate::hazard::Pointer<Descriptor<V>> for DropDescriptor<V> {
+    unsafe fn from_raw(ptr: *mut Descriptor<V>) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_transfer() {
+        let a = Stack::new();
+        let b = Stack::new();
+
+        (0..3).for_each(|i| a.push(i));
+        b.push(10);
+
+        assert!(transfer(&a, &b));
+        assert!(transfer(&a, &b));
+
+        assert_eq!(b.len(), 3);
+        assert_eq!(b.into_iter().collect::<Vec<_>>(), vec![1, 2, 10]);
+        assert_eq!(a.into_iter().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn test_transfer_empty() {
+        let a = Stack::<i32>::new();
+        let b = Stack::new();
+
+        assert!(!transfer(&a, &b));
+        assert!(!transfer(&a, &a));
+        assert!(b.pop().is_none());
+    }
+
+    #[test]
+    fn test_transfer_held_entry() {
+        let a = Stack::new();
+        let b = Stack::new();
+
+        a.push(String::from("moved"));
+
+        let entry = a.peek().unwrap();
+
+        assert!(transfer(&a, &b));
+        assert_eq!(&*entry, "moved");
+        assert_eq!(b.peek().as_deref().map(String::as_str), Some("moved"));
+    }
+
+    #[test]
+    fn test_transfer_opposite_sync() {
+        let active = Stack::new();
+        let dead = Stack::new();
+
+        (0..1000).for_each(|i| active.push(i));
+
+        // Transfers in opposite directions finish each other's installs rather than aborting
+        // them, so every thread gets through its attempts.
+        std::thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| (0..1000).for_each(|_| _ = transfer(&active, &dead)));
+                s.spawn(|| (0..1000).for_each(|_| _ = transfer(&dead, &active)));
+            }
+        });
+
+        let mut values = active.into_iter().chain(dead).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_transfer_sync() {
+        let stages = [Stack::new(), Stack::new(), Stack::new()];
+        let moved = AtomicUsize::new(0);
+
+        (0..1000).for_each(|i| stages[0].push(i));
+
+        std::thread::scope(|s| {
+            for i in 0..6 {
+                let (stages, moved) = (&stages, &moved);
+                s.spawn(move || {
+                    let (from, to) = if i % 2 == 0 { (0, 1) } else { (1, 2) };
+
+                    for _ in 0..400 {
+                        if transfer(&stages[from], &stages[to]) {
+                            moved.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+
+            s.spawn(|| {
+                for i in 0..400 {
+                    let popped = if i % 2 == 0 {
+                        stages[1].pop()
+                    } else {
+                        stages[1].pop_where(|_| true)
+                    };
+
+                    if let Some(val) = popped {
+                        stages[1].push(*val);
+                    }
+                }
+            });
+        });
+
+        let [first, second, third] = stages;
+        let mut values = first
+            .into_iter()
+            .chain(second)
+            .chain(third)
+            .collect::<Vec<_>>();
+
+        values.sort();
+
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+        assert!(moved.load(Ordering::Relaxed) > 0);
+    }
+}
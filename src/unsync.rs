@@ -0,0 +1,190 @@
+//! A single threaded stack with the same API as [Stack](crate::Stack), for generic code that
+//! runs where no other thread can observe the stack.
+//!
+//! The stack is neither `Send` nor `Sync`, and uses no atomics. Elements are reference counted,
+//! so [Entries](Entry) handed out by `peek` stay valid while the stack is modified.
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use std::rc::Rc;
+
+pub struct Stack<V> {
+    items: RefCell<Vec<Rc<V>>>,
+}
+
+impl<V> core::fmt::Debug for Stack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stack").finish()
+    }
+}
+
+impl<V> Default for Stack<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Stack<V> {
+    pub fn new() -> Self {
+        Stack {
+            items: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+
+    pub fn push(&self, val: V) {
+        self.items.borrow_mut().push(Rc::new(val));
+    }
+
+    pub fn pop(&self) -> Option<Entry<'_, V>> {
+        self.items.borrow_mut().pop().map(Entry::new)
+    }
+
+    pub fn peek(&self) -> Option<Entry<'_, V>> {
+        self.items.borrow().last().cloned().map(Entry::new)
+    }
+
+    /// Moves the contents of `other` on top of `self`, keeping their order.
+    pub fn append(&self, other: Self) {
+        self.items.borrow_mut().extend(other.items.into_inner());
+    }
+}
+
+impl<V> crate::ConcurrentStack<V> for Stack<V> {
+    type Entry<'a>
+        = Entry<'a, V>
+    where
+        Self: 'a;
+
+    fn push(&self, val: V) -> Result<(), V> {
+        Stack::push(self, val);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Self::Entry<'_>> {
+        Stack::pop(self)
+    }
+
+    fn peek(&self) -> Option<Self::Entry<'_>> {
+        Stack::peek(self)
+    }
+
+    fn append(&self, other: Self) {
+        Stack::append(self, other)
+    }
+
+    fn len(&self) -> usize {
+        Stack::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        Stack::is_empty(self)
+    }
+}
+
+/// [Entry](Entry) is a reference to a value of an unsync [Stack](Stack), valid until it is
+/// dropped.
+pub struct Entry<'a, V> {
+    val: Rc<V>,
+    _stack: PhantomData<&'a Stack<V>>,
+}
+
+impl<'a, V> Entry<'a, V> {
+    fn new(val: Rc<V>) -> Self {
+        Entry {
+            val,
+            _stack: PhantomData,
+        }
+    }
+}
+
+impl<'a, V> core::ops::Deref for Entry<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        &self.val
+    }
+}
+
+pub struct IntoIter<V> {
+    items: Vec<Rc<V>>,
+}
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Entries borrow the stack, so none can outlive it and every count is unique here.
+        self.items
+            .pop()
+            .map(|val| Rc::try_unwrap(val).ok().expect("entry outlived its stack"))
+    }
+}
+
+impl<V> IntoIterator for Stack<V> {
+    type Item = V;
+    type IntoIter = IntoIter<V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            items: self.items.into_inner(),
+        }
+    }
+}
+
+impl<V> FromIterator<V> for Stack<V> {
+    fn from_iter<T: IntoIterator<Item = V>>(iter: T) -> Self {
+        Stack {
+            items: RefCell::new(iter.into_iter().map(Rc::new).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConcurrentStack;
+
+    #[test]
+    fn test_push_pop() {
+        let stack = Stack::new();
+
+        (0..3).for_each(|i| stack.push(i));
+
+        let top = stack.peek().unwrap();
+
+        assert_eq!(*stack.pop().unwrap(), 2);
+        assert_eq!(*top, 2);
+        assert_eq!(stack.len(), 2);
+
+        stack.append((3..5).collect());
+
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), [4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn test_generic() {
+        fn fill<S: ConcurrentStack<i32>>(stack: &S) {
+            (0..4).for_each(|i| stack.push(i).unwrap());
+            stack.pop();
+        }
+
+        let stack = Stack::new();
+        let sync = crate::Stack::new();
+
+        fill(&stack);
+        fill(&sync);
+
+        assert_eq!(ConcurrentStack::len(&stack), 3);
+        assert_eq!(ConcurrentStack::len(&sync), 3);
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            sync.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Bounded proof harnesses for the [Kani](https://github.com/model-checking/kani) model checker,
+//! run with `cargo kani --features tags`.
+//!
+//! Kani does not model threads, so instead of interleavings across threads, the harnesses cover
+//! every sequence of a few operations on one or two stacks, with every value, and check the
+//! outcomes against a `Vec`. Kani checks each of those executions for use after free, double
+//! free, and leaks on the retire path as well, where popped and taken nodes are only reclaimed
+//! once the hazard pointers of their entries are released.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::base::UNIQUE_FAMILY;
+use crate::{txn, Stack};
+
+/// The most operations a harness runs. Loops are unwound one further.
+const MAX_OPS: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Push(u8),
+    Pop,
+    Peek,
+    Append(u8, u8),
+    PopMatching(usize),
+    Transfer,
+}
+
+impl kani::Arbitrary for Op {
+    fn any() -> Self {
+        match kani::any::<u8>() % 6 {
+            0 => Op::Push(kani::any()),
+            1 => Op::Pop,
+            2 => Op::Peek,
+            3 => Op::Append(kani::any(), kani::any()),
+            4 => Op::PopMatching(kani::any()),
+            _ => Op::Transfer,
+        }
+    }
+}
+
+/// Runs `op` against `stack` and its `model`, bottom first, moving values onto `other` and its
+/// model on a transfer.
+fn step(
+    op: Op,
+    (stack, model): (&Stack<u8>, &mut Vec<(u8, usize)>),
+    (other, other_model): (&Stack<u8>, &mut Vec<(u8, usize)>),
+) {
+    match op {
+        Op::Push(val) => {
+            stack.push_tagged(val, val as usize);
+            model.push((val, val as usize));
+        }
+        Op::Pop => assert_eq!(stack.pop().map(|e| *e), model.pop().map(|(v, _)| v)),
+        Op::Peek => assert_eq!(stack.peek().map(|e| *e), model.last().map(|&(v, _)| v)),
+        Op::Append(a, b) => {
+            stack.append([a, b].into_iter().collect());
+            model.extend([(a, 0), (b, 0)]);
+        }
+        Op::PopMatching(mask) => {
+            let expected = model.iter().rposition(|&(_, tag)| tag & mask != 0);
+            let popped = stack.pop_matching(mask).map(|e| *e);
+
+            assert_eq!(popped, expected.map(|i| model.remove(i).0));
+        }
+        Op::Transfer => {
+            let moved = txn::transfer(stack, other);
+
+            assert_eq!(moved, !model.is_empty());
+
+            if let Some(top) = model.pop() {
+                other_model.push(top);
+            }
+        }
+    }
+}
+
+/// Any few operations on a stack behave as on a `Vec`, and leave the same values behind.
+#[kani::proof]
+#[kani::unwind(5)]
+fn sequential_ops_match_vec() {
+    let (stack, other) = (Stack::new(), Stack::new());
+    let (mut model, mut other_model) = (Vec::new(), Vec::new());
+
+    let ops: usize = kani::any();
+    kani::assume(ops <= MAX_OPS);
+
+    for _ in 0..ops {
+        step(
+            kani::any(),
+            (&stack, &mut model),
+            (&other, &mut other_model),
+        );
+    }
+
+    assert!(stack
+        .into_iter()
+        .eq(model.into_iter().rev().map(|(v, _)| v)));
+    assert!(other
+        .into_iter()
+        .eq(other_model.into_iter().rev().map(|(v, _)| v)));
+}
+
+/// Operations may run on either of two stacks, so that values move back and forth between them.
+#[kani::proof]
+#[kani::unwind(5)]
+fn two_stacks_match_vecs() {
+    let stacks = [Stack::new(), Stack::new()];
+    let mut models = [Vec::new(), Vec::new()];
+
+    let ops: usize = kani::any();
+    kani::assume(ops <= MAX_OPS);
+
+    for _ in 0..ops {
+        let (first, second) = models.split_at_mut(1);
+        let (model, other_model) = (&mut first[0], &mut second[0]);
+
+        if kani::any() {
+            step(kani::any(), (&stacks[0], model), (&stacks[1], other_model));
+        } else {
+            step(kani::any(), (&stacks[1], other_model), (&stacks[0], model));
+        }
+    }
+
+    let [stack, other] = stacks;
+    let [model, other_model] = models;
+
+    assert!(stack
+        .into_iter()
+        .eq(model.into_iter().rev().map(|(v, _)| v)));
+    assert!(other
+        .into_iter()
+        .eq(other_model.into_iter().rev().map(|(v, _)| v)));
+}
+
+static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+struct Counted;
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Every value is dropped exactly once, whether it was popped while an entry to another value was
+/// held, taken from below the head, or left on the stack.
+#[kani::proof]
+#[kani::unwind(5)]
+fn values_are_dropped_once() {
+    let stack = Stack::new();
+
+    let pushed: usize = kani::any();
+    kani::assume(pushed <= 3);
+
+    (0..pushed).for_each(|i| stack.push_tagged(Counted, i));
+
+    let held = stack.peek();
+
+    let popped: usize = kani::any();
+    kani::assume(popped <= pushed);
+
+    for _ in 0..popped {
+        if kani::any() {
+            drop(stack.pop());
+        } else {
+            drop(stack.pop_matching(usize::MAX));
+        }
+    }
+
+    let taken = stack.pop_matching(kani::any());
+
+    drop((held, taken));
+    drop(stack);
+    UNIQUE_FAMILY.eager_reclaim();
+
+    assert_eq!(DROPS.load(Ordering::Relaxed), pushed);
+}
@@ -0,0 +1,81 @@
+//! How a thread waits on another, when it cannot make progress by itself.
+//!
+//! Most operations are lock free: a failed compare exchange means that another thread made
+//! progress, so they retry right away. The only waits are in [reclaim_all](crate::reclaim_all),
+//! for the hazard pointers that protect retired nodes, and, with the `peek-mut` feature, on the
+//! lock of a value, which entries take shared and `EntryMut`s take exclusively. Those call the
+//! yield hook, which defaults to [yield_now](std::thread::yield_now).
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The hook set through [set_yield_hook](set_yield_hook), or null for the default.
+static HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Replaces what a thread does while it waits on another thread, for all collections in
+/// the process. Threads that wait inside an async executor can, for example, spend their task
+/// budget or hint the executor instead of yielding the whole worker thread.
+/// ```
+/// unlink::set_yield_hook(core::hint::spin_loop);
+/// ```
+pub fn set_yield_hook(hook: fn()) {
+    HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Restores [yield_now](std::thread::yield_now) as the yield hook.
+pub fn reset_yield_hook() {
+    HOOK.store(core::ptr::null_mut(), Ordering::Release);
+}
+
+/// Yields through the hook, while waiting for another thread.
+#[inline]
+pub(crate) fn yield_now() {
+    let hook = HOOK.load(Ordering::Acquire);
+
+    if hook.is_null() {
+        std::thread::yield_now();
+    } else {
+        // # Safety: Only `fn()`s are stored in the hook.
+        unsafe { core::mem::transmute::<*mut (), fn()>(hook)() };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Stack;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    static YIELDS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count() {
+        YIELDS.fetch_add(1, Ordering::Relaxed);
+        std::thread::yield_now();
+    }
+
+    #[test]
+    fn test_yield_hook() {
+        let stack = Stack::new();
+        stack.push(0);
+
+        set_yield_hook(count);
+
+        thread::scope(|s| {
+            let entry = stack.peek().unwrap();
+            drop(stack.pop());
+
+            // Waits for the entry to release the popped node.
+            let reclaimer = s.spawn(crate::reclaim_all);
+
+            while YIELDS.load(Ordering::Relaxed) == 0 {
+                thread::yield_now();
+            }
+
+            drop(entry);
+            reclaimer.join().unwrap();
+        });
+
+        reset_yield_hook();
+
+        assert!(stack.is_empty());
+    }
+}
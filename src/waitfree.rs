@@ -0,0 +1,605 @@
+//! A stack whose pops complete in a bounded number of steps, regardless of what other threads
+//! do.
+//!
+//! Every pop announces a request in the slot of its [Handle](Handle), tagged with a ticket that
+//! orders it among all requests. Before doing anything else, every operation, push or pop, helps
+//! the oldest pending request to completion. Once a request is the oldest, every other thread
+//! therefore works on it, and it completes after at most one attempt per thread.
+//!
+//! A request is served by swapping the head node `h` for a marked pointer to a descriptor
+//! naming `h` and the request. Whoever sees the marked head hands `h` to the request, unless it
+//! was already served, and swings the head to `h.next`, or back to `h` if the request got
+//! another node. Pushes help any marked head along before pushing on top of it. The requester
+//! retires its node only once it has been swung off the head.
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use crate::base::{UniqueFamily, UNIQUE_FAMILY};
+use crate::domain::{self, Hazard};
+use crate::hazard::Domain;
+
+struct Node<V> {
+    val: V,
+    next: *mut Self,
+}
+
+unsafe impl<V: Send> Send for Node<V> {}
+
+/// A pop waiting to be handed a node, or `EMPTY` if it found the stack empty.
+struct Request<V> {
+    ticket: u64,
+    result: AtomicPtr<Node<V>>,
+    /// One count for the announcing slot, and one for every descriptor naming the request.
+    refs: AtomicUsize,
+}
+
+unsafe impl<V> Send for Request<V> {}
+
+/// An attempt to hand `node` to `request`, installed as the marked head.
+struct Descriptor<V> {
+    node: *mut Node<V>,
+    request: *mut Request<V>,
+}
+
+unsafe impl<V> Send for Descriptor<V> {}
+
+fn empty<V>() -> *mut Node<V> {
+    // Nodes are at least pointer aligned, so this address is never a node.
+    1 as *mut Node<V>
+}
+
+fn is_marked<T>(ptr: *mut T) -> bool {
+    ptr as usize & 1 == 1
+}
+
+fn marked<V>(desc: *mut Descriptor<V>) -> *mut Node<V> {
+    (desc as usize | 1) as *mut Node<V>
+}
+
+fn descriptor<V>(head: *mut Node<V>) -> *mut Descriptor<V> {
+    (head as usize & !1) as *mut Descriptor<V>
+}
+
+impl<V> Request<V> {
+    /// Takes an additional count on `raw` unless it has already dropped to zero.
+    ///
+    /// # Safety: `raw` must be protected by a hazard pointer.
+    unsafe fn try_acquire(raw: *mut Self) -> bool {
+        let mut refs = (*raw).refs.load(Ordering::Acquire);
+
+        while refs != 0 {
+            match (*raw).refs.compare_exchange_weak(
+                refs,
+                refs + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(now) => refs = now,
+            }
+        }
+
+        false
+    }
+
+    /// Gives up one count on `raw`, retiring it once the last count is gone.
+    ///
+    /// # Safety: The caller must own one of the counts on `raw`.
+    unsafe fn release(raw: *mut Self) {
+        if (*raw).refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            domain::retire::<_, Reclaim<_>>(&UNIQUE_FAMILY, raw);
+        }
+    }
+}
+
+/// [Reclaim](Reclaim) frees a retired node, request, or descriptor. Descriptors give up the
+/// count they hold on their request.
+#[repr(transparent)]
+struct Reclaim<T>(NonNull<T>);
+
+impl<T> Drop for Reclaim<T> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.0.as_ptr())) }
+        domain::reclaimed();
+    }
+}
+
+unsafe impl<T> crate::hazard::Pointer<T> for Reclaim<T> {
+    unsafe fn from_raw(ptr: *mut T) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+impl<V> Drop for Descriptor<V> {
+    fn drop(&mut self) {
+        unsafe { Request::release(self.request) }
+    }
+}
+
+/// [Stack](Stack) is a lock free stack with wait free pops, for threads that cannot afford to
+/// be starved by contention. Pops go through a [Handle](Handle), of which there can be at most
+/// as many as the stack was created for.
+/// ```
+/// use unlink::waitfree::Stack;
+///
+/// let stack = Stack::new(4);
+/// let handle = stack.handle().unwrap();
+///
+/// stack.push(1);
+/// stack.push(2);
+///
+/// assert_eq!(handle.pop().map(|e| *e), Some(2));
+/// ```
+pub struct Stack<V> {
+    head: AtomicPtr<Node<V>>,
+    domain: &'static Domain<UniqueFamily>,
+    slots: Box<[AtomicPtr<Request<V>>]>,
+    claimed: Box<[AtomicBool]>,
+    tickets: AtomicU64,
+}
+
+unsafe impl<V: Send + Sync> Send for Stack<V> {}
+unsafe impl<V: Send + Sync> Sync for Stack<V> {}
+
+impl<V> core::fmt::Debug for Stack<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stack")
+            .field("handles", &self.slots.len())
+            .finish()
+    }
+}
+
+impl<V> Stack<V> {
+    /// Creates a stack that can be popped from through at most `handles` handles at once.
+    pub fn new(handles: usize) -> Self {
+        Stack {
+            head: AtomicPtr::new(null_mut()),
+            domain: &UNIQUE_FAMILY,
+            slots: (0..handles).map(|_| AtomicPtr::new(null_mut())).collect(),
+            claimed: (0..handles).map(|_| AtomicBool::new(false)).collect(),
+            tickets: AtomicU64::new(0),
+        }
+    }
+
+    /// Claims one of the stack's handles, or returns `None` if all of them are in use.
+    pub fn handle(&self) -> Option<Handle<'_, V>> {
+        let index = self.claimed.iter().position(|claimed| {
+            claimed
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })?;
+
+        Some(Handle { stack: self, index })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync,
+{
+    pub fn push(&self, val: V) {
+        self.help();
+
+        let node = Box::into_raw(Box::new(Node {
+            val,
+            next: null_mut(),
+        }));
+
+        let mut hazard = Hazard::new(self.domain);
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+
+            if is_marked(head) {
+                self.help_head(&mut hazard);
+                continue;
+            }
+
+            // # Safety: The node is not published yet.
+            unsafe { (*node).next = head };
+
+            if self
+                .head
+                .compare_exchange(head, node, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Helps the oldest pending request, if there is one, to completion.
+    fn help(&self) {
+        let mut hazard = Hazard::new(self.domain);
+        let mut oldest = None;
+
+        for slot in self.slots.iter() {
+            let Some(request) = self.protect_request(slot, &mut hazard) else {
+                continue;
+            };
+
+            unsafe {
+                let pending = (*request).result.load(Ordering::Acquire).is_null();
+
+                if pending && oldest.is_none_or(|(ticket, _)| (*request).ticket < ticket) {
+                    oldest = Some(((*request).ticket, slot));
+                }
+            }
+        }
+
+        let Some((_, slot)) = oldest else {
+            return;
+        };
+
+        // The request may have completed and been replaced in the meantime, in which case its
+        // successor is younger than any request we saw, and helping it does no harm.
+        if let Some(request) = self.protect_request(slot, &mut hazard) {
+            if unsafe { Request::try_acquire(request) } {
+                unsafe {
+                    self.serve(request);
+                    Request::release(request);
+                }
+            }
+        }
+    }
+
+    fn protect_request(
+        &self,
+        slot: &AtomicPtr<Request<V>>,
+        hazard: &mut Hazard<'_>,
+    ) -> Option<*mut Request<V>> {
+        hazard
+            .protect_ptr(slot)
+            .map(|(request, _)| request.as_ptr())
+    }
+
+    /// Tries to hand a node to `request` until it has been handed one or found the stack empty.
+    ///
+    /// # Safety: The caller must hold a count on `request`.
+    unsafe fn serve(&self, request: *mut Request<V>) {
+        let mut hazard = Hazard::new(self.domain);
+
+        while (*request).result.load(Ordering::Acquire).is_null() {
+            let head = self.protect_head(&mut hazard);
+
+            if is_marked(head) {
+                self.help_head(&mut hazard);
+                continue;
+            }
+
+            if head.is_null() {
+                // The request was pending when we found the stack empty.
+                let _ = (*request).result.compare_exchange(
+                    null_mut(),
+                    empty(),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+                continue;
+            }
+
+            (*request).refs.fetch_add(1, Ordering::AcqRel);
+            let desc = Box::into_raw(Box::new(Descriptor {
+                node: head,
+                request,
+            }));
+
+            // Once published, a helper may complete and retire the descriptor at any time, so
+            // it is protected before then, and for as long as we complete it ourselves.
+            let mut desc_hazard = Hazard::new(self.domain);
+            desc_hazard.protect_raw(desc);
+
+            if self
+                .head
+                .compare_exchange(head, marked(desc), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.complete(desc);
+            } else {
+                // The descriptor was never published, so dropping it only gives up its count.
+                drop(Box::from_raw(desc));
+            }
+        }
+    }
+
+    /// Protects the node the head points to, or its descriptor if the head is marked, and
+    /// returns the head.
+    fn protect_head(&self, hazard: &mut Hazard<'_>) -> *mut Node<V> {
+        let mut head = self.head.load(Ordering::Acquire);
+
+        loop {
+            hazard.protect_raw(descriptor(head));
+
+            let now = self.head.load(Ordering::Acquire);
+
+            if now == head {
+                return head;
+            }
+
+            head = now;
+        }
+    }
+
+    /// Completes the descriptor installed at the head, if there is one.
+    fn help_head(&self, hazard: &mut Hazard<'_>) {
+        let head = self.protect_head(hazard);
+
+        if is_marked(head) {
+            // # Safety: The descriptor is protected and was installed when we protected it.
+            unsafe { self.complete(descriptor(head)) };
+        }
+    }
+
+    /// Hands the node of `desc` to its request unless the request was already served, and
+    /// swings the head past the node, or back to it.
+    ///
+    /// # Safety: `desc` must be protected and must have been installed at the head after it was
+    /// protected.
+    unsafe fn complete(&self, desc: *mut Descriptor<V>) {
+        let node = (*desc).node;
+        let request = (*desc).request;
+
+        // The node is only retired once it has been swung off the head, so while the head is
+        // still marked with `desc` it can be protected like any linked node.
+        let mut hazard = Hazard::new(self.domain);
+        hazard.protect_raw(node);
+
+        if self.head.load(Ordering::Acquire) != marked(desc) {
+            return;
+        }
+
+        let _ = (*request).result.compare_exchange(
+            null_mut(),
+            node,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+
+        let next = if (*request).result.load(Ordering::Acquire) == node {
+            (*node).next
+        } else {
+            node
+        };
+
+        if self
+            .head
+            .compare_exchange(marked(desc), next, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            domain::retire::<_, Reclaim<_>>(self.domain, desc);
+        }
+    }
+}
+
+impl<V> Drop for Stack<V> {
+    fn drop(&mut self) {
+        self.domain.eager_reclaim();
+
+        let mut curr = *self.head.get_mut();
+
+        // # Safety: We have exclusive ownership of self, and every operation has completed, so
+        // the head is not marked.
+        unsafe {
+            while !curr.is_null() {
+                let node = Box::from_raw(curr);
+                curr = node.next;
+            }
+        }
+    }
+}
+
+/// [Handle](Handle) is one of the slots through which a wait free [Stack](Stack) is popped.
+/// Handles are released when they are dropped.
+pub struct Handle<'a, V> {
+    stack: &'a Stack<V>,
+    index: usize,
+}
+
+impl<'a, V> core::fmt::Debug for Handle<'a, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl<'a, V> Handle<'a, V>
+where
+    V: Send + Sync,
+{
+    pub fn push(&self, val: V) {
+        self.stack.push(val);
+    }
+
+    /// Pops the top value, completing within a number of steps bounded by the number of
+    /// handles of the stack.
+    pub fn pop(&self) -> Option<Entry<'a, V>> {
+        let stack = self.stack;
+
+        stack.help();
+
+        let request = Box::into_raw(Box::new(Request {
+            ticket: stack.tickets.fetch_add(1, Ordering::Relaxed),
+            result: AtomicPtr::new(null_mut()),
+            refs: AtomicUsize::new(1),
+        }));
+
+        let slot = &stack.slots[self.index];
+        slot.store(request, Ordering::Release);
+
+        unsafe {
+            stack.serve(request);
+
+            let node = (*request).result.load(Ordering::Acquire);
+
+            slot.store(null_mut(), Ordering::Release);
+            Request::release(request);
+
+            if node == empty() {
+                return None;
+            }
+
+            // Make sure the node is off the head before retiring it.
+            let mut hazard = Hazard::new(stack.domain);
+
+            loop {
+                let head = stack.protect_head(&mut hazard);
+
+                if !is_marked(head) || (*descriptor(head)).node != node {
+                    break;
+                }
+
+                stack.complete(descriptor(head));
+            }
+
+            // # Safety: We are the only thread that retires `node`, so protecting it before
+            // retiring it is sound.
+            hazard.protect_raw(node);
+            domain::retire::<_, Reclaim<_>>(stack.domain, node);
+
+            Some(Entry {
+                node: NonNull::new_unchecked(node),
+                _hazard: hazard,
+            })
+        }
+    }
+}
+
+impl<'a, V> Drop for Handle<'a, V> {
+    fn drop(&mut self) {
+        self.stack.claimed[self.index].store(false, Ordering::Release);
+    }
+}
+
+/// [Entry](Entry) is a protected reference to a value popped from a wait free [Stack](Stack).
+/// It stays valid until it is dropped.
+pub struct Entry<'a, V> {
+    node: NonNull<Node<V>>,
+    _hazard: Hazard<'a>,
+}
+
+impl<'a, V> core::ops::Deref for Entry<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.node.as_ref().val }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::DropCounter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_push_pop() {
+        let stack = Stack::new(2);
+        let handle = stack.handle().unwrap();
+
+        (0..3).for_each(|i| stack.push(i));
+
+        assert_eq!(handle.pop().map(|e| *e), Some(2));
+        assert_eq!(handle.pop().map(|e| *e), Some(1));
+
+        handle.push(3);
+
+        assert_eq!(handle.pop().map(|e| *e), Some(3));
+        assert_eq!(handle.pop().map(|e| *e), Some(0));
+        assert!(handle.pop().is_none());
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_handles() {
+        let stack = Stack::<i32>::new(2);
+
+        let first = stack.handle().unwrap();
+        let second = stack.handle().unwrap();
+
+        assert!(stack.handle().is_none());
+
+        drop(first);
+
+        assert!(stack.handle().is_some());
+        drop(second);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stack = Stack::new(1);
+        let handle = stack.handle().unwrap();
+
+        (0..5).for_each(|_| stack.push(DropCounter(counter.clone())));
+
+        drop(handle.pop());
+        drop(handle.pop());
+        stack.domain.eager_reclaim();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        drop(handle);
+        drop(stack);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_sync() {
+        let stack = Stack::new(8);
+        let popped = AtomicUsize::new(0);
+
+        std::thread::scope(|s| {
+            for i in 0..8 {
+                let (stack, popped) = (&stack, &popped);
+                s.spawn(move || {
+                    let handle = stack.handle().unwrap();
+
+                    for j in 0..500 {
+                        if (i + j) % 2 == 0 {
+                            handle.push(j);
+                        } else if handle.pop().is_some() {
+                            popped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        let handle = stack.handle().unwrap();
+        let remaining = core::iter::from_fn(|| handle.pop()).count();
+
+        assert_eq!(popped.load(Ordering::Relaxed) + remaining, 8 * 250);
+    }
+
+    #[test]
+    fn test_unique_values() {
+        let stack = Stack::new(4);
+
+        (0..2000).for_each(|i| stack.push(i));
+
+        let mut popped = std::thread::scope(|s| {
+            let handles = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let handle = stack.handle().unwrap();
+                        core::iter::from_fn(|| handle.pop().map(|e| *e)).collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        popped.sort();
+
+        assert_eq!(popped, (0..2000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,122 @@
+//! JavaScript bindings through `wasm-bindgen`, enabled by the `wasm-bindgen` feature.
+//!
+//! A `JsValue` belongs to the JavaScript realm that created it and cannot be sent to a worker,
+//! so the stack exported here holds bytes, which every realm can read. On a module built with
+//! shared memory, the main thread and its workers all see the same stack: one of them creates
+//! it and hands out a [handle](ByteStack::share) through `postMessage`, and the others
+//! [attach](ByteStack::attach) to it. Only the handle is copied, never the values.
+//! ```js
+//! // main.js
+//! const jobs = new ByteStack();
+//! worker.postMessage(jobs.share());
+//! jobs.push(new TextEncoder().encode("resize"));
+//!
+//! // worker.js
+//! onmessage = (e) => {
+//!     const jobs = ByteStack.attach(e.data);
+//!     const job = jobs.pop(); // A Uint8Array, or undefined
+//! };
+//! ```
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+use crate::Stack;
+
+/// [ByteStack](ByteStack) is a [Stack](crate::Stack) of byte arrays that JavaScript can use
+/// from several workers at once. Byte arrays are copied into the stack when pushed and out of it
+/// when popped.
+#[wasm_bindgen]
+pub struct ByteStack {
+    inner: Arc<Stack<Box<[u8]>>>,
+}
+
+impl core::fmt::Debug for ByteStack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ByteStack").finish()
+    }
+}
+
+impl Default for ByteStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl ByteStack {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        ByteStack {
+            inner: Arc::new(Stack::new()),
+        }
+    }
+
+    pub fn push(&self, bytes: &[u8]) {
+        self.inner.push(bytes.into());
+    }
+
+    /// Pops the top byte array, which JavaScript receives as a `Uint8Array`, or `undefined` if
+    /// the stack was empty.
+    pub fn pop(&self) -> Option<Vec<u8>> {
+        self.inner.pop().map(|entry| entry.to_vec())
+    }
+
+    #[wasm_bindgen(js_name = isEmpty)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.peek().is_none()
+    }
+
+    /// Returns a handle to the stack, which a worker sharing this module's memory passes to
+    /// [attach](ByteStack::attach). The stack lives for as long as any thread uses it, so every
+    /// handle must be attached exactly once, or it is leaked.
+    pub fn share(&self) -> usize {
+        Arc::into_raw(Arc::clone(&self.inner)) as usize
+    }
+
+    /// Uses the stack behind a `handle` returned by [share](ByteStack::share).
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been returned by [share](ByteStack::share) in a module sharing this
+    /// one's memory, and must not have been attached before.
+    pub unsafe fn attach(handle: usize) -> ByteStack {
+        ByteStack {
+            inner: Arc::from_raw(handle as *const Stack<Box<[u8]>>),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let stack = ByteStack::new();
+
+        assert!(stack.is_empty());
+
+        stack.push(b"first");
+        stack.push(b"second");
+
+        assert_eq!(stack.pop(), Some(b"second".to_vec()));
+        assert_eq!(stack.pop(), Some(b"first".to_vec()));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn test_share() {
+        let stack = ByteStack::new();
+        let handle = stack.share();
+
+        std::thread::spawn(move || {
+            let attached = unsafe { ByteStack::attach(handle) };
+            attached.push(b"from a worker");
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(stack.pop(), Some(b"from a worker".to_vec()));
+        assert_eq!(Arc::strong_count(&stack.inner), 1);
+    }
+}
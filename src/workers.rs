@@ -0,0 +1,194 @@
+//! Threads that drain a shared [Stack](crate::Stack) until they are closed.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::base::Entry;
+use crate::Stack;
+
+/// The longest a consumer parks while the stack is empty, before it looks again.
+const MAX_PARK: Duration = Duration::from_millis(1);
+
+/// [Consumers](Consumers) are the threads started by
+/// [spawn_consumers](Stack::spawn_consumers). Dropping them closes them as well, waiting for the
+/// threads to finish.
+pub struct Consumers {
+    closed: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl core::fmt::Debug for Consumers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Consumers").finish()
+    }
+}
+
+impl<V> Stack<V>
+where
+    V: Send + Sync + 'static,
+{
+    /// Spawns `n` threads that each pop values and pass them to `f`, until the returned
+    /// [Consumers](Consumers) are closed and the stack is empty. A consumer backs off while the
+    /// stack is empty, parking for up to a millisecond at a time.
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use unlink::Stack;
+    ///
+    /// let stack = Arc::new(Stack::new());
+    /// let sum = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let consumers = {
+    ///     let sum = Arc::clone(&sum);
+    ///     stack.spawn_consumers(2, move |entry| {
+    ///         sum.fetch_add(*entry, Ordering::Relaxed);
+    ///     })
+    /// };
+    ///
+    /// (1..=10).for_each(|i| stack.push(i));
+    ///
+    /// consumers.close().unwrap();
+    ///
+    /// assert_eq!(sum.load(Ordering::Relaxed), 55);
+    /// ```
+    pub fn spawn_consumers<F>(self: &Arc<Self>, n: usize, f: F) -> Consumers
+    where
+        F: Fn(Entry<'_, V>) + Send + Sync + 'static,
+    {
+        let closed = Arc::new(AtomicBool::new(false));
+        let f = Arc::new(f);
+
+        let threads = (0..n)
+            .map(|_| {
+                let stack = Arc::clone(self);
+                let closed = Arc::clone(&closed);
+                let f = Arc::clone(&f);
+
+                thread::spawn(move || consume(&stack, &closed, &*f))
+            })
+            .collect();
+
+        Consumers { closed, threads }
+    }
+}
+
+/// Pops values off `stack` until it is empty after `closed` was set.
+fn consume<V, F>(stack: &Stack<V>, closed: &AtomicBool, f: &F)
+where
+    V: Send + Sync,
+    F: Fn(Entry<'_, V>),
+{
+    let mut park = Duration::from_micros(1);
+
+    loop {
+        // Values pushed before the consumers were closed are visible to the pop below, so none
+        // of them is left behind.
+        let closing = closed.load(Ordering::Acquire);
+
+        match stack.pop() {
+            Some(entry) => {
+                f(entry);
+                park = Duration::from_micros(1);
+            }
+            None if closing => return,
+            None => {
+                thread::park_timeout(park);
+                park = (park * 2).min(MAX_PARK);
+            }
+        }
+    }
+}
+
+impl Consumers {
+    /// Lets the consumers drain the values already pushed, and waits for them to finish.
+    /// Returns the panic of the first consumer that panicked, if any did.
+    pub fn close(mut self) -> thread::Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> thread::Result<()> {
+        self.closed.store(true, Ordering::Release);
+
+        self.threads.iter().for_each(|t| t.thread().unpark());
+
+        let mut result = Ok(());
+
+        // Join every thread, even after one of them panicked.
+        for thread in self.threads.drain(..) {
+            let joined = thread.join();
+
+            if result.is_ok() {
+                result = joined;
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for Consumers {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_consumers() {
+        let stack = Arc::new(Stack::new());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let consumers = {
+            let seen = Arc::clone(&seen);
+            stack.spawn_consumers(4, move |entry| seen.lock().unwrap().push(*entry))
+        };
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || (0..250).for_each(|i| stack.push(t * 250 + i)));
+            }
+        });
+
+        consumers.close().unwrap();
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn test_close_empty() {
+        let stack = Arc::new(Stack::<usize>::new());
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let consumers = {
+            let count = Arc::clone(&count);
+            stack.spawn_consumers(2, move |_| {
+                count.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+
+        drop(consumers);
+
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_panicking_consumer() {
+        let stack = Arc::new(Stack::new());
+        let consumers = stack.spawn_consumers(1, |entry| assert_ne!(*entry, 1));
+
+        stack.push(1);
+
+        assert!(consumers.close().is_err());
+    }
+}